@@ -0,0 +1,305 @@
+use chrono::{DateTime, Local, NaiveDate};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+	fmt,
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use crate::{errors::Error, history::Change, traits::Tabular, workspace::WorkspaceSummary};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntity {
+	Company,
+	Department,
+	Staff,
+	// the whole directory, replaced when a load is undone or redone
+	Directory,
+	// a workspace created, renamed or deleted, logged with its summary
+	Workspace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+	Create,
+	Update,
+	Delete,
+}
+
+/// What caused the change
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOrigin {
+	Edit,
+	Undo,
+	Redo,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+	pub at: DateTime<Local>,
+	pub actor: String,
+	// entries logged before workspaces existed have none
+	#[serde(default)]
+	pub workspace: String,
+	pub origin: AuditOrigin,
+	pub entity: AuditEntity,
+	// `None` for the company, the directory and workspaces
+	pub id: Option<u32>,
+	pub action: AuditAction,
+	pub before: Option<Value>,
+	pub after: Option<Value>,
+}
+
+impl AuditEntry {
+	pub fn new(
+		change: &Change,
+		actor: &str,
+		workspace: &str,
+		origin: AuditOrigin,
+	) -> Result<Self, serde_json::Error> {
+		let (entity, id, before, after) = match change {
+			Change::Company { before, after } => (
+				AuditEntity::Company,
+				None,
+				Some(serde_json::to_value(before)?),
+				Some(serde_json::to_value(after)?),
+			),
+			Change::Department { before, after } => (
+				AuditEntity::Department,
+				before.as_ref().or(after.as_ref()).map(|d| d.id().0),
+				before.as_ref().map(serde_json::to_value).transpose()?,
+				after.as_ref().map(serde_json::to_value).transpose()?,
+			),
+			Change::Staff { before, after } => (
+				AuditEntity::Staff,
+				before.as_ref().or(after.as_ref()).map(|s| s.id().0),
+				before.as_ref().map(serde_json::to_value).transpose()?,
+				after.as_ref().map(serde_json::to_value).transpose()?,
+			),
+			// the records of a whole directory are too large to log
+			Change::Directory { .. } => (AuditEntity::Directory, None, None, None),
+			Change::OtherWorkspace { workspace, change } => {
+				return Self::new(change, actor, workspace, origin);
+			}
+		};
+
+		let action = match (&before, &after) {
+			(None, Some(_)) => AuditAction::Create,
+			(Some(_), None) => AuditAction::Delete,
+			_ => AuditAction::Update,
+		};
+
+		Ok(Self {
+			at: Local::now(),
+			actor: actor.to_string(),
+			workspace: workspace.to_string(),
+			origin,
+			entity,
+			id,
+			action,
+			before,
+			after,
+		})
+	}
+
+	/// An entry for a workspace created, renamed or deleted. Those can't be undone, so they have no
+	/// `Change`.
+	pub fn workspace(
+		actor: &str,
+		before: Option<&WorkspaceSummary>,
+		after: Option<&WorkspaceSummary>,
+	) -> Result<Self, serde_json::Error> {
+		let action = match (before, after) {
+			(None, Some(_)) => AuditAction::Create,
+			(Some(_), None) => AuditAction::Delete,
+			_ => AuditAction::Update,
+		};
+
+		Ok(Self {
+			at: Local::now(),
+			actor: actor.to_string(),
+			workspace: before.or(after).map_or("".to_string(), |summary| summary.name.clone()),
+			origin: AuditOrigin::Edit,
+			entity: AuditEntity::Workspace,
+			id: None,
+			action,
+			before: before.map(serde_json::to_value).transpose()?,
+			after: after.map(serde_json::to_value).transpose()?,
+		})
+	}
+}
+
+impl fmt::Display for AuditEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} {} {:?} {:?}",
+			self.at.format("%Y-%m-%d %H:%M:%S"),
+			self.actor,
+			self.action,
+			self.entity
+		)?;
+		if let Some(id) = self.id {
+			write!(f, " {id}")?;
+		}
+		if !self.workspace.is_empty() {
+			write!(f, " in {}", self.workspace)?;
+		}
+		if self.origin != AuditOrigin::Edit {
+			write!(f, " ({:?})", self.origin)?;
+		}
+		Ok(())
+	}
+}
+
+impl Tabular for AuditEntry {
+	fn headers() -> Vec<&'static str> {
+		vec!["at", "actor", "workspace", "origin", "entity", "id", "action", "before", "after"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		let json = |value: &Option<Value>| value.as_ref().map_or("".to_string(), |v| v.to_string());
+		vec![
+			self.at.to_rfc3339(),
+			self.actor.clone(),
+			self.workspace.clone(),
+			format!("{:?}", self.origin).to_lowercase(),
+			format!("{:?}", self.entity).to_lowercase(),
+			self.id.map_or("".to_string(), |id| id.to_string()),
+			format!("{:?}", self.action).to_lowercase(),
+			json(&self.before),
+			json(&self.after),
+		]
+	}
+}
+
+/**
+ * An append-only log of every change made to a context, one JSON entry per line. Entries that couldn't
+ * be written are kept, and written along with the next ones. Entries can also be held back until their
+ * changes are saved, so that changes which fail to be saved are never logged.
+ **/
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+	path: PathBuf,
+	actor: String,
+	pending: Vec<AuditEntry>,
+	// the pending entries from this one on are held back
+	held_from: Option<usize>,
+}
+
+impl AuditLog {
+	pub fn new(path: &Path, actor: &str) -> Self {
+		Self { path: path.to_path_buf(), actor: actor.to_string(), pending: vec![], held_from: None }
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub(crate) fn log(&mut self, workspace: &str, changes: &[Change], origin: AuditOrigin) {
+		for change in changes {
+			// records always serialize, so an entry can't fail to be created
+			if let Ok(entry) = AuditEntry::new(change, &self.actor, workspace, origin) {
+				self.pending.push(entry);
+			}
+		}
+		// errors surface on the next explicit `flush()`
+		let _ = self.flush();
+	}
+
+	pub(crate) fn log_workspace(
+		&mut self,
+		before: Option<&WorkspaceSummary>,
+		after: Option<&WorkspaceSummary>,
+	) {
+		if let Ok(entry) = AuditEntry::workspace(&self.actor, before, after) {
+			self.pending.push(entry);
+		}
+		let _ = self.flush();
+	}
+
+	/// Hold back the entries logged from now on, until `release()` or `discard_held()`
+	pub(crate) fn hold(&mut self) {
+		self.held_from.get_or_insert(self.pending.len());
+	}
+
+	/// Write the entries held back, e.g. as their changes are saved
+	pub(crate) fn release(&mut self) -> io::Result<()> {
+		self.held_from = None;
+		self.flush()
+	}
+
+	/// Forget the entries held back, e.g. as their changes are rolled back
+	pub(crate) fn discard_held(&mut self) {
+		if let Some(held_from) = self.held_from.take() {
+			self.pending.truncate(held_from);
+		}
+	}
+
+	/// Write the pending entries to the log file, but those held back
+	pub fn flush(&mut self) -> io::Result<()> {
+		let end = self.held_from.unwrap_or(self.pending.len());
+		if end == 0 {
+			return Ok(());
+		}
+
+		let mut lines = String::new();
+		for entry in &self.pending[..end] {
+			lines.push_str(&serde_json::to_string(entry)?);
+			lines.push('\n');
+		}
+
+		let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+		file.write_all(lines.as_bytes())?;
+		file.sync_data()?;
+		self.pending.drain(..end);
+		if let Some(held_from) = self.held_from.as_mut() {
+			*held_from = 0;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AuditQuery {
+	pub workspace: Option<String>,
+	pub entity: Option<AuditEntity>,
+	pub id: Option<u32>,
+	// both dates are inclusive
+	pub from: Option<NaiveDate>,
+	pub to: Option<NaiveDate>,
+}
+
+impl AuditQuery {
+	pub fn matches(&self, entry: &AuditEntry) -> bool {
+		let date = entry.at.date_naive();
+		self.workspace.as_ref().is_none_or(|workspace| *workspace == entry.workspace)
+			&& self.entity.is_none_or(|entity| entity == entry.entity)
+			&& self.id.is_none_or(|id| Some(id) == entry.id)
+			&& self.from.is_none_or(|from| date >= from)
+			&& self.to.is_none_or(|to| date <= to)
+	}
+}
+
+/// Entries of the log file matching the query, oldest first
+pub fn query(path: &Path, query: &AuditQuery) -> Result<Vec<AuditEntry>, Error> {
+	// nothing has been logged yet
+	if !path.exists() {
+		return Ok(vec![]);
+	}
+
+	let mut entries = vec![];
+	for line in fs::read_to_string(path)?.lines().filter(|l| !l.trim().is_empty()) {
+		let entry = serde_json::from_str::<AuditEntry>(line)?;
+		if query.matches(&entry) {
+			entries.push(entry);
+		}
+	}
+	Ok(entries)
+}
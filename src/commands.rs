@@ -0,0 +1,517 @@
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use std::path::{Path, PathBuf};
+
+use crate::{
+	audit::{AuditEntity, AuditLog, AuditQuery},
+	context::Context,
+	department::{DepartmentBuilder, DepartmentChanges, DepartmentId, InfoScope},
+	errors::{Error, PersistenceError},
+	export::{ExportColumn, ExportOptions},
+	import::{ColumnMapping, ImportKind, ImportOptions},
+	menu_items::{
+		BackupRef, CreateDepartment, CreateStaff, CreateWorkspace, DeleteWorkspace, Execute, ExportCsv,
+		ExportCsvInput, ImportCsv, ImportCsvInput, Invocation, ListBackups, ListDepartments,
+		ListReportingLines, ListStaff, ListWorkspaces, MoveStaff, MoveStaffInput, NameCompany,
+		NameCompanyInput, OrgChart, OrgChartInput, QueryAuditLog, RenameWorkspace, RenameWorkspaceInput,
+		RestoreBackup, RestoreBackupInput, ShowDepartment, ShowDepartmentInput, ShowReportingLine,
+		ShowStaffHistory, SwitchWorkspace, UpdateDepartment, UpdateDepartmentInput, UpdateStaff,
+		UpdateStaffInput,
+	},
+	org_chart::{ChartSyntax, OrgChartOptions},
+	output::{self, OutputFormat},
+	staff::{Gender, Paging, StaffBuilder, StaffChanges, StaffFilter, StaffId},
+	storage::{self, StorageKind},
+};
+
+/**
+ * Non-interactive subcommands. Each one loads the data file, runs a single menu item, prints the
+ * result, and writes the data file back if the menu item changed it.
+ **/
+#[derive(Subcommand, Debug)]
+pub enum Command {
+	/// Manage staff
+	#[command(subcommand)]
+	Staff(StaffCommand),
+	/// Manage departments
+	#[command(subcommand)]
+	Dept(DeptCommand),
+	/// Manage the company info
+	#[command(subcommand)]
+	Company(CompanyCommand),
+	/// List and restore backups of the data file
+	#[command(subcommand)]
+	Backup(BackupCommand),
+	/// Manage the workspaces of the data file, one per company. Other commands run in the current one.
+	#[command(subcommand)]
+	Workspace(WorkspaceCommand),
+	/// List the changes in the audit log given with --audit-log, oldest first
+	Audit(AuditArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+	/// Only list changes made in this workspace
+	#[arg(long)]
+	workspace: Option<String>,
+	#[arg(long, value_enum)]
+	entity: Option<AuditEntity>,
+	/// Only list changes of the staff or department with this id
+	#[arg(long, requires = "entity")]
+	id: Option<u32>,
+	/// Only list changes made on or after this date
+	#[arg(long)]
+	from: Option<NaiveDate>,
+	/// Only list changes made on or before this date
+	#[arg(long)]
+	to: Option<NaiveDate>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StaffCommand {
+	/// Create a new staff
+	Add(StaffAddArgs),
+	/// Record a transfer, a salary change or a new title of a staff
+	Change(StaffChangeArgs),
+	/// Show when a staff joined, changed department, salary or title, and left
+	History {
+		/// Id of the staff
+		id: u32,
+	},
+	/// List staff, sorted by last name then first name
+	List(StaffListArgs),
+	/// Create staff from a CSV file
+	Import(ImportArgs),
+	/// Export staff as CSV, sorted by last name then first name
+	Export(StaffExportArgs),
+	/// Move staff to another workspace, where they are given new ids
+	Move {
+		/// Ids of the staff to move
+		#[arg(required = true)]
+		ids: Vec<u32>,
+		/// Workspace to move the staff to
+		#[arg(long)]
+		to: String,
+		/// Department id in that workspace
+		#[arg(long)]
+		department: Option<u32>,
+	},
+	/// Set whom a staff reports to
+	Manager {
+		/// Id of the staff
+		id: u32,
+		/// Staff id of the manager. Leave it out for the staff to report to nobody.
+		manager: Option<u32>,
+	},
+	/// Show the chain of command of a staff and their direct reports
+	Reports {
+		/// Id of the staff
+		id: u32,
+	},
+	/// Print the reporting lines, staff without a manager at the top
+	Tree {
+		/// Only print the reporting lines below this staff id
+		#[arg(long)]
+		root: Option<u32>,
+	},
+}
+
+#[derive(Args, Debug)]
+pub struct StaffAddArgs {
+	#[arg(long)]
+	first_name: String,
+	#[arg(long)]
+	last_name: String,
+	#[arg(long)]
+	email: String,
+	/// Date of birth, in YYYY-MM-DD format
+	#[arg(long)]
+	dob: NaiveDate,
+	/// Date of joining, in YYYY-MM-DD format
+	#[arg(long)]
+	doj: NaiveDate,
+	/// "m" or "f"
+	#[arg(long, value_parser = parse_gender)]
+	gender: Gender,
+	/// Job title
+	#[arg(long)]
+	title: Option<String>,
+	/// Department id
+	#[arg(long)]
+	department: Option<u32>,
+	/// Staff id of the manager
+	#[arg(long)]
+	manager: Option<u32>,
+	#[arg(long)]
+	monthly_salary: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+pub struct StaffChangeArgs {
+	/// Id of the staff
+	id: u32,
+	/// Department id to transfer the staff to
+	#[arg(long)]
+	department: Option<u32>,
+	#[arg(long)]
+	monthly_salary: Option<u32>,
+	/// Job title
+	#[arg(long)]
+	title: Option<String>,
+	/// When the change takes effect, in YYYY-MM-DD format. Defaults to today.
+	#[arg(long)]
+	effective: Option<NaiveDate>,
+}
+
+#[derive(Args, Debug)]
+pub struct StaffListArgs {
+	/// Only list staff of this department id
+	#[arg(long)]
+	department: Option<u32>,
+	/// Only list active staff
+	#[arg(long, conflicts_with = "inactive")]
+	active: bool,
+	/// Only list inactive staff
+	#[arg(long)]
+	inactive: bool,
+	/// Only list staff joining on or after this date
+	#[arg(long)]
+	joined_from: Option<NaiveDate>,
+	/// Only list staff joining on or before this date
+	#[arg(long)]
+	joined_to: Option<NaiveDate>,
+	/// Match --department, --active and --inactive against the employment on this date
+	#[arg(long)]
+	as_of: Option<NaiveDate>,
+	/// Page number, starting from 1. Requires --per-page.
+	#[arg(long, requires = "per_page")]
+	page: Option<usize>,
+	#[arg(long)]
+	per_page: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+pub struct StaffExportArgs {
+	/// Write the CSV to this file instead of the standard output
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+	/// Only export staff of this department id and its sub-departments
+	#[arg(long)]
+	department: Option<u32>,
+	/// Only export active staff
+	#[arg(long)]
+	active: bool,
+	/// Columns to export, in order. Defaults to all columns.
+	#[arg(long, value_enum, value_delimiter = ',')]
+	columns: Vec<ExportColumn>,
+	/// Format of the date columns
+	#[arg(long, default_value = "%Y-%m-%d")]
+	date_format: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeptCommand {
+	/// Create a new department
+	Add {
+		#[arg(long)]
+		name: String,
+		/// Parent department id
+		#[arg(long)]
+		parent: Option<u32>,
+	},
+	/// Set the staff heading a department
+	Head {
+		/// Department id
+		id: u32,
+		/// Staff id of the head. Leave it out for the department to have no head.
+		staff: Option<u32>,
+	},
+	/// Show the headcount and monthly cost of a department
+	Info {
+		/// Department id
+		id: u32,
+		/// Include the sub-departments
+		#[arg(long)]
+		rolled_up: bool,
+		/// The figures on this date, from the employment history of the staff
+		#[arg(long)]
+		as_of: Option<NaiveDate>,
+	},
+	/// Print the department hierarchy
+	Tree,
+	/// Export the department hierarchy as a Graphviz DOT or Mermaid chart
+	Chart(DeptChartArgs),
+	/// Create departments from a CSV file
+	Import(ImportArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DeptChartArgs {
+	#[arg(value_enum, default_value_t = ChartSyntax::Dot)]
+	syntax: ChartSyntax,
+	/// Write the chart to this file instead of the standard output
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+	/// Only chart this department id and its sub-departments
+	#[arg(long)]
+	root: Option<u32>,
+	/// Levels of sub-departments to chart below the top departments
+	#[arg(long)]
+	depth: Option<usize>,
+	/// List the active staff in each department
+	#[arg(long)]
+	staff: bool,
+	/// Name the head of each department
+	#[arg(long)]
+	heads: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+	/// The CSV file, with a header row
+	file: PathBuf,
+	/// Read a field from a differently named column, e.g. --map "first_name=Given Name"
+	#[arg(long = "map", value_name = "FIELD=COLUMN", value_parser = parse_column_mapping)]
+	mappings: Vec<(String, String)>,
+	/// Format of the date columns
+	#[arg(long, default_value = "%Y-%m-%d")]
+	date_format: String,
+	/// Only report what would be created, without changing the data file
+	#[arg(long)]
+	dry_run: bool,
+}
+
+impl ImportArgs {
+	fn into_input(self, kind: ImportKind) -> (ImportCsvInput, bool) {
+		let mapping = self
+			.mappings
+			.iter()
+			.fold(ColumnMapping::new(), |mapping, (field, column)| mapping.map(field, column));
+		let dry_run = self.dry_run;
+		let options = ImportOptions { mapping, dry_run, date_format: self.date_format };
+		(ImportCsvInput { kind, file: self.file, options }, !dry_run)
+	}
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CompanyCommand {
+	/// Set the name and domain of the company
+	Set {
+		#[arg(long)]
+		name: String,
+		#[arg(long)]
+		domain: String,
+		/// Whether staff emails have to be under the domain
+		#[arg(long)]
+		enforce_email_domain: Option<bool>,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WorkspaceCommand {
+	/// List the workspaces, marking the current one
+	List,
+	/// Create a workspace with an empty directory
+	Create { name: String },
+	/// Make another workspace the current one
+	Switch { name: String },
+	/// Rename a workspace
+	Rename { name: String, new_name: String },
+	/// Delete a workspace other than the current one, along with its directory
+	Delete { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BackupCommand {
+	/// List the backups, the latest first
+	List,
+	/// Replace the data file with a backup. The replaced data file is backed up as well.
+	Restore {
+		/// Number of the backup in `backup list`, or its path
+		backup: String,
+	},
+}
+
+impl Command {
+	/// The menu item carrying out the command, bound to its input, and whether the context has to be
+	/// saved afterwards
+	fn into_invocation(self, data_file: &Path) -> (Box<dyn Invocation>, bool) {
+		let data_file = data_file.to_path_buf();
+		match self {
+			Command::Staff(StaffCommand::Add(args)) => {
+				let builder = StaffBuilder {
+					first_name: args.first_name,
+					last_name: args.last_name,
+					email: args.email,
+					dob: args.dob,
+					doj: args.doj,
+					gender: args.gender,
+					title: args.title,
+					department: args.department.map(DepartmentId),
+					manager: args.manager.map(StaffId),
+					monthly_salary: args.monthly_salary,
+				};
+				(CreateStaff().bind(builder), true)
+			}
+			Command::Staff(StaffCommand::Change(args)) => {
+				let changes = StaffChanges {
+					department: args.department.map(|d| Some(DepartmentId(d))),
+					monthly_salary: args.monthly_salary.map(Some),
+					title: args.title.map(Some),
+					effective: args.effective,
+					..Default::default()
+				};
+				(UpdateStaff().bind(UpdateStaffInput { id: StaffId(args.id), changes }), true)
+			}
+			Command::Staff(StaffCommand::History { id }) => (ShowStaffHistory().bind(StaffId(id)), false),
+			Command::Staff(StaffCommand::List(args)) => {
+				let filter = StaffFilter {
+					department: args.department.map(DepartmentId),
+					active: if args.active {
+						Some(true)
+					} else if args.inactive {
+						Some(false)
+					} else {
+						None
+					},
+					joined_from: args.joined_from,
+					joined_to: args.joined_to,
+					as_of: args.as_of,
+					paging: args
+						.per_page
+						.map(|per_page| Paging { page: args.page.unwrap_or(1).saturating_sub(1), per_page }),
+				};
+				(ListStaff().bind(filter), false)
+			}
+			Command::Staff(StaffCommand::Export(args)) => {
+				let options = ExportOptions {
+					department: args.department.map(DepartmentId),
+					active_only: args.active,
+					columns: if args.columns.is_empty() { ExportColumn::all() } else { args.columns },
+					date_format: args.date_format,
+				};
+				(ExportCsv().bind(ExportCsvInput { options, output: args.output }), false)
+			}
+			Command::Staff(StaffCommand::Move { ids, to, department }) => {
+				let ids = ids.into_iter().map(StaffId).collect();
+				let input = MoveStaffInput { ids, workspace: to, department: department.map(DepartmentId) };
+				(MoveStaff().bind(input), true)
+			}
+			Command::Staff(StaffCommand::Manager { id, manager }) => {
+				let changes = StaffChanges { manager: Some(manager.map(StaffId)), ..Default::default() };
+				(UpdateStaff().bind(UpdateStaffInput { id: StaffId(id), changes }), true)
+			}
+			Command::Staff(StaffCommand::Reports { id }) => (ShowReportingLine().bind(StaffId(id)), false),
+			Command::Staff(StaffCommand::Tree { root }) => {
+				(ListReportingLines().bind(root.map(StaffId)), false)
+			}
+			Command::Dept(DeptCommand::Add { name, parent }) => {
+				let builder = DepartmentBuilder::new(name, parent.map(DepartmentId));
+				(CreateDepartment::new().bind(builder), true)
+			}
+			Command::Staff(StaffCommand::Import(args)) => {
+				let (input, mutates) = args.into_input(ImportKind::Staff);
+				(ImportCsv().bind(input), mutates)
+			}
+			Command::Dept(DeptCommand::Import(args)) => {
+				let (input, mutates) = args.into_input(ImportKind::Departments);
+				(ImportCsv().bind(input), mutates)
+			}
+			Command::Dept(DeptCommand::Head { id, staff }) => {
+				let changes = DepartmentChanges { head: Some(staff.map(StaffId)), ..Default::default() };
+				(UpdateDepartment().bind(UpdateDepartmentInput { id: DepartmentId(id), changes }), true)
+			}
+			Command::Dept(DeptCommand::Info { id, rolled_up, as_of }) => {
+				let scope = if rolled_up { InfoScope::RolledUp } else { InfoScope::Direct };
+				(ShowDepartment().bind(ShowDepartmentInput { id: DepartmentId(id), scope, as_of }), false)
+			}
+			Command::Dept(DeptCommand::Tree) => (ListDepartments::new().bind(()), false),
+			Command::Dept(DeptCommand::Chart(args)) => {
+				let options = OrgChartOptions {
+					syntax: args.syntax,
+					root: args.root.map(DepartmentId),
+					depth: args.depth,
+					staff: args.staff,
+					heads: args.heads,
+				};
+				(OrgChart().bind(OrgChartInput { options, output: args.output }), false)
+			}
+			Command::Company(CompanyCommand::Set { name, domain, enforce_email_domain }) => {
+				let input = NameCompanyInput { name, domain: Some(domain), enforce_email_domain };
+				(NameCompany::new().bind(input), true)
+			}
+			Command::Workspace(WorkspaceCommand::List) => (ListWorkspaces().bind(()), false),
+			Command::Workspace(WorkspaceCommand::Create { name }) => (CreateWorkspace().bind(name), true),
+			Command::Workspace(WorkspaceCommand::Switch { name }) => (SwitchWorkspace().bind(name), true),
+			Command::Workspace(WorkspaceCommand::Rename { name, new_name }) => {
+				(RenameWorkspace().bind(RenameWorkspaceInput { name, new_name }), true)
+			}
+			Command::Workspace(WorkspaceCommand::Delete { name }) => (DeleteWorkspace().bind(name), true),
+			Command::Audit(AuditArgs { workspace, entity, id, from, to }) => {
+				(QueryAuditLog().bind(AuditQuery { workspace, entity, id, from, to }), false)
+			}
+			Command::Backup(BackupCommand::List) => (ListBackups().bind(data_file), false),
+			// restoring writes the data file itself
+			Command::Backup(BackupCommand::Restore { backup }) => (
+				RestoreBackup()
+					.bind(RestoreBackupInput { data_file, backup: BackupRef::from(backup.as_str()) }),
+				false,
+			),
+		}
+	}
+}
+
+pub fn run_command(
+	command: Command,
+	data_file: &Path,
+	format: OutputFormat,
+	storage: StorageKind,
+	audit_log: Option<AuditLog>,
+) -> Result<(), Error> {
+	// a missing data file is a new, empty directory. Backups are managed without loading the data file,
+	// so that a corrupted one can still be restored.
+	let mut ctx = if data_file.exists() && !matches!(command, Command::Backup(_)) {
+		storage::open(storage, data_file)?.load()?
+	} else {
+		Context::new()
+	};
+	ctx.set_storage(storage);
+	ctx.set_audit_log(audit_log);
+	// the changes are logged once they are saved
+	ctx.hold_audit_log();
+
+	let (invocation, mutates) = command.into_invocation(data_file);
+	let output = invocation.invoke(&mut ctx)?;
+	let rendered = output::render(&output, format)?;
+	if !rendered.is_empty() {
+		println!("{rendered}");
+	}
+
+	if mutates {
+		storage::open(storage, data_file)?.save_changes(&ctx)?;
+	}
+	ctx.release_audit_log()?;
+	Ok(())
+}
+
+/// Process exit status for a failed command, following the BSD `sysexits.h` convention
+pub fn exit_code(err: &Error) -> u8 {
+	match err {
+		// EX_DATAERR
+		Error::Validation(_) | Error::NotFound { .. } | Error::Conflict(_) | Error::Parse { .. } => 65,
+		// EX_NOINPUT: the data file can't be understood
+		Error::Persistence(PersistenceError::DataFile(_)) => 66,
+		// EX_IOERR
+		Error::Io { .. } => 74,
+		Error::Persistence(_) => 1,
+	}
+}
+
+fn parse_column_mapping(value: &str) -> Result<(String, String), String> {
+	let (field, column) = value.split_once('=').ok_or("Expected FIELD=COLUMN")?;
+	Ok((field.trim().to_string(), column.trim().to_string()))
+}
+
+fn parse_gender(value: &str) -> Result<Gender, String> {
+	Gender::try_from(value).map_err(|e| e.to_string())
+}
@@ -1,23 +1,73 @@
+use chrono::{Local, NaiveDate};
 use getset::{Getters, Setters};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
 
 use crate::{
-	department::{Department, DepartmentBuilder, DepartmentId, DepartmentInfo},
-	errors::ApplicationError,
-	staff::{Staff, StaffBuilder, StaffId},
+	audit::{AuditLog, AuditOrigin},
+	department::{
+		Department, DepartmentBuilder, DepartmentChanges, DepartmentId, DepartmentInfo, DepartmentNode,
+		DepartmentTree, InfoScope, RemovalPolicy,
+	},
+	errors::{ConflictError, Entity, Error, ValidationError},
+	history::{self, Change, CompanyInfo, History},
+	output::OutputFormat,
+	search::{SearchEntity, SearchHit, SearchIndex, SearchKey, SearchResult},
+	staff::{
+		ReportingLine, ReportingNode, ReportingTree, Staff, StaffBuilder, StaffChanges, StaffFilter, StaffId,
+		StaffMatch, StaffPage, StaffTimeline,
+	},
+	storage::{ChangeSet, StorageKind},
+	workspace::{self, DEFAULT_WORKSPACE, MovedStaff, WorkspaceSummary},
 };
 
 #[derive(Clone, Debug, Getters, Setters, Serialize, Deserialize)]
 #[getset(get = "pub")]
 pub struct Context {
-	#[getset(set = "pub")]
 	company_name: String,
-	#[getset(set = "pub")]
 	domain: String,
+	// whether staff emails have to be under `domain`
+	enforce_email_domain: bool,
 	next_department_id: DepartmentId,
 	next_staff_id: StaffId,
 	departments: Vec<Department>,
 	staff: Vec<Staff>,
+	// kept up to date by every mutation, but not persisted. Call `rebuild_search_index()` after
+	// deserializing a context.
+	#[serde(skip)]
+	#[getset(skip)]
+	search_index: SearchIndex,
+	// how menu items print their results. Not persisted.
+	#[serde(skip)]
+	#[getset(set = "pub")]
+	output_format: OutputFormat,
+	// how the context is loaded and saved. Not persisted.
+	#[serde(skip)]
+	#[getset(set = "pub")]
+	storage: StorageKind,
+	// records changed since the context was loaded or last saved, for storages saving incrementally
+	#[serde(skip)]
+	unsaved_changes: ChangeSet,
+	// the file the context was loaded from or last saved to, which "Save" writes to. Not persisted.
+	#[serde(skip)]
+	#[getset(set = "pub")]
+	data_file: Option<PathBuf>,
+	// undo and redo of the mutations. Not persisted.
+	#[serde(skip)]
+	#[getset(set = "pub")]
+	history: History,
+	// where every change is logged, if anywhere. Not persisted.
+	#[serde(skip)]
+	#[getset(set = "pub")]
+	audit_log: Option<AuditLog>,
+	// name of the workspace holding this directory. Persisted by `persistence` as the key of the directory.
+	#[serde(skip)]
+	workspace: String,
+	// the other workspaces of the data file, keyed by name. Persisted by `persistence` along with this one.
+	#[serde(skip)]
+	#[getset(skip)]
+	other_workspaces: BTreeMap<String, Context>,
 }
 
 impl Context {
@@ -25,11 +75,346 @@ impl Context {
 		Self {
 			company_name: "".to_string(),
 			domain: "".to_string(),
+			enforce_email_domain: true,
 			next_department_id: DepartmentId(0),
 			next_staff_id: StaffId(0),
 			departments: vec![],
 			staff: vec![],
+			search_index: SearchIndex::new(),
+			output_format: OutputFormat::default(),
+			storage: StorageKind::default(),
+			unsaved_changes: ChangeSet::default(),
+			data_file: None,
+			history: History::default(),
+			audit_log: None,
+			workspace: DEFAULT_WORKSPACE.to_string(),
+			other_workspaces: BTreeMap::new(),
+		}
+	}
+
+	/// Assemble the workspaces read from a data file, `current` being the one to work on. Employment changes
+	/// that took effect since the file was saved are brought in.
+	pub(crate) fn from_workspaces(current: &str, mut workspaces: BTreeMap<String, Context>) -> Option<Self> {
+		let today = Local::now().date_naive();
+		let mut ctx = workspaces.remove(current)?;
+		for (name, other) in workspaces.iter_mut() {
+			other.workspace = name.clone();
+			other.staff.iter_mut().for_each(|staff| staff.set_current(today));
+			other.rebuild_search_index();
+		}
+		ctx.workspace = current.to_string();
+		ctx.other_workspaces = workspaces;
+		ctx.staff.iter_mut().for_each(|staff| staff.set_current(today));
+		ctx.rebuild_search_index();
+		Some(ctx)
+	}
+
+	/// Replace the directory with one freshly loaded from `data_file`, keeping the settings of the session.
+	/// Unless the history is cleared on load, the load itself can be undone, going back to the previous data
+	/// file as well.
+	pub fn replace_directory(&mut self, loaded: Context, data_file: PathBuf) {
+		let history = std::mem::take(&mut self.history);
+		let audit_log = self.audit_log.take();
+		let previous = std::mem::replace(
+			self,
+			Context {
+				output_format: self.output_format,
+				storage: self.storage,
+				data_file: Some(data_file),
+				..loaded
+			},
+		);
+
+		if history.clear_on_load() {
+			self.history = history.cleared();
+		} else {
+			// neither snapshot carries a history or an audit log of its own
+			let change = Change::Directory { before: Box::new(previous), after: Box::new(self.clone()) };
+			self.history = history;
+			self.history.record(vec![change]);
+		}
+		self.audit_log = audit_log;
+	}
+
+	/// Go back to a clone of the context, e.g. when the changes made since couldn't be saved. The audit log
+	/// entries held back since are forgotten along with the changes.
+	pub fn roll_back(&mut self, snapshot: Context) {
+		let mut audit_log = self.audit_log.take();
+		if let Some(log) = audit_log.as_mut() {
+			log.discard_held();
+		}
+		*self = Context { audit_log, ..snapshot };
+	}
+
+	/// Write the audit log entries not written yet, e.g. because the disk was full
+	pub fn flush_audit_log(&mut self) -> std::io::Result<()> {
+		self.audit_log.as_mut().map_or(Ok(()), |log| log.flush())
+	}
+
+	/// Hold back the audit log entries of the changes made from now on, until they are saved and
+	/// `release_audit_log()` writes them
+	pub fn hold_audit_log(&mut self) {
+		if let Some(log) = self.audit_log.as_mut() {
+			log.hold();
+		}
+	}
+
+	pub fn release_audit_log(&mut self) -> std::io::Result<()> {
+		self.audit_log.as_mut().map_or(Ok(()), |log| log.release())
+	}
+
+	/// Make the mutations done by `f` a single step to undo, e.g. all the rows of a CSV import
+	pub fn group<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+		let outermost = self.history.begin_group();
+		let result = f(self);
+		if outermost {
+			self.history.end_group();
+		}
+		result
+	}
+
+	/// Revert the latest step in the history, returning its summary
+	pub fn undo(&mut self) -> Option<String> {
+		let changes = self.history.pop_undo()?;
+		let inverses = changes.iter().rev().map(|change| change.inverse()).collect::<Vec<_>>();
+		inverses.iter().for_each(|change| self.apply(change));
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log(&self.workspace, &inverses, AuditOrigin::Undo);
+		}
+
+		let summary = history::summary(&changes);
+		self.history.push_redo(changes);
+		Some(summary)
+	}
+
+	/// Make the latest undone step again, returning its summary
+	pub fn redo(&mut self) -> Option<String> {
+		let changes = self.history.pop_redo()?;
+		changes.iter().for_each(|change| self.apply(change));
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log(&self.workspace, &changes, AuditOrigin::Redo);
+		}
+
+		let summary = history::summary(&changes);
+		self.history.push_undo(changes);
+		Some(summary)
+	}
+
+	/// Have the next save write every record, as they were read in an older format
+	pub(crate) fn mark_migrated(&mut self) {
+		self.unsaved_changes.migrated = true;
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.unsaved_changes = ChangeSet::default();
+		self.other_workspaces.values_mut().for_each(|other| other.mark_saved());
+	}
+
+	/// Whether anything changed, in any workspace, since the context was loaded or last saved
+	pub fn is_dirty(&self) -> bool {
+		!self.unsaved_changes.is_empty() || self.other_workspaces.values().any(|other| other.is_dirty())
+	}
+
+	// A data file holds the directories of several companies, each in a workspace of its own. The context
+	// is the directory of the current workspace, and keeps the others aside until switched to.
+
+	/// Every workspace, the current one included, by name
+	pub fn workspaces(&self) -> BTreeMap<&str, &Context> {
+		let mut workspaces =
+			self.other_workspaces.iter().map(|(name, ctx)| (name.as_str(), ctx)).collect::<BTreeMap<_, _>>();
+		workspaces.insert(&self.workspace, self);
+		workspaces
+	}
+
+	pub fn workspace_summaries(&self) -> Vec<WorkspaceSummary> {
+		self.workspaces()
+			.into_iter()
+			.map(|(name, ctx)| WorkspaceSummary::new(name, ctx, name == self.workspace))
+			.collect()
+	}
+
+	/// Add a workspace with an empty directory
+	pub fn create_workspace(&mut self, name: &str) -> Result<(), Error> {
+		let name = workspace::validate_name(name)?;
+		if self.workspaces().contains_key(name.as_str()) {
+			Err(ConflictError::DuplicateWorkspace(name.clone()))?
+		}
+
+		let ctx = Context { workspace: name.clone(), ..Context::new() };
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log_workspace(None, Some(&WorkspaceSummary::new(&name, &ctx, false)));
+		}
+		self.other_workspaces.insert(name, ctx);
+		self.unsaved_changes.workspaces = true;
+		Ok(())
+	}
+
+	/// Make another workspace the current one. The undo history is cleared, as it only covers the
+	/// directory of the current workspace.
+	pub fn switch_workspace(&mut self, name: &str) -> Result<(), Error> {
+		if name == self.workspace {
+			return Ok(());
 		}
+
+		let next = self.other_workspaces.remove(name).ok_or_else(|| Error::unknown_workspace(name))?;
+		let next = Context {
+			output_format: self.output_format,
+			storage: self.storage,
+			data_file: self.data_file.take(),
+			history: self.history.cleared(),
+			audit_log: self.audit_log.take(),
+			other_workspaces: std::mem::take(&mut self.other_workspaces),
+			..next
+		};
+		let mut previous = std::mem::replace(self, next);
+		previous.history = previous.history.cleared();
+		self.other_workspaces.insert(previous.workspace.clone(), previous);
+		self.unsaved_changes.workspaces = true;
+		Ok(())
+	}
+
+	pub fn rename_workspace(&mut self, name: &str, new_name: &str) -> Result<(), Error> {
+		let new_name = workspace::validate_name(new_name)?;
+		if !self.workspaces().contains_key(name) {
+			Err(Error::unknown_workspace(name))?
+		}
+		if name == new_name {
+			return Ok(());
+		}
+		if self.workspaces().contains_key(new_name.as_str()) {
+			Err(ConflictError::DuplicateWorkspace(new_name.clone()))?
+		}
+
+		let before = WorkspaceSummary::new(name, self.workspaces()[name], name == self.workspace);
+		match self.other_workspaces.remove(name) {
+			Some(mut other) => {
+				other.workspace = new_name.clone();
+				self.other_workspaces.insert(new_name.clone(), other);
+			}
+			None => self.workspace = new_name.clone(),
+		}
+		self.history.rename_workspace(name, Some(&new_name));
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log_workspace(Some(&before), Some(&WorkspaceSummary { name: new_name, ..before.clone() }));
+		}
+		self.unsaved_changes.workspaces = true;
+		Ok(())
+	}
+
+	/// Remove a workspace along with its directory. The current workspace can't be removed.
+	pub fn delete_workspace(&mut self, name: &str) -> Result<(), Error> {
+		if name == self.workspace {
+			Err(ConflictError::CurrentWorkspace(name.to_string()))?
+		}
+		let removed = self.other_workspaces.remove(name).ok_or_else(|| Error::unknown_workspace(name))?;
+		self.history.rename_workspace(name, None);
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log_workspace(Some(&WorkspaceSummary::new(name, &removed, false)), None);
+		}
+		self.unsaved_changes.workspaces = true;
+		Ok(())
+	}
+
+	/// Move staff of the current workspace to another one, under `department` of that workspace. They are
+	/// given new ids there, and if that company enforces its email domain, their emails are moved under
+	/// it. Either all the staff are moved, or none is.
+	///
+	/// The move is undone as a whole, bringing the staff back in the current workspace and removing them
+	/// from the other one.
+	pub fn move_staff(
+		&mut self,
+		staff_ids: &[StaffId],
+		workspace: &str,
+		department: Option<DepartmentId>,
+	) -> Result<Vec<MovedStaff>, Error> {
+		if workspace == self.workspace {
+			Err(ConflictError::CurrentWorkspace(workspace.to_string()))?
+		}
+		let Some(target) = self.other_workspaces.get(workspace) else {
+			Err(Error::unknown_workspace(workspace))?
+		};
+		let staff = staff_ids
+			.iter()
+			.map(|staff_id| self.staff_member(staff_id).cloned().ok_or(Error::unknown_staff(*staff_id)))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		// insert into a copy, so that the other workspace is changed only if every staff fits in. The
+		// insertions are logged and undone along with the deletions below.
+		let mut moved_to = target.clone();
+		moved_to.audit_log = None;
+		let new_ids = moved_to.insert_moved_staff(&staff, department)?;
+		let insertions = new_ids
+			.iter()
+			.filter_map(|staff_id| moved_to.staff_member(staff_id))
+			.map(|inserted| Change::OtherWorkspace {
+				workspace: workspace.to_string(),
+				change: Box::new(Change::Staff { before: None, after: Some(inserted.clone()) }),
+			})
+			.collect::<Vec<_>>();
+		moved_to.history = moved_to.history.cleared();
+
+		self.group(|ctx| {
+			staff_ids.iter().try_for_each(|staff_id| ctx.delete_staff(staff_id).map(|_| ()))?;
+			ctx.other_workspaces.insert(workspace.to_string(), moved_to);
+			ctx.record(insertions);
+			Ok::<_, Error>(())
+		})?;
+
+		Ok(staff_ids.iter().zip(new_ids).map(|(from, to)| MovedStaff { from: *from, to }).collect())
+	}
+
+	pub fn company_info(&self) -> CompanyInfo {
+		CompanyInfo {
+			name: self.company_name.clone(),
+			domain: self.domain.clone(),
+			enforce_email_domain: self.enforce_email_domain,
+		}
+	}
+
+	pub fn set_company_info(&mut self, info: CompanyInfo) {
+		let before = self.company_info();
+		self.apply(&Change::Company { before: before.clone(), after: info.clone() });
+		self.record(vec![Change::Company { before, after: info }]);
+	}
+
+	pub fn set_company_name(&mut self, company_name: String) {
+		self.set_company_info(CompanyInfo { name: company_name, ..self.company_info() });
+	}
+
+	pub fn set_domain(&mut self, domain: String) {
+		self.set_company_info(CompanyInfo { domain, ..self.company_info() });
+	}
+
+	pub fn set_enforce_email_domain(&mut self, enforce_email_domain: bool) {
+		self.set_company_info(CompanyInfo { enforce_email_domain, ..self.company_info() });
+	}
+
+	pub fn rebuild_search_index(&mut self) {
+		self.search_index = SearchIndex::build(&self.departments, &self.staff);
+	}
+
+	/// Typo-tolerant search over staff and department names, best matches first
+	pub fn fuzzy_search(&self, query: &str) -> Vec<SearchResult<'_>> {
+		// a context fresh from deserialization may not have its index built yet
+		let rebuilt;
+		let index = if self.search_index.len() == self.departments.len() + self.staff.len() {
+			&self.search_index
+		} else {
+			rebuilt = SearchIndex::build(&self.departments, &self.staff);
+			&rebuilt
+		};
+
+		index
+			.search(query)
+			.into_iter()
+			.filter_map(|SearchHit { key, score }| {
+				let entity = match key {
+					SearchKey::Staff(staff_id) => SearchEntity::Staff(self.staff_member(&staff_id)?),
+					SearchKey::Department(dep_id) => SearchEntity::Department(self.department(&dep_id)?),
+				};
+				Some(SearchResult { entity, score })
+			})
+			.collect()
 	}
 
 	fn get_next_department_id(&mut self) -> DepartmentId {
@@ -43,15 +428,20 @@ impl Context {
 		self.departments.iter().any(|dep| dep.id() == dep_id)
 	}
 
-	pub fn insert_department(&mut self, builder: DepartmentBuilder) -> Result<&Department, ApplicationError> {
+	pub fn insert_department(&mut self, builder: DepartmentBuilder) -> Result<&Department, Error> {
 		// builder parameter validation
+		if builder.name.trim().is_empty() {
+			Err(ValidationError::EmptyField("name"))?
+		}
 		if let Some(dep_id) = builder.parent {
 			if !self.validate_department_id(&dep_id) {
-				Err(ApplicationError("Unknown department".to_string()))?
+				Err(Error::unknown_department(dep_id))?
 			}
 		};
 
 		let new_department = Department::new(self.get_next_department_id(), builder);
+		self.search_index.upsert_department(&new_department);
+		self.record(vec![Change::Department { before: None, after: Some(new_department.clone()) }]);
 		self.departments.push(new_department);
 
 		Ok(self.departments.last().unwrap())
@@ -61,6 +451,128 @@ impl Context {
 		self.departments().iter().filter(|dep| *dep.id() == *dep_id).collect::<Vec<_>>().first().copied()
 	}
 
+	/// Look a department up by id, or else by its name, ignoring case
+	pub fn resolve_department(&self, reference: &str) -> Result<DepartmentId, Error> {
+		let reference = reference.trim();
+		if let Ok(dep_id) = DepartmentId::try_from(reference) {
+			if self.validate_department_id(&dep_id) {
+				return Ok(dep_id);
+			}
+		}
+
+		match self.departments.iter().filter(|d| d.name().eq_ignore_ascii_case(reference)).collect::<Vec<_>>()
+			[..]
+		{
+			[dep] => Ok(*dep.id()),
+			[] => Err(Error::NotFound { entity: Entity::Department, id: reference.to_string() }),
+			_ => Err(ConflictError::AmbiguousDepartmentName(reference.to_string()).into()),
+		}
+	}
+
+	fn department_index(&self, dep_id: &DepartmentId) -> Result<usize, Error> {
+		self.departments.iter().position(|d| *d.id() == *dep_id).ok_or(Error::unknown_department(*dep_id))
+	}
+
+	/// Rename, re-parent, (de)activate a department or change its head
+	pub fn update_department(
+		&mut self,
+		dep_id: &DepartmentId,
+		changes: DepartmentChanges,
+	) -> Result<&Department, Error> {
+		let idx = self.department_index(dep_id)?;
+
+		if changes.name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+			Err(ValidationError::EmptyField("name"))?
+		}
+
+		if let Some(Some(parent)) = changes.parent {
+			if !self.validate_department_id(&parent) {
+				Err(Error::unknown_department(parent))?
+			}
+			if parent == *dep_id || self.descendant_department_ids(dep_id).contains(&parent) {
+				Err(ConflictError::DepartmentCycle { department: *dep_id, parent })?
+			}
+		}
+
+		if let Some(Some(head)) = changes.head {
+			if self.staff_member(&head).is_none() {
+				Err(Error::unknown_staff(head))?
+			}
+		}
+
+		let before = self.departments[idx].clone();
+		self.departments[idx].update(changes);
+		self.search_index.upsert_department(&self.departments[idx]);
+		self.record(vec![Change::Department {
+			before: Some(before),
+			after: Some(self.departments[idx].clone()),
+		}]);
+		Ok(&self.departments[idx])
+	}
+
+	/// Remove a department, handling its sub-departments and staff according to `policy`.
+	/// Returns the removed departments.
+	pub fn remove_department(
+		&mut self,
+		dep_id: &DepartmentId,
+		policy: RemovalPolicy,
+	) -> Result<Vec<Department>, Error> {
+		let idx = self.department_index(dep_id)?;
+		let parent = *self.departments[idx].parent();
+		let has_children = self.departments.iter().any(|d| *d.parent() == Some(*dep_id));
+		let has_staff = self.staff.iter().any(|s| *s.department() == Some(*dep_id));
+
+		let mut changes = vec![];
+		let removed_ids = match policy {
+			RemovalPolicy::Refuse if has_children => Err(ConflictError::DepartmentHasChildren(*dep_id))?,
+			RemovalPolicy::Refuse if has_staff => Err(ConflictError::DepartmentHasStaff(*dep_id))?,
+			RemovalPolicy::Refuse => vec![*dep_id],
+			RemovalPolicy::Cascade => {
+				let mut ids = vec![*dep_id];
+				ids.extend(self.descendant_department_ids(dep_id));
+				ids
+			}
+			RemovalPolicy::ReassignToParent => {
+				for dep in self.departments.iter_mut().filter(|d| *d.parent() == Some(*dep_id)) {
+					let before = dep.clone();
+					dep.set_parent(parent);
+					changes.push(Change::Department { before: Some(before), after: Some(dep.clone()) });
+				}
+				vec![*dep_id]
+			}
+		};
+
+		// staff of removed departments move to the parent when reassigning, and are left without a
+		// department otherwise. So do the transfers to them yet to take effect.
+		let new_department = if policy == RemovalPolicy::ReassignToParent { parent } else { None };
+		let today = Local::now().date_naive();
+		for staff in self.staff.iter_mut().filter(|s| {
+			s.department().is_some_and(|d| removed_ids.contains(&d))
+				|| s.has_pending_transfer(&removed_ids, today)
+		}) {
+			let before = staff.clone();
+			if staff.department().is_some_and(|d| removed_ids.contains(&d)) {
+				let staff_changes = StaffChanges { department: Some(new_department), ..Default::default() };
+				staff.update(staff_changes.apply_to(staff.to_builder()), today);
+			}
+			staff.redirect_pending_transfers(&removed_ids, new_department, today);
+			changes.push(Change::Staff { before: Some(before), after: Some(staff.clone()) });
+		}
+
+		let (removed, kept): (Vec<_>, Vec<_>) =
+			self.departments.drain(..).partition(|d| removed_ids.contains(d.id()));
+		self.departments = kept;
+		// the removals come first, to be summarized first
+		let mut removals = removed
+			.iter()
+			.map(|dep| Change::Department { before: Some(dep.clone()), after: None })
+			.collect::<Vec<_>>();
+		removals.extend(changes);
+		removed.iter().for_each(|dep| self.search_index.remove(&SearchKey::Department(*dep.id())));
+		self.record(removals);
+		Ok(removed)
+	}
+
 	fn get_next_staff_id(&mut self) -> StaffId {
 		let ret = self.next_staff_id;
 		self.next_staff_id = StaffId(self.next_staff_id.0 + 1);
@@ -68,20 +580,540 @@ impl Context {
 		ret
 	}
 
-	pub fn department_info(&self, dep_id: &DepartmentId) -> Option<DepartmentInfo> {
+	/// Ids of all departments below `dep_id` in the `parent` tree, not including `dep_id` itself.
+	pub fn descendant_department_ids(&self, dep_id: &DepartmentId) -> Vec<DepartmentId> {
+		let mut result: Vec<DepartmentId> = vec![];
+		let mut pending = vec![*dep_id];
+
+		while let Some(current) = pending.pop() {
+			for dep in self.departments.iter().filter(|d| *d.parent() == Some(current)) {
+				// guard against a malformed tree, so we never loop forever
+				if *dep.id() != *dep_id && !result.contains(dep.id()) {
+					result.push(*dep.id());
+					pending.push(*dep.id());
+				}
+			}
+		}
+
+		result
+	}
+
+	pub fn department_tree(&self) -> DepartmentTree<'_> {
+		DepartmentTree {
+			company_name: &self.company_name,
+			domain: &self.domain,
+			roots: self
+				.departments
+				.iter()
+				.filter(|dep| dep.parent().is_none())
+				.map(|dep| self.department_node(dep, &mut vec![]))
+				.collect(),
+		}
+	}
+
+	/// `visited` guards against a malformed tree, e.g. a hand-edited data file, so we never recurse forever
+	fn department_node<'a>(
+		&'a self,
+		dep: &'a Department,
+		visited: &mut Vec<DepartmentId>,
+	) -> DepartmentNode<'a> {
+		visited.push(*dep.id());
+		DepartmentNode {
+			department: dep,
+			children: self
+				.departments
+				.iter()
+				.filter(|d| *d.parent() == Some(*dep.id()))
+				.filter(|d| !visited.contains(d.id()))
+				.collect::<Vec<_>>()
+				.into_iter()
+				.map(|d| self.department_node(d, visited))
+				.collect(),
+		}
+	}
+
+	pub fn department_info(&self, dep_id: &DepartmentId, scope: InfoScope) -> Option<DepartmentInfo> {
+		self.department_info_as_of(dep_id, scope, None)
+	}
+
+	/// The department figures on `as_of`, from the employment history of the staff, or the current ones
+	/// if `as_of` is `None`
+	pub fn department_info_as_of(
+		&self,
+		dep_id: &DepartmentId,
+		scope: InfoScope,
+		as_of: Option<NaiveDate>,
+	) -> Option<DepartmentInfo> {
 		let dep = self.department(dep_id)?;
-		let headcount = 0;
-		let monthly_expense = 0;
 
-		Some(DepartmentInfo { department: dep, headcount, monthly_expense })
+		let mut dep_ids = vec![*dep_id];
+		if scope == InfoScope::RolledUp {
+			dep_ids.extend(self.descendant_department_ids(dep_id));
+		}
+
+		let mut headcount = 0;
+		let mut monthly_expense = 0;
+		let mut unknown_salary_count = 0;
+
+		for staff in &self.staff {
+			let (active, department, monthly_salary) = match as_of {
+				Some(date) => {
+					(staff.employed_on(date), staff.department_on(date), staff.monthly_salary_on(date))
+				}
+				None => (*staff.active(), *staff.department(), *staff.monthly_salary()),
+			};
+			if !active || department.is_none_or(|d| !dep_ids.contains(&d)) {
+				continue;
+			}
+
+			headcount += 1;
+			match monthly_salary {
+				Some(salary) => monthly_expense += u64::from(salary),
+				None => unknown_salary_count += 1,
+			}
+		}
+
+		Some(DepartmentInfo {
+			department: dep,
+			scope,
+			as_of,
+			headcount,
+			monthly_expense,
+			unknown_salary_count,
+		})
 	}
 
-	pub fn insert_staff(&mut self, builder: StaffBuilder) -> Result<&Staff, ApplicationError> {
-		// TODO: validate the staff builder info in the context
+	/// Check the staff info against the context. `staff_id` is the id of the staff being validated, if it
+	/// already exists, so it isn't reported as a duplicate of itself.
+	fn validate_staff(&self, builder: &StaffBuilder, staff_id: Option<StaffId>) -> Result<(), Error> {
+		if builder.first_name.trim().is_empty() {
+			Err(ValidationError::EmptyField("first_name"))?
+		}
+		if builder.last_name.trim().is_empty() {
+			Err(ValidationError::EmptyField("last_name"))?
+		}
+
+		if let Some(dep_id) = builder.department {
+			if !self.validate_department_id(&dep_id) {
+				Err(Error::unknown_department(dep_id))?
+			}
+		}
+
+		if let Some(manager) = builder.manager {
+			if self.staff_member(&manager).is_none() {
+				Err(Error::unknown_staff(manager))?
+			}
+			// a new staff has no reports yet, so can't close a cycle
+			if let Some(staff_id) = staff_id {
+				if manager == staff_id || self.chain_of_command(&manager).iter().any(|m| *m.id() == staff_id)
+				{
+					Err(ConflictError::ReportingCycle { staff: staff_id, manager })?
+				}
+			}
+		}
+
+		if builder.doj < builder.dob {
+			Err(ValidationError::JoinedBeforeBirth { dob: builder.dob, doj: builder.doj })?
+		}
+
+		let regex_email = Regex::new(r"^[^@\s]+@([^@\s]+)$").unwrap();
+		let Some(captures) = regex_email.captures(&builder.email) else {
+			Err(ValidationError::InvalidEmail(builder.email.clone()))?
+		};
+
+		if self.enforce_email_domain
+			&& !self.domain.is_empty()
+			&& !captures[1].eq_ignore_ascii_case(&self.domain)
+		{
+			Err(ValidationError::EmailOutsideDomain {
+				email: builder.email.clone(),
+				domain: self.domain.clone(),
+			})?
+		}
+
+		if self
+			.staff
+			.iter()
+			.any(|s| Some(*s.id()) != staff_id && s.email().eq_ignore_ascii_case(&builder.email))
+		{
+			Err(ConflictError::DuplicateEmail(builder.email.clone()))?
+		}
+
+		Ok(())
+	}
+
+	pub fn insert_staff(&mut self, builder: StaffBuilder) -> Result<&Staff, Error> {
+		self.validate_staff(&builder, None)?;
 
 		let new_staff = Staff::new(self.get_next_staff_id(), builder);
+		self.search_index.upsert_staff(&new_staff);
+		self.record(vec![Change::Staff { before: None, after: Some(new_staff.clone()) }]);
 		self.staff.push(new_staff);
 
 		Ok(self.staff.last().unwrap())
 	}
+
+	pub fn staff_member(&self, staff_id: &StaffId) -> Option<&Staff> {
+		self.staff.iter().find(|s| *s.id() == *staff_id)
+	}
+
+	/// When the staff joined, changed department, salary or title, and left
+	pub fn staff_timeline(&self, staff_id: &StaffId) -> Option<StaffTimeline<'_>> {
+		let staff = self.staff_member(staff_id)?;
+		let entries = staff.timeline(|dep_id| self.department(&dep_id).map(|dep| dep.name().clone()));
+		Some(StaffTimeline { staff, entries })
+	}
+
+	fn staff_index(&self, staff_id: &StaffId) -> Result<usize, Error> {
+		self.staff.iter().position(|s| *s.id() == *staff_id).ok_or(Error::unknown_staff(*staff_id))
+	}
+
+	pub fn update_staff(&mut self, staff_id: &StaffId, changes: StaffChanges) -> Result<&Staff, Error> {
+		let idx = self.staff_index(staff_id)?;
+		let effective = changes.effective.unwrap_or_else(|| Local::now().date_naive());
+		let staff = &self.staff[idx];
+		let builder = changes.apply_to(staff.to_builder());
+
+		self.validate_staff(&builder, Some(*staff_id))?;
+		if let Some(dot) = *staff.dot() {
+			if dot < builder.doj {
+				Err(ValidationError::TerminatedBeforeJoining { doj: builder.doj, dot })?
+			}
+		}
+		let employment_changed = builder.department != *staff.department()
+			|| builder.monthly_salary != *staff.monthly_salary()
+			|| builder.title != *staff.title();
+		if employment_changed && effective < builder.doj {
+			Err(ValidationError::ChangeBeforeJoining { doj: builder.doj, effective })?
+		}
+
+		let before = self.staff[idx].clone();
+		self.staff[idx].update(builder, effective);
+		self.search_index.upsert_staff(&self.staff[idx]);
+		self.record(vec![Change::Staff { before: Some(before), after: Some(self.staff[idx].clone()) }]);
+		Ok(&self.staff[idx])
+	}
+
+	/// Mark the staff as no longer with the company. The record is kept.
+	pub fn terminate_staff(&mut self, staff_id: &StaffId, dot: NaiveDate) -> Result<&Staff, Error> {
+		let idx = self.staff_index(staff_id)?;
+		let staff = &self.staff[idx];
+
+		if !*staff.active() {
+			Err(ConflictError::AlreadyTerminated(*staff_id))?
+		}
+		if dot < *staff.doj() {
+			Err(ValidationError::TerminatedBeforeJoining { doj: *staff.doj(), dot })?
+		}
+
+		let before = self.staff[idx].clone();
+		self.staff[idx].terminate(dot);
+		self.record(vec![Change::Staff { before: Some(before), after: Some(self.staff[idx].clone()) }]);
+		Ok(&self.staff[idx])
+	}
+
+	/// Remove the staff record entirely. Prefer `terminate_staff()` for staff leaving the company.
+	///
+	/// The reports of the staff are handed over to the staff's own manager, and the departments they
+	/// headed are left without a head.
+	pub fn delete_staff(&mut self, staff_id: &StaffId) -> Result<Staff, Error> {
+		let idx = self.staff_index(staff_id)?;
+		self.search_index.remove(&SearchKey::Staff(*staff_id));
+		let removed = self.staff.remove(idx);
+
+		// the removal comes first, to be summarized first
+		let mut changes = vec![Change::Staff { before: Some(removed.clone()), after: None }];
+		for staff in self.staff.iter_mut().filter(|s| *s.manager() == Some(*staff_id)) {
+			let before = staff.clone();
+			let staff_changes = StaffChanges { manager: Some(*removed.manager()), ..Default::default() };
+			staff.update(staff_changes.apply_to(staff.to_builder()), Local::now().date_naive());
+			changes.push(Change::Staff { before: Some(before), after: Some(staff.clone()) });
+		}
+		for dep in self.departments.iter_mut().filter(|d| *d.head() == Some(*staff_id)) {
+			let before = dep.clone();
+			dep.update(DepartmentChanges { head: Some(None), ..Default::default() });
+			changes.push(Change::Department { before: Some(before), after: Some(dep.clone()) });
+		}
+		self.record(changes);
+		Ok(removed)
+	}
+
+	/// Staff reporting to `staff_id` directly, sorted alphabetically like `list_staff()`
+	pub fn direct_reports(&self, staff_id: &StaffId) -> Vec<&Staff> {
+		let mut reports = self.staff.iter().filter(|s| *s.manager() == Some(*staff_id)).collect::<Vec<_>>();
+		sort_by_name(&mut reports);
+		reports
+	}
+
+	/// The managers above `staff_id`: their own manager first, up to the top of the reporting line
+	pub fn chain_of_command(&self, staff_id: &StaffId) -> Vec<&Staff> {
+		let mut chain: Vec<&Staff> = vec![];
+		let mut next = self.staff_member(staff_id).and_then(|s| *s.manager());
+
+		while let Some(manager_id) = next {
+			// guard against a malformed chain, so we never loop forever
+			if manager_id == *staff_id || chain.iter().any(|m| *m.id() == manager_id) {
+				break;
+			}
+			let Some(manager) = self.staff_member(&manager_id) else {
+				break;
+			};
+			chain.push(manager);
+			next = *manager.manager();
+		}
+
+		chain
+	}
+
+	/// Number of staff reporting to `staff_id` directly
+	pub fn span_of_control(&self, staff_id: &StaffId) -> usize {
+		self.staff.iter().filter(|s| *s.manager() == Some(*staff_id)).count()
+	}
+
+	/// Ids of all staff reporting to `staff_id`, directly or through their reports, not including
+	/// `staff_id` itself.
+	pub fn indirect_report_ids(&self, staff_id: &StaffId) -> Vec<StaffId> {
+		let mut result: Vec<StaffId> = vec![];
+		let mut pending = vec![*staff_id];
+
+		while let Some(current) = pending.pop() {
+			for staff in self.staff.iter().filter(|s| *s.manager() == Some(current)) {
+				// guard against malformed reporting lines, so we never loop forever
+				if *staff.id() != *staff_id && !result.contains(staff.id()) {
+					result.push(*staff.id());
+					pending.push(*staff.id());
+				}
+			}
+		}
+
+		result
+	}
+
+	pub fn reporting_line(&self, staff_id: &StaffId) -> Option<ReportingLine<'_>> {
+		let staff = self.staff_member(staff_id)?;
+		let direct_reports = self.direct_reports(staff_id);
+		Some(ReportingLine {
+			staff,
+			chain_of_command: self.chain_of_command(staff_id),
+			span_of_control: direct_reports.len(),
+			direct_reports,
+			total_reports: self.indirect_report_ids(staff_id).len(),
+		})
+	}
+
+	/// The reporting lines below `root`, or those of the whole company
+	pub fn reporting_tree(&self, root: Option<StaffId>) -> Result<ReportingTree<'_>, Error> {
+		let roots = match root {
+			Some(staff_id) => {
+				vec![self.staff_member(&staff_id).ok_or(Error::unknown_staff(staff_id))?]
+			}
+			None => {
+				// staff whose manager isn't in the directory head a reporting line of their own
+				let mut roots = self
+					.staff
+					.iter()
+					.filter(|s| s.manager().is_none_or(|m| self.staff_member(&m).is_none()))
+					.collect::<Vec<_>>();
+				sort_by_name(&mut roots);
+				roots
+			}
+		};
+
+		Ok(ReportingTree {
+			company_name: &self.company_name,
+			domain: &self.domain,
+			roots: roots.into_iter().map(|staff| self.reporting_node(staff, &mut vec![])).collect(),
+		})
+	}
+
+	/// `visited` guards against a reporting cycle, e.g. in a hand-edited data file, so we never recurse
+	/// forever
+	fn reporting_node<'a>(&'a self, staff: &'a Staff, visited: &mut Vec<StaffId>) -> ReportingNode<'a> {
+		visited.push(*staff.id());
+		let reports = self
+			.direct_reports(staff.id())
+			.into_iter()
+			.filter(|s| !visited.contains(s.id()))
+			.collect::<Vec<_>>();
+		ReportingNode {
+			staff,
+			reports: reports.into_iter().map(|s| self.reporting_node(s, visited)).collect(),
+		}
+	}
+
+	/// Staff whose id, name or email matches the query, best matches first
+	pub fn search_staff(&self, query: &str) -> Vec<StaffMatch<'_>> {
+		let mut matches = self
+			.staff
+			.iter()
+			.filter_map(|staff| {
+				staff.match_query(query).map(|(kind, field)| StaffMatch { staff, kind, field })
+			})
+			.collect::<Vec<_>>();
+
+		matches.sort_by_cached_key(|m| {
+			(
+				std::cmp::Reverse(m.kind),
+				m.staff.last_name().to_lowercase(),
+				m.staff.first_name().to_lowercase(),
+				m.staff.id().0,
+			)
+		});
+		matches
+	}
+
+	/// Staff matching the filter, sorted alphabetically by last name then first name.
+	pub fn list_staff(&self, filter: &StaffFilter) -> StaffPage<'_> {
+		let mut staff = self.staff.iter().filter(|s| filter.matches(s)).collect::<Vec<_>>();
+		sort_by_name(&mut staff);
+
+		let total = staff.len();
+		if let Some(paging) = filter.paging {
+			staff = staff
+				.into_iter()
+				.skip(paging.page.saturating_mul(paging.per_page))
+				.take(paging.per_page)
+				.collect();
+		}
+
+		StaffPage { staff, total, paging: filter.paging }
+	}
+
+	fn record(&mut self, changes: Vec<Change>) {
+		changes.iter().for_each(|change| self.mark_unsaved(change));
+		if let Some(log) = self.audit_log.as_mut() {
+			log.log(&self.workspace, &changes, AuditOrigin::Edit);
+		}
+		self.history.record(changes);
+	}
+
+	/// Bring the records in `change` to their `after` state, as when undoing or redoing. There is no
+	/// validation, as the states were valid when recorded.
+	fn apply(&mut self, change: &Change) {
+		match change {
+			Change::Company { after, .. } => {
+				self.company_name = after.name.clone();
+				self.domain = after.domain.clone();
+				self.enforce_email_domain = after.enforce_email_domain;
+			}
+			Change::Department { before, after } => {
+				let Some(dep_id) = after.as_ref().or(before.as_ref()).map(|d| *d.id()) else {
+					return;
+				};
+				replace_record(&mut self.departments, after.as_ref(), |d| *d.id() == dep_id, |d| *d.id());
+				match after {
+					Some(dep) => self.search_index.upsert_department(dep),
+					None => self.search_index.remove(&SearchKey::Department(dep_id)),
+				}
+			}
+			Change::Staff { before, after } => {
+				let Some(staff_id) = after.as_ref().or(before.as_ref()).map(|s| *s.id()) else {
+					return;
+				};
+				replace_record(&mut self.staff, after.as_ref(), |s| *s.id() == staff_id, |s| *s.id());
+				match after {
+					Some(staff) => self.search_index.upsert_staff(staff),
+					None => self.search_index.remove(&SearchKey::Staff(staff_id)),
+				}
+			}
+			Change::OtherWorkspace { workspace, change } => {
+				// the workspace may have been deleted since
+				if let Some(other) = self.other_workspaces.get_mut(workspace) {
+					other.apply(change);
+				}
+			}
+			Change::Directory { after, .. } => {
+				self.company_name = after.company_name.clone();
+				self.domain = after.domain.clone();
+				self.enforce_email_domain = after.enforce_email_domain;
+				self.next_department_id = after.next_department_id;
+				self.next_staff_id = after.next_staff_id;
+				self.departments = after.departments.clone();
+				self.staff = after.staff.clone();
+				self.workspace = after.workspace.clone();
+				self.other_workspaces = after.other_workspaces.clone();
+				self.data_file = after.data_file.clone();
+				self.rebuild_search_index();
+			}
+		}
+		self.mark_unsaved(change);
+	}
+
+	/// Insert staff moved from another workspace, returning their new ids
+	fn insert_moved_staff(
+		&mut self,
+		staff: &[Staff],
+		department: Option<DepartmentId>,
+	) -> Result<Vec<StaffId>, Error> {
+		self.group(|ctx| {
+			staff
+				.iter()
+				.map(|moved| {
+					// their manager stays behind, in the workspace they come from. Their employment history
+					// starts afresh, as it refers to the departments of that workspace.
+					let mut builder = StaffBuilder { department, manager: None, ..moved.to_builder() };
+					if ctx.enforce_email_domain && !ctx.domain.is_empty() {
+						if let Some((local_part, _)) = builder.email.split_once('@') {
+							builder.email = format!("{local_part}@{}", ctx.domain);
+						}
+					}
+
+					let staff_id = *ctx.insert_staff(builder)?.id();
+					if let Some(dot) = *moved.dot() {
+						ctx.terminate_staff(&staff_id, dot)?;
+					}
+					Ok(staff_id)
+				})
+				.collect()
+		})
+	}
+
+	fn mark_unsaved(&mut self, change: &Change) {
+		let unsaved = &mut self.unsaved_changes;
+		match change {
+			Change::Company { .. } => unsaved.company = true,
+			Change::Department { before, after } => {
+				unsaved.departments.extend(before.iter().chain(after).map(|d| *d.id()))
+			}
+			Change::Staff { before, after } => {
+				unsaved.staff.extend(before.iter().chain(after).map(|s| *s.id()))
+			}
+			Change::Directory { before, after } => {
+				unsaved.company = true;
+				unsaved.workspaces = true;
+				for ctx in [before, after] {
+					unsaved.departments.extend(ctx.departments.iter().map(|d| *d.id()));
+					unsaved.staff.extend(ctx.staff.iter().map(|s| *s.id()));
+				}
+			}
+			// marked in that workspace, by `apply()` or the mutation
+			Change::OtherWorkspace { .. } => {}
+		}
+	}
+}
+
+/// Sort staff alphabetically by last name then first name
+fn sort_by_name(staff: &mut [&Staff]) {
+	staff.sort_by_cached_key(|s| (s.last_name().to_lowercase(), s.first_name().to_lowercase(), s.id().0));
+}
+
+/// Replace the record matching `is_target` with `record`, or remove it if `record` is `None`. A record
+/// not found is inserted in id order.
+fn replace_record<T: Clone, K: Ord>(
+	records: &mut Vec<T>,
+	record: Option<&T>,
+	is_target: impl Fn(&T) -> bool,
+	id: impl Fn(&T) -> K,
+) {
+	match (records.iter().position(is_target), record) {
+		(Some(idx), Some(record)) => records[idx] = record.clone(),
+		(Some(idx), None) => {
+			records.remove(idx);
+		}
+		(None, Some(record)) => {
+			let idx = records.partition_point(|r| id(r) < id(record));
+			records.insert(idx, record.clone());
+		}
+		(None, None) => {}
+	}
 }
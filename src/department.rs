@@ -1,9 +1,15 @@
-use crate::traits::OneLiner;
+use crate::{
+	errors::Error,
+	persistence::double_option,
+	staff::StaffId,
+	traits::{OneLiner, Tabular},
+};
+use chrono::NaiveDate;
 use getset::{Getters, Setters};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct DepartmentId(pub u32);
 
 impl From<u32> for DepartmentId {
@@ -19,10 +25,11 @@ impl From<DepartmentId> for u32 {
 }
 
 impl TryFrom<&str> for DepartmentId {
-	type Error = &'static str;
+	type Error = Error;
 
 	fn try_from(value: &str) -> Result<Self, Self::Error> {
-		let value = value.parse::<u32>().map_err(|_| "Input unable to convert to DepartmentId")?;
+		let value =
+			value.parse::<u32>().map_err(|_| Error::parse("department id", format!("\"{value}\"")))?;
 		Ok(DepartmentId(value))
 	}
 }
@@ -36,13 +43,37 @@ pub struct Department {
 	id: DepartmentId,
 	name: String,
 	parent: Option<DepartmentId>,
+	// the staff heading the department
+	head: Option<StaffId>,
 	active: bool,
 }
 
 impl Department {
 	pub fn new(id: DepartmentId, builder: DepartmentBuilder) -> Self {
 		let DepartmentBuilder { name, parent, active } = builder;
-		Department { id, name, parent, active: active.unwrap_or(true) }
+		Department { id, name, parent, head: None, active: active.unwrap_or(true) }
+	}
+}
+
+impl Department {
+	pub(crate) fn update(&mut self, changes: DepartmentChanges) {
+		let DepartmentChanges { name, parent, head, active } = changes;
+		if let Some(name) = name {
+			self.name = name;
+		}
+		if let Some(parent) = parent {
+			self.parent = parent;
+		}
+		if let Some(head) = head {
+			self.head = head;
+		}
+		if let Some(active) = active {
+			self.active = active;
+		}
+	}
+
+	pub(crate) fn set_parent(&mut self, parent: Option<DepartmentId>) {
+		self.parent = parent;
 	}
 }
 
@@ -52,7 +83,23 @@ impl OneLiner for Department {
 	}
 }
 
-#[derive(Debug)]
+impl Tabular for Department {
+	fn headers() -> Vec<&'static str> {
+		vec!["id", "name", "parent", "head", "active"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![
+			self.id.0.to_string(),
+			self.name.clone(),
+			self.parent.map_or("".to_string(), |p| p.0.to_string()),
+			self.head.map_or("".to_string(), |h| h.0.to_string()),
+			self.active.to_string(),
+		]
+	}
+}
+
+#[derive(Debug, Deserialize)]
 pub struct DepartmentBuilder {
 	pub name: String,
 	pub parent: Option<DepartmentId>,
@@ -65,11 +112,84 @@ impl DepartmentBuilder {
 	}
 }
 
-#[derive(Debug)]
+/**
+ * A change set for an existing department. Only the fields set to `Some` are changed.
+ **/
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DepartmentChanges {
+	pub name: Option<String>,
+	// `Some(None)` turns the department into a top-level department
+	#[serde(default, deserialize_with = "double_option")]
+	pub parent: Option<Option<DepartmentId>>,
+	// `Some(None)` leaves the department without a head
+	#[serde(default, deserialize_with = "double_option")]
+	pub head: Option<Option<StaffId>>,
+	pub active: Option<bool>,
+}
+
+/**
+ * What happens to the sub-departments and the staff of a department being removed
+ **/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalPolicy {
+	// refuse to remove a department that still has sub-departments or staff
+	#[default]
+	Refuse,
+	// remove all sub-departments as well, leaving their staff without a department
+	Cascade,
+	// move the sub-departments and the staff up to the parent of the removed department
+	ReassignToParent,
+}
+
+/**
+ * Whether department figures cover the department alone, or the department and all of its
+ * descendant departments
+ **/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InfoScope {
+	#[default]
+	Direct,
+	RolledUp,
+}
+
+#[derive(Debug, Serialize)]
 pub struct DepartmentInfo<'a> {
 	pub department: &'a Department,
+	pub scope: InfoScope,
+	// the date the figures are for, if not the current ones
+	pub as_of: Option<NaiveDate>,
+	// number of active staff
 	pub headcount: u32,
+	// sum of the monthly salary of active staff with a known salary
 	pub monthly_expense: u64,
+	// number of active staff whose salary is unknown, hence not part of `monthly_expense`
+	pub unknown_salary_count: u32,
+}
+
+impl Tabular for DepartmentInfo<'_> {
+	fn headers() -> Vec<&'static str> {
+		let mut headers = Department::headers();
+		headers.extend(["scope", "as_of", "headcount", "monthly_expense", "unknown_salary_count"]);
+		headers
+	}
+
+	fn row(&self) -> Vec<String> {
+		let mut row = self.department.row();
+		row.extend([
+			match self.scope {
+				InfoScope::Direct => "direct".to_string(),
+				InfoScope::RolledUp => "rolled_up".to_string(),
+			},
+			self.as_of.map_or("".to_string(), |d| d.to_string()),
+			self.headcount.to_string(),
+			self.monthly_expense.to_string(),
+			self.unknown_salary_count.to_string(),
+		]);
+		row
+	}
 }
 
 impl fmt::Display for DepartmentInfo<'_> {
@@ -80,16 +200,67 @@ impl fmt::Display for DepartmentInfo<'_> {
 Id: {}
 Name: {}
 Parent department: {}
+Head: {}
+Scope: {}
+As of: {}
 Head count: {}
-Monthly cost: {}"#,
+Monthly cost: {}
+Staff with unknown salary: {}"#,
 			self.department.id().0,
 			self.department.name(),
 			match self.department.parent() {
 				Some(parent_dep_id) => parent_dep_id.0.to_string(),
 				None => "none".to_string(),
 			},
+			self.department.head().map_or("none".to_string(), |h| h.0.to_string()),
+			match self.scope {
+				InfoScope::Direct => "this department only",
+				InfoScope::RolledUp => "including sub-departments",
+			},
+			self.as_of.map_or("today".to_string(), |d| d.to_string()),
 			self.headcount,
 			self.monthly_expense,
+			self.unknown_salary_count,
 		)
 	}
 }
+
+/**
+ * The department hierarchy of a company, top-level departments first
+ **/
+#[derive(Debug, Serialize)]
+pub struct DepartmentTree<'a> {
+	pub company_name: &'a str,
+	pub domain: &'a str,
+	pub roots: Vec<DepartmentNode<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DepartmentNode<'a> {
+	pub department: &'a Department,
+	pub children: Vec<DepartmentNode<'a>>,
+}
+
+impl<'a> DepartmentTree<'a> {
+	/// All departments with their depth in the tree, in depth-first order
+	pub fn flatten(&self) -> Vec<(usize, &'a Department)> {
+		fn visit<'a>(node: &DepartmentNode<'a>, depth: usize, result: &mut Vec<(usize, &'a Department)>) {
+			result.push((depth, node.department));
+			node.children.iter().for_each(|child| visit(child, depth + 1, result));
+		}
+
+		let mut result = vec![];
+		self.roots.iter().for_each(|root| visit(root, 0, &mut result));
+		result
+	}
+}
+
+impl fmt::Display for DepartmentTree<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} ({})", self.company_name, self.domain)?;
+		for (depth, dep) in self.flatten() {
+			write!(f, "\n{}L {}", "  ".repeat(depth), dep.one_liner())?;
+		}
+		Ok(())
+	}
+}
@@ -1,12 +1,397 @@
-use std::{error, fmt};
+use chrono::NaiveDate;
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+use std::{error, fmt, io, num, str, string};
 
-#[derive(Debug)]
-pub struct ApplicationError(pub String);
+use crate::{department::DepartmentId, staff::StaffId};
 
-impl fmt::Display for ApplicationError {
+/**
+ * Every error of the application. `code()` is stable: scripts and clients of the HTTP API and the
+ * line protocol match on it, so a code is never changed or reused once released.
+ **/
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+	// a field of the input breaking a rule
+	Validation(ValidationError),
+	// a record looked up by its id, or its name, that doesn't exist
+	NotFound { entity: Entity, id: String },
+	// a change the current state of the directory doesn't allow
+	Conflict(ConflictError),
+	Persistence(PersistenceError),
+	// text that can't be read as the value it stands for, e.g. a malformed date or JSON
+	Parse { what: String, reason: String },
+	Io { kind: io::ErrorKind, message: String },
+}
+
+impl Error {
+	pub fn unknown_staff(staff_id: StaffId) -> Self {
+		Self::NotFound { entity: Entity::Staff, id: staff_id.0.to_string() }
+	}
+
+	pub fn unknown_department(dep_id: DepartmentId) -> Self {
+		Self::NotFound { entity: Entity::Department, id: dep_id.0.to_string() }
+	}
+
+	pub fn unknown_workspace(name: &str) -> Self {
+		Self::NotFound { entity: Entity::Workspace, id: name.to_string() }
+	}
+
+	pub fn parse(what: &str, reason: impl fmt::Display) -> Self {
+		Self::Parse { what: what.to_string(), reason: reason.to_string() }
+	}
+
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::Validation(err) => err.code(),
+			Self::NotFound { .. } => "not_found",
+			Self::Conflict(err) => err.code(),
+			Self::Persistence(err) => err.code(),
+			Self::Parse { .. } => "parse_error",
+			Self::Io { .. } => "io_error",
+		}
+	}
+
+	/// The field of the input at fault, for validation errors
+	pub fn field(&self) -> Option<&'static str> {
+		match self {
+			Self::Validation(err) => Some(err.field()),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "Application Error: {}", self.0)
+		match self {
+			Self::Validation(err) => write!(f, "Validation Error: {err}"),
+			// ids are numbers, while workspaces and departments are also looked up by name
+			Self::NotFound { entity, id } if id.parse::<u32>().is_ok() => {
+				write!(f, "Not Found: unknown {entity} {id}")
+			}
+			Self::NotFound { entity, id } => write!(f, "Not Found: unknown {entity} \"{id}\""),
+			Self::Conflict(err) => write!(f, "Conflict: {err}"),
+			Self::Persistence(err) => write!(f, "{err}"),
+			Self::Parse { what, reason } => write!(f, "Parse Error: invalid {what}: {reason}"),
+			Self::Io { message, .. } => write!(f, "IO Error: {message}"),
+		}
+	}
+}
+
+impl error::Error for Error {}
+
+/// Serializes as `{"code": ..., "message": ..., "field": ...}`, leaving the field out unless there is one
+impl Serialize for Error {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut state = serializer.serialize_struct("Error", 3)?;
+		state.serialize_field("code", self.code())?;
+		state.serialize_field("message", &self.to_string())?;
+		match self.field() {
+			Some(field) => state.serialize_field("field", field)?,
+			None => state.skip_field("field")?,
+		}
+		state.end()
+	}
+}
+
+impl From<ValidationError> for Error {
+	fn from(err: ValidationError) -> Self {
+		Self::Validation(err)
+	}
+}
+
+impl From<ConflictError> for Error {
+	fn from(err: ConflictError) -> Self {
+		Self::Conflict(err)
+	}
+}
+
+impl From<PersistenceError> for Error {
+	fn from(err: PersistenceError) -> Self {
+		Self::Persistence(err)
+	}
+}
+
+impl From<DataFileError> for Error {
+	fn from(err: DataFileError) -> Self {
+		Self::Persistence(PersistenceError::DataFile(err))
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Self::Io { kind: err.kind(), message: err.to_string() }
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(err: serde_json::Error) -> Self {
+		Self::parse("JSON", err)
+	}
+}
+
+impl From<csv::Error> for Error {
+	fn from(err: csv::Error) -> Self {
+		match err.kind() {
+			csv::ErrorKind::Io(io_err) => Self::Io { kind: io_err.kind(), message: io_err.to_string() },
+			_ => Self::parse("CSV", err),
+		}
+	}
+}
+
+impl From<rusqlite::Error> for Error {
+	fn from(err: rusqlite::Error) -> Self {
+		Self::Persistence(PersistenceError::Database(err.to_string()))
+	}
+}
+
+impl From<num::ParseIntError> for Error {
+	fn from(err: num::ParseIntError) -> Self {
+		Self::parse("number", err)
 	}
 }
 
-impl error::Error for ApplicationError {}
+impl From<str::ParseBoolError> for Error {
+	fn from(err: str::ParseBoolError) -> Self {
+		Self::parse("flag", err)
+	}
+}
+
+impl From<chrono::ParseError> for Error {
+	fn from(err: chrono::ParseError) -> Self {
+		Self::parse("date", err)
+	}
+}
+
+impl From<string::FromUtf8Error> for Error {
+	fn from(err: string::FromUtf8Error) -> Self {
+		Self::parse("UTF-8", err)
+	}
+}
+
+/**
+ * The kinds of records that can be looked up
+ **/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entity {
+	Staff,
+	Department,
+	Workspace,
+	Backup,
+	// a path of the HTTP API
+	Route,
+}
+
+impl fmt::Display for Entity {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Staff => write!(f, "staff"),
+			Self::Department => write!(f, "department"),
+			Self::Workspace => write!(f, "workspace"),
+			Self::Backup => write!(f, "backup"),
+			Self::Route => write!(f, "route"),
+		}
+	}
+}
+
+/**
+ * Data files that can't be upgraded to the current format
+ **/
+#[derive(Debug, PartialEq, Eq)]
+pub enum DataFileError {
+	InvalidVersion,
+	// saved by a newer build of the application
+	UnsupportedVersion { found: u32, supported: u32 },
+	Migration { from: u32, reason: String },
+	// the workspace the file was saved in isn't among its workspaces
+	MissingWorkspace(String),
+	// the fields of the file don't make a directory
+	Malformed(String),
+}
+
+impl fmt::Display for DataFileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Data File Error: ")?;
+		match self {
+			Self::InvalidVersion => write!(f, "format_version must be a non-negative integer"),
+			Self::UnsupportedVersion { found, supported } => write!(
+				f,
+				"the file is in format version {found}, but this build only reads up to version {supported}. Please upgrade the application."
+			),
+			Self::Migration { from, reason } => {
+				write!(f, "cannot upgrade the file from format version {from}: {reason}")
+			}
+			Self::MissingWorkspace(name) => write!(f, "the current workspace \"{name}\" is not in the file"),
+			Self::Malformed(reason) => write!(f, "{reason}"),
+		}
+	}
+}
+
+/**
+ * Failures to load or save the directory, other than I/O errors
+ **/
+#[derive(Debug, PartialEq, Eq)]
+pub enum PersistenceError {
+	DataFile(DataFileError),
+	Database(String),
+	// there is no data file to save to
+	NoDataFile,
+	NoAuditLog,
+	// backups are only kept for JSON data files
+	BackupsUnsupported,
+}
+
+impl PersistenceError {
+	fn code(&self) -> &'static str {
+		match self {
+			Self::DataFile(DataFileError::InvalidVersion) => "invalid_format_version",
+			Self::DataFile(DataFileError::UnsupportedVersion { .. }) => "unsupported_format_version",
+			Self::DataFile(DataFileError::Migration { .. }) => "migration_failed",
+			Self::DataFile(DataFileError::MissingWorkspace(_)) => "missing_workspace",
+			Self::DataFile(DataFileError::Malformed(_)) => "malformed_data_file",
+			Self::Database(_) => "database_error",
+			Self::NoDataFile => "no_data_file",
+			Self::NoAuditLog => "no_audit_log",
+			Self::BackupsUnsupported => "backups_unsupported",
+		}
+	}
+}
+
+impl fmt::Display for PersistenceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::DataFile(err) => write!(f, "{err}"),
+			Self::Database(reason) => write!(f, "Persistence Error: {reason}"),
+			Self::NoDataFile => {
+				write!(f, "Persistence Error: there is no data file. Please give one, or use \"Save as\".")
+			}
+			Self::NoAuditLog => {
+				write!(f, "Persistence Error: there is no audit log. Please start with --audit-log FILE.")
+			}
+			Self::BackupsUnsupported => {
+				write!(f, "Persistence Error: backups are only kept for JSON data files")
+			}
+		}
+	}
+}
+
+/**
+ * Rules the fields of a record have to satisfy before it is accepted into the context
+ **/
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+	TerminatedBeforeJoining { doj: NaiveDate, dot: NaiveDate },
+	// a department, salary or title change taking effect before the staff joined
+	ChangeBeforeJoining { doj: NaiveDate, effective: NaiveDate },
+	EmptyField(&'static str),
+	JoinedBeforeBirth { dob: NaiveDate, doj: NaiveDate },
+	InvalidEmail(String),
+	EmailOutsideDomain { email: String, domain: String },
+	InvalidDomain(String),
+}
+
+impl ValidationError {
+	pub fn field(&self) -> &'static str {
+		match self {
+			Self::TerminatedBeforeJoining { .. } => "dot",
+			Self::ChangeBeforeJoining { .. } => "effective",
+			Self::EmptyField(field) => field,
+			Self::JoinedBeforeBirth { .. } => "doj",
+			Self::InvalidEmail(_) | Self::EmailOutsideDomain { .. } => "email",
+			Self::InvalidDomain(_) => "domain",
+		}
+	}
+
+	fn code(&self) -> &'static str {
+		match self {
+			Self::TerminatedBeforeJoining { .. } => "terminated_before_joining",
+			Self::ChangeBeforeJoining { .. } => "change_before_joining",
+			Self::EmptyField(_) => "empty_field",
+			Self::JoinedBeforeBirth { .. } => "joined_before_birth",
+			Self::InvalidEmail(_) => "invalid_email",
+			Self::EmailOutsideDomain { .. } => "email_outside_domain",
+			Self::InvalidDomain(_) => "invalid_domain",
+		}
+	}
+}
+
+impl fmt::Display for ValidationError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::TerminatedBeforeJoining { doj, dot } => {
+				write!(f, "date of termination {dot} is before date of joining {doj}")
+			}
+			Self::ChangeBeforeJoining { doj, effective } => {
+				write!(f, "a change effective on {effective} is before date of joining {doj}")
+			}
+			Self::EmptyField(field) => write!(f, "{field} must not be empty"),
+			Self::JoinedBeforeBirth { dob, doj } => {
+				write!(f, "date of joining {doj} is before date of birth {dob}")
+			}
+			Self::InvalidEmail(email) => write!(f, "invalid email {email}"),
+			Self::EmailOutsideDomain { email, domain } => {
+				write!(f, "email {email} is not under the company domain {domain}")
+			}
+			Self::InvalidDomain(domain) => write!(f, "invalid domain {domain}"),
+		}
+	}
+}
+
+/**
+ * Changes that clash with the records already in the context
+ **/
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConflictError {
+	AlreadyTerminated(StaffId),
+	AmbiguousDepartmentName(String),
+	DepartmentCycle { department: DepartmentId, parent: DepartmentId },
+	DepartmentHasChildren(DepartmentId),
+	DepartmentHasStaff(DepartmentId),
+	ReportingCycle { staff: StaffId, manager: StaffId },
+	DuplicateEmail(String),
+	DuplicateWorkspace(String),
+	// the operation needs a workspace other than the current one
+	CurrentWorkspace(String),
+}
+
+impl ConflictError {
+	fn code(&self) -> &'static str {
+		match self {
+			Self::AlreadyTerminated(_) => "already_terminated",
+			Self::AmbiguousDepartmentName(_) => "ambiguous_department_name",
+			Self::DepartmentCycle { .. } => "department_cycle",
+			Self::DepartmentHasChildren(_) => "department_has_children",
+			Self::DepartmentHasStaff(_) => "department_has_staff",
+			Self::ReportingCycle { .. } => "reporting_cycle",
+			Self::DuplicateEmail(_) => "duplicate_email",
+			Self::DuplicateWorkspace(_) => "duplicate_workspace",
+			Self::CurrentWorkspace(_) => "current_workspace",
+		}
+	}
+}
+
+impl fmt::Display for ConflictError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::AlreadyTerminated(staff_id) => write!(f, "staff {} is already terminated", staff_id.0),
+			Self::AmbiguousDepartmentName(name) => {
+				write!(f, "more than one department is named \"{name}\", please use the department id")
+			}
+			Self::DepartmentCycle { department, parent } => write!(
+				f,
+				"department {} cannot be moved under {}, as it would become its own ancestor",
+				department.0, parent.0
+			),
+			Self::DepartmentHasChildren(dep_id) => write!(f, "department {} has sub-departments", dep_id.0),
+			Self::DepartmentHasStaff(dep_id) => write!(f, "department {} has staff assigned", dep_id.0),
+			Self::ReportingCycle { staff, manager } => write!(
+				f,
+				"staff {} cannot report to {}, as they would end up reporting to themselves",
+				staff.0, manager.0
+			),
+			Self::DuplicateEmail(email) => write!(f, "email {email} is already used by another staff"),
+			Self::DuplicateWorkspace(name) => write!(f, "workspace \"{name}\" already exists"),
+			Self::CurrentWorkspace(name) => {
+				write!(f, "\"{name}\" is the current workspace, please switch to another one first")
+			}
+		}
+	}
+}
@@ -0,0 +1,110 @@
+use crate::{
+	context::Context,
+	department::DepartmentId,
+	errors::Error,
+	output,
+	staff::{Staff, StaffFilter},
+};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum ExportColumn {
+	Id,
+	FirstName,
+	LastName,
+	Email,
+	Dob,
+	Doj,
+	Dot,
+	Gender,
+	// the department name
+	Department,
+	DepartmentId,
+	Title,
+	MonthlySalary,
+	Active,
+}
+
+impl ExportColumn {
+	pub fn all() -> Vec<ExportColumn> {
+		Self::value_variants().to_vec()
+	}
+
+	pub fn header(&self) -> String {
+		self.to_possible_value().map_or("".to_string(), |v| v.get_name().to_string())
+	}
+
+	fn value(&self, ctx: &Context, staff: &Staff, date_format: &str) -> String {
+		match self {
+			Self::Id => staff.id().0.to_string(),
+			Self::FirstName => staff.first_name().clone(),
+			Self::LastName => staff.last_name().clone(),
+			Self::Email => staff.email().clone(),
+			Self::Dob => staff.dob().format(date_format).to_string(),
+			Self::Doj => staff.doj().format(date_format).to_string(),
+			Self::Dot => staff.dot().map_or("".to_string(), |d| d.format(date_format).to_string()),
+			Self::Gender => format!("{:?}", staff.gender()),
+			Self::Title => staff.title().clone().unwrap_or_default(),
+			Self::Department => staff
+				.department()
+				.and_then(|dep_id| ctx.department(&dep_id))
+				.map_or("".to_string(), |dep| dep.name().clone()),
+			Self::DepartmentId => staff.department().map_or("".to_string(), |d| d.0.to_string()),
+			Self::MonthlySalary => staff.monthly_salary().map_or("".to_string(), |s| s.to_string()),
+			Self::Active => staff.active().to_string(),
+		}
+	}
+}
+
+impl TryFrom<&str> for ExportColumn {
+	type Error = String;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		Self::from_str(value.trim(), true).map_err(|_| format!("Unknown column \"{}\"", value.trim()))
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+	// only export staff of this department and its sub-departments
+	pub department: Option<DepartmentId>,
+	pub active_only: bool,
+	pub columns: Vec<ExportColumn>,
+	// `chrono` format of the dob, doj and dot columns
+	pub date_format: String,
+}
+
+impl Default for ExportOptions {
+	fn default() -> Self {
+		Self {
+			department: None,
+			active_only: false,
+			columns: ExportColumn::all(),
+			date_format: "%Y-%m-%d".to_string(),
+		}
+	}
+}
+
+/// Export staff as CSV with a header row, sorted by last name then first name
+pub fn export_staff_csv(ctx: &Context, options: &ExportOptions) -> Result<String, Error> {
+	let departments = options.department.map(|dep_id| {
+		let mut ids = vec![dep_id];
+		ids.extend(ctx.descendant_department_ids(&dep_id));
+		ids
+	});
+
+	let filter = StaffFilter { active: options.active_only.then_some(true), ..Default::default() };
+	let rows = ctx
+		.list_staff(&filter)
+		.staff
+		.into_iter()
+		.filter(|s| departments.as_ref().is_none_or(|ids| s.department().is_some_and(|d| ids.contains(&d))))
+		.map(|s| options.columns.iter().map(|c| c.value(ctx, s, &options.date_format)).collect())
+		.collect::<Vec<_>>();
+
+	let headers = options.columns.iter().map(|c| c.header()).collect::<Vec<_>>();
+	let mut content = output::csv(&headers, &rows)?;
+	content.push('\n');
+	Ok(content)
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+use std::{collections::VecDeque, fmt};
+
+use crate::{context::Context, department::Department, staff::Staff, traits::OneLiner};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CompanyInfo {
+	pub name: String,
+	pub domain: String,
+	pub enforce_email_domain: bool,
+}
+
+/**
+ * A single record as it was before and after a mutation. `None` means the record didn't exist,
+ * so an insertion has no `before` and a deletion has no `after`.
+ **/
+#[derive(Clone, Debug)]
+// staff changes are the most common ones, so boxing them would only add an allocation to most changes
+#[allow(clippy::large_enum_variant)]
+pub enum Change {
+	Company { before: CompanyInfo, after: CompanyInfo },
+	Department { before: Option<Department>, after: Option<Department> },
+	Staff { before: Option<Staff>, after: Option<Staff> },
+	// the whole directory was replaced by loading a file
+	Directory { before: Box<Context>, after: Box<Context> },
+	// a change made to another workspace of the data file, e.g. by moving staff there
+	OtherWorkspace { workspace: String, change: Box<Change> },
+}
+
+impl Change {
+	/// The change reverting this one
+	pub fn inverse(&self) -> Change {
+		match self.clone() {
+			Change::Company { before, after } => Change::Company { before: after, after: before },
+			Change::Department { before, after } => Change::Department { before: after, after: before },
+			Change::Staff { before, after } => Change::Staff { before: after, after: before },
+			Change::Directory { before, after } => Change::Directory { before: after, after: before },
+			Change::OtherWorkspace { workspace, change } => {
+				Change::OtherWorkspace { workspace, change: Box::new(change.inverse()) }
+			}
+		}
+	}
+}
+
+impl fmt::Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Change::Company { after, .. } => {
+				write!(f, "company info set to {} ({})", after.name, after.domain)
+			}
+			Change::Department { before, after } => describe(f, before.as_ref(), after.as_ref()),
+			Change::Staff { before, after } => describe(f, before.as_ref(), after.as_ref()),
+			Change::Directory { .. } => write!(f, "directory loaded"),
+			Change::OtherWorkspace { workspace, change } => write!(f, "{change} in workspace {workspace}"),
+		}
+	}
+}
+
+/**
+ * Undo and redo stacks of the mutations made to a context. Each step is the list of changes made by one
+ * mutation, e.g. removing a department along with its sub-departments is undone as a whole.
+ **/
+#[derive(Clone, Debug)]
+pub struct History {
+	undo: VecDeque<Vec<Change>>,
+	redo: Vec<Vec<Change>>,
+	// the oldest steps are dropped beyond this
+	limit: usize,
+	// whether loading a file forgets the history, or is recorded as a step that can be undone
+	clear_on_load: bool,
+	// changes made within `Context::group()`, to be recorded as one step
+	group: Option<Vec<Change>>,
+}
+
+impl Default for History {
+	fn default() -> Self {
+		Self::new(Self::DEFAULT_LIMIT, true)
+	}
+}
+
+impl History {
+	pub const DEFAULT_LIMIT: usize = 100;
+
+	pub fn new(limit: usize, clear_on_load: bool) -> Self {
+		Self { undo: VecDeque::new(), redo: vec![], limit, clear_on_load, group: None }
+	}
+
+	pub fn clear_on_load(&self) -> bool {
+		self.clear_on_load
+	}
+
+	/// An empty history with the same settings
+	pub fn cleared(&self) -> Self {
+		Self::new(self.limit, self.clear_on_load)
+	}
+
+	/// Record a new step. Anything undone before can no longer be redone.
+	pub fn record(&mut self, changes: Vec<Change>) {
+		if let Some(group) = self.group.as_mut() {
+			group.extend(changes);
+			return;
+		}
+		self.redo.clear();
+		self.push_undo(changes);
+	}
+
+	/// Returns false if a group is already open, in which case the changes go to that group
+	pub(crate) fn begin_group(&mut self) -> bool {
+		if self.group.is_some() {
+			return false;
+		}
+		self.group = Some(vec![]);
+		true
+	}
+
+	pub(crate) fn end_group(&mut self) {
+		if let Some(changes) = self.group.take() {
+			self.record(changes);
+		}
+	}
+
+	pub(crate) fn pop_undo(&mut self) -> Option<Vec<Change>> {
+		self.undo.pop_back()
+	}
+
+	pub(crate) fn pop_redo(&mut self) -> Option<Vec<Change>> {
+		self.redo.pop()
+	}
+
+	pub(crate) fn push_undo(&mut self, changes: Vec<Change>) {
+		if changes.is_empty() || self.limit == 0 {
+			return;
+		}
+		self.undo.push_back(changes);
+		while self.undo.len() > self.limit {
+			self.undo.pop_front();
+		}
+	}
+
+	pub(crate) fn push_redo(&mut self, changes: Vec<Change>) {
+		self.redo.push(changes);
+	}
+
+	/// Follow a workspace renamed to `new_name`, or deleted with `None`, in the changes made to it from
+	/// another workspace. The changes to a deleted workspace are dropped, as there is nothing left to undo.
+	pub(crate) fn rename_workspace(&mut self, name: &str, new_name: Option<&str>) {
+		let steps = self.undo.iter_mut().chain(self.redo.iter_mut()).chain(self.group.iter_mut());
+		for changes in steps {
+			changes.retain_mut(|change| match change {
+				Change::OtherWorkspace { workspace, .. } if workspace == name => match new_name {
+					Some(new_name) => {
+						*workspace = new_name.to_string();
+						true
+					}
+					None => false,
+				},
+				_ => true,
+			});
+		}
+	}
+}
+
+/// One line describing a step, e.g. when it's undone
+pub fn summary(changes: &[Change]) -> String {
+	match changes {
+		[] => "nothing".to_string(),
+		[change] => change.to_string(),
+		[change, rest @ ..] => format!("{change}, and {} more change(s)", rest.len()),
+	}
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn describe<T: OneLiner>(f: &mut fmt::Formatter<'_>, before: Option<&T>, after: Option<&T>) -> fmt::Result {
+	match (before, after) {
+		(None, Some(after)) => write!(f, "created {}", after.one_liner()),
+		(Some(_), Some(after)) => write!(f, "updated {}", after.one_liner()),
+		(Some(before), None) => write!(f, "removed {}", before.one_liner()),
+		(None, None) => Ok(()),
+	}
+}
@@ -0,0 +1,263 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::{collections::HashMap, fmt, io};
+
+use crate::{
+	context::Context,
+	department::DepartmentBuilder,
+	errors::Error,
+	staff::{Gender, StaffBuilder, StaffId},
+	traits::Tabular,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportKind {
+	Staff,
+	Departments,
+}
+
+impl ImportKind {
+	/// Fields that can be imported, and whether each is required
+	fn fields(&self) -> &'static [(&'static str, bool)] {
+		match self {
+			ImportKind::Staff => &[
+				("first_name", true),
+				("last_name", true),
+				("email", true),
+				("dob", true),
+				("doj", true),
+				("gender", true),
+				("title", false),
+				("department", false),
+				("manager", false),
+				("monthly_salary", false),
+			],
+			ImportKind::Departments => &[("name", true), ("parent", false), ("active", false)],
+		}
+	}
+}
+
+/**
+ * Which CSV column each field is read from. A field not in the mapping is read from the column
+ * with the same name as the field, e.g. `first_name`.
+ **/
+#[derive(Clone, Debug, Default)]
+pub struct ColumnMapping(HashMap<String, String>);
+
+impl ColumnMapping {
+	pub fn new() -> Self {
+		Self(HashMap::new())
+	}
+
+	pub fn map(mut self, field: &str, column: &str) -> Self {
+		self.0.insert(field.to_string(), column.to_string());
+		self
+	}
+
+	pub fn column<'a>(&'a self, field: &'a str) -> &'a str {
+		self.0.get(field).map_or(field, |c| c.as_str())
+	}
+}
+
+/// Parse a mapping in the form of `field=column`, e.g. `first_name=Given Name`
+impl TryFrom<&str> for ColumnMapping {
+	type Error = &'static str;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		value.split(',').filter(|pair| !pair.trim().is_empty()).try_fold(Self::new(), |mapping, pair| {
+			let (field, column) =
+				pair.split_once('=').ok_or("Column mapping must be in the form of field=column")?;
+			Ok(mapping.map(field.trim(), column.trim()))
+		})
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+	pub mapping: ColumnMapping,
+	// validate every row and report the result, without changing the context
+	pub dry_run: bool,
+	// `chrono` format of the date columns
+	pub date_format: String,
+}
+
+impl Default for ImportOptions {
+	fn default() -> Self {
+		Self { mapping: ColumnMapping::new(), dry_run: false, date_format: "%Y-%m-%d".to_string() }
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ImportRow {
+	// line number in the CSV file, the header being line 1
+	pub line: u64,
+	pub result: Result<u32, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportReport {
+	pub kind: ImportKind,
+	pub dry_run: bool,
+	pub rows: Vec<ImportRow>,
+}
+
+impl ImportReport {
+	pub fn created(&self) -> usize {
+		self.rows.iter().filter(|r| r.result.is_ok()).count()
+	}
+
+	pub fn failed(&self) -> usize {
+		self.rows.len() - self.created()
+	}
+}
+
+impl fmt::Display for ImportReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for row in &self.rows {
+			match &row.result {
+				Ok(id) => writeln!(f, "line {}: created with id {id}", row.line)?,
+				Err(err) => writeln!(f, "line {}: {err}", row.line)?,
+			}
+		}
+		let created = if self.dry_run { "would be created" } else { "created" };
+		write!(f, "{} {created}, {} failed", self.created(), self.failed())
+	}
+}
+
+impl Tabular for ImportRow {
+	fn headers() -> Vec<&'static str> {
+		vec!["line", "status", "id", "error"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		match &self.result {
+			Ok(id) => vec![self.line.to_string(), "ok".to_string(), id.to_string(), "".to_string()],
+			Err(err) => vec![self.line.to_string(), "error".to_string(), "".to_string(), err.clone()],
+		}
+	}
+}
+
+/// Import staff or departments from CSV. Every row goes through the same validation as the
+/// interactive menu, and a failing row doesn't stop the rows after it.
+pub fn import_csv(
+	ctx: &mut Context,
+	kind: ImportKind,
+	reader: impl io::Read,
+	options: &ImportOptions,
+) -> Result<ImportReport, Error> {
+	let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+	let headers = csv_reader.headers()?.clone();
+
+	// position of each field's column
+	let mut columns: HashMap<&str, usize> = HashMap::new();
+	for (field, required) in kind.fields() {
+		let column = options.mapping.column(field);
+		match headers.iter().position(|h| h == column) {
+			Some(idx) => {
+				columns.insert(field, idx);
+			}
+			None if *required => {
+				Err(Error::parse("CSV", format!("missing column \"{column}\" for {field}")))?
+			}
+			None => {}
+		}
+	}
+
+	// a dry run works on a copy, so the rows can still refer to each other
+	let mut dry_run_ctx;
+	let ctx = if options.dry_run {
+		dry_run_ctx = ctx.clone();
+		dry_run_ctx.set_audit_log(None);
+		&mut dry_run_ctx
+	} else {
+		ctx
+	};
+
+	// the whole import is undone at once
+	let rows = ctx.group(|ctx| -> Result<_, Error> {
+		let mut rows = vec![];
+		for record in csv_reader.records() {
+			// a malformed record fails its own row only, e.g. one with more fields than the header. The
+			// reader failing stops the import.
+			let record = match record {
+				Ok(record) => record,
+				Err(err) if err.is_io_error() => Err(err)?,
+				Err(err) => {
+					let line = err.position().map_or(0, |p| p.line());
+					rows.push(ImportRow { line, result: Err(Error::from(err).to_string()) });
+					continue;
+				}
+			};
+			let line = record.position().map_or(0, |p| p.line());
+			let values =
+				columns.iter().map(|(field, idx)| (*field, record.get(*idx).unwrap_or_default())).collect();
+
+			let result = match kind {
+				ImportKind::Staff => import_staff_row(ctx, &values, &options.date_format),
+				ImportKind::Departments => import_department_row(ctx, &values),
+			};
+			rows.push(ImportRow { line, result: result.map_err(|e| e.to_string()) });
+		}
+		Ok(rows)
+	})?;
+
+	Ok(ImportReport { kind, dry_run: options.dry_run, rows })
+}
+
+fn import_staff_row(
+	ctx: &mut Context,
+	values: &HashMap<&str, &str>,
+	date_format: &str,
+) -> Result<u32, Error> {
+	let value = |field: &str| values.get(field).copied().unwrap_or_default();
+	let date = |field: &str| {
+		NaiveDate::parse_from_str(value(field), date_format)
+			.map_err(|_| Error::parse(field, format!("\"{}\"", value(field))))
+	};
+
+	let department = match value("department") {
+		"" => None,
+		reference => Some(ctx.resolve_department(reference)?),
+	};
+	// a staff id, as staff names aren't unique. The manager must exist, e.g. on an earlier row.
+	let manager = match value("manager") {
+		"" => None,
+		reference => Some(StaffId::try_from(reference)?),
+	};
+	let monthly_salary = match value("monthly_salary") {
+		"" => None,
+		salary => Some(salary.parse::<u32>().map_err(|_| Error::parse("salary", format!("\"{salary}\"")))?),
+	};
+
+	let builder = StaffBuilder {
+		first_name: value("first_name").to_string(),
+		last_name: value("last_name").to_string(),
+		email: value("email").to_string(),
+		dob: date("dob")?,
+		doj: date("doj")?,
+		gender: Gender::try_from(value("gender"))?,
+		title: Some(value("title").to_string()).filter(|title| !title.is_empty()),
+		department,
+		manager,
+		monthly_salary,
+	};
+
+	Ok(ctx.insert_staff(builder)?.id().0)
+}
+
+fn import_department_row(ctx: &mut Context, values: &HashMap<&str, &str>) -> Result<u32, Error> {
+	let value = |field: &str| values.get(field).copied().unwrap_or_default();
+	let parent = match value("parent") {
+		"" => None,
+		reference => Some(ctx.resolve_department(reference)?),
+	};
+	let active = match value("active").to_lowercase().as_str() {
+		"" | "true" | "y" | "yes" => true,
+		"false" | "n" | "no" => false,
+		other => Err(Error::parse("active flag", format!("\"{other}\"")))?,
+	};
+
+	let builder = DepartmentBuilder { name: value("name").to_string(), parent, active: Some(active) };
+	Ok(ctx.insert_department(builder)?.id().0)
+}
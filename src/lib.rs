@@ -1,28 +1,51 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser, error::ErrorKind};
 use regex::Regex;
 use std::{
 	boxed::Box,
-	error, fs,
+	env,
 	io::{self, Write},
 	path::PathBuf,
 };
 
+pub mod audit;
+pub mod commands;
 pub mod context;
 pub mod department;
 pub mod errors;
+pub mod export;
+pub mod history;
+pub mod import;
 pub mod menu_items;
+pub mod org_chart;
+pub mod output;
+pub mod persistence;
+pub mod protocol;
+pub mod search;
+pub mod server;
 pub mod staff;
+pub mod storage;
 pub mod traits;
+pub mod workspace;
 
 #[cfg(test)]
 mod tests;
 
 use crate::{
+	audit::AuditLog,
+	commands::Command,
 	context::Context,
+	errors::{Error, PersistenceError},
+	history::History,
 	menu_items::{
-		CreateDepartment, CreateStaff, ListDepartments, ListStaff, LoadContext, MenuItem, NameCompany,
-		PrintContext, Quit, SaveContext, ShowDepartment,
+		CreateDepartment, CreateStaff, CreateWorkspace, DeleteStaff, DeleteWorkspace, ExportCsv, FuzzySearch,
+		ImportCsv, ListBackups, ListDepartments, ListReportingLines, ListStaff, ListWorkspaces, LoadContext,
+		MenuItem, MoveStaff, NameCompany, OrgChart, PrintContext, QueryAuditLog, Quit, Redo,
+		RemoveDepartment, RenameWorkspace, RestoreBackup, RetrieveStaff, SaveContext, SaveContextAs,
+		ShowDepartment, ShowReportingLine, ShowStaffHistory, SwitchWorkspace, Undo, UpdateDepartment,
+		UpdateStaff,
 	},
+	output::OutputFormat,
+	storage::StorageKind,
 };
 
 #[derive(Parser, Debug)]
@@ -32,39 +55,130 @@ pub struct Cli {
 
 	#[arg(short, long, default_value_t = false)]
 	debug: bool,
+
+	/// How results are printed
+	#[arg(short, long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+	format: OutputFormat,
+
+	/// How the data file is stored
+	#[arg(long, value_enum, default_value_t = StorageKind::Json, global = true)]
+	storage: StorageKind,
+
+	/// How many changes can be undone
+	#[arg(long, default_value_t = History::DEFAULT_LIMIT)]
+	history_size: usize,
+
+	/// Keep the undo history when loading a file, so that the load itself can be undone
+	#[arg(long)]
+	keep_history_on_load: bool,
+
+	/// Log every change to this file
+	#[arg(long, value_name = "FILE", global = true)]
+	audit_log: Option<PathBuf>,
+
+	/// Who the changes are logged as made by. Defaults to the current user.
+	#[arg(long, global = true)]
+	actor: Option<String>,
+
+	/// Serve the data file over a local HTTP JSON API on this address, e.g. 127.0.0.1:8080
+	#[arg(long, value_name = "ADDR", requires = "data_file")]
+	serve: Option<String>,
+
+	/// Answer newline-delimited JSON requests from stdin on stdout instead of opening the menu
+	#[arg(long)]
+	stdio: bool,
+
+	/// Run a single command on the data file instead of the interactive menu
+	#[command(subcommand)]
+	command: Option<Command>,
 }
 
-pub fn run(cli: Option<Cli>) -> Result<(), Box<dyn error::Error>> {
+pub fn run(cli: Option<Cli>) -> Result<(), Error> {
+	// run a single command non-interactively if specified
+	let audit_log = cli.as_ref().and_then(|cli| {
+		let actor = cli.actor.clone().or_else(|| env::var("USER").ok()).unwrap_or("unknown".to_string());
+		cli.audit_log.as_ref().map(|path| AuditLog::new(path, &actor))
+	});
+
+	if let Some(Cli { data_file, command: Some(command), format, storage, .. }) = cli {
+		let Some(data_file) = data_file else { Err(PersistenceError::NoDataFile)? };
+		return commands::run_command(command, &data_file, format, storage, audit_log);
+	}
+
+	// serve the data file over HTTP if specified
+	if let Some(Cli { data_file: Some(data_file), serve: Some(addr), storage, .. }) = cli {
+		return server::serve(&addr, &data_file, storage, audit_log);
+	}
+
 	// load the context if specified
 	let mut ctx = if cli.is_none() || cli.as_ref().unwrap().data_file.is_none() {
 		Context::new()
 	} else {
 		let data_filepath = cli.as_ref().unwrap().data_file.as_ref().unwrap();
-		let content = fs::read_to_string(data_filepath)?;
-		serde_json::from_str::<Context>(&content)?
+		let mut ctx = storage::open(cli.as_ref().unwrap().storage, data_filepath)?.load()?;
+		ctx.set_data_file(Some(data_filepath.clone()));
+		ctx
 	};
 
+	// answer requests over stdin and stdout if specified
+	if cli.as_ref().unwrap().stdio {
+		ctx.set_storage(cli.as_ref().unwrap().storage);
+		ctx.set_audit_log(audit_log);
+		return protocol::run(&mut ctx, io::stdin().lock(), io::stdout().lock());
+	}
+
 	let debug = cli.as_ref().unwrap().debug;
+	ctx.set_output_format(cli.as_ref().unwrap().format);
+	ctx.set_storage(cli.as_ref().unwrap().storage);
+	ctx.set_audit_log(audit_log);
+	ctx.set_history(History::new(
+		cli.as_ref().unwrap().history_size,
+		!cli.as_ref().unwrap().keep_history_on_load,
+	));
 
 	let mut menu_items: Vec<Box<dyn MenuItem>> = vec![
 		Box::new(NameCompany::new()),
 		Box::new(ListDepartments::new()),
+		Box::new(OrgChart()),
 		Box::new(CreateDepartment::new()),
 		Box::new(ShowDepartment()),
+		Box::new(UpdateDepartment()),
+		Box::new(RemoveDepartment()),
 		Box::new(ListStaff()),
 		Box::new(CreateStaff()),
+		Box::new(RetrieveStaff()),
+		Box::new(FuzzySearch()),
+		Box::new(UpdateStaff()),
+		Box::new(DeleteStaff()),
+		Box::new(ShowStaffHistory()),
+		Box::new(ShowReportingLine()),
+		Box::new(ListReportingLines()),
+		Box::new(ImportCsv()),
+		Box::new(ExportCsv()),
+		Box::new(ListWorkspaces()),
+		Box::new(CreateWorkspace()),
+		Box::new(SwitchWorkspace()),
+		Box::new(RenameWorkspace()),
+		Box::new(DeleteWorkspace()),
+		Box::new(MoveStaff()),
+		Box::new(QueryAuditLog()),
+		Box::new(Undo()),
+		Box::new(Redo()),
 		Box::new(SaveContext()),
+		Box::new(SaveContextAs()),
 		Box::new(LoadContext()),
+		Box::new(ListBackups()),
+		Box::new(RestoreBackup()),
 	];
 	if debug {
 		menu_items.push(Box::new(PrintContext()));
 	}
 	menu_items.push(Box::new(Quit()));
 
-	let re_digits = Regex::new(r"\d+$")?;
+	let re_digits = Regex::new(r"\d+$").unwrap();
 
 	loop {
-		display_menu(&menu_items)?;
+		display_menu(&ctx, &menu_items)?;
 
 		let mut input = String::new();
 		io::stdin().read_line(&mut input)?;
@@ -93,7 +207,9 @@ pub fn run(cli: Option<Cli>) -> Result<(), Box<dyn error::Error>> {
 			}
 			t if !t.is_empty() => {
 				if let Some(mi) = get_menu_item_from_shortcut(&menu_items, t) {
-					let _ = mi.execute_interactive(&mut ctx);
+					if let Err(err) = mi.execute_interactive(&mut ctx) {
+						println!("{}", err);
+					}
 				} else {
 					println!("Invalid shortcut");
 				}
@@ -104,13 +220,30 @@ pub fn run(cli: Option<Cli>) -> Result<(), Box<dyn error::Error>> {
 }
 
 pub fn parse() -> Cli {
-	Cli::parse()
+	let cli = Cli::parse();
+	if cli.command.is_some() && cli.data_file.is_none() {
+		Cli::command()
+			.error(ErrorKind::MissingRequiredArgument, "A data file is required to run a command")
+			.exit();
+	}
+	if cli.command.is_some() && (cli.serve.is_some() || cli.stdio) {
+		Cli::command().error(ErrorKind::ArgumentConflict, "A command can't be run while serving").exit();
+	}
+	if cli.serve.is_some() && cli.stdio {
+		Cli::command()
+			.error(ErrorKind::ArgumentConflict, "--serve and --stdio can't be used together")
+			.exit();
+	}
+	cli
 }
 
 /**
  * Un-export / internal helper methods below
  **/
-fn display_menu(menu_items: &[Box<dyn MenuItem>]) -> Result<(), Box<dyn error::Error>> {
+fn display_menu(ctx: &Context, menu_items: &[Box<dyn MenuItem>]) -> Result<(), Error> {
+	if ctx.workspaces().len() > 1 {
+		println!("[Workspace: {}]", ctx.workspace());
+	}
 	println!("What do you want to do?");
 
 	for (idx, item) in menu_items.iter().enumerate() {
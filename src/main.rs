@@ -1,6 +1,13 @@
-use std::error;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn error::Error>> {
+fn main() -> ExitCode {
 	let cli = staff_directory::parse();
-	staff_directory::run(Some(cli))
+
+	match staff_directory::run(Some(cli)) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(staff_directory::commands::exit_code(&err))
+		}
+	}
 }
@@ -1,49 +1,175 @@
-use chrono::NaiveDate;
+use chrono::{Local, NaiveDate};
 use regex::Regex;
+use serde::Serialize;
 use std::{
-	error, fs,
+	fmt, fs,
 	io::{self, Write},
-	path::Path,
+	path::{Path, PathBuf},
 };
 
 use crate::{
+	audit::{self, AuditEntity, AuditEntry, AuditQuery},
 	context::Context,
-	department::{Department, DepartmentBuilder, DepartmentId, DepartmentInfo},
-	errors::ApplicationError,
-	staff::{Gender, Staff, StaffBuilder},
+	department::{
+		Department, DepartmentBuilder, DepartmentChanges, DepartmentId, DepartmentInfo, DepartmentTree,
+		InfoScope, RemovalPolicy,
+	},
+	errors::{Entity, Error, PersistenceError, ValidationError},
+	export::{self, ExportColumn, ExportOptions},
+	history::CompanyInfo,
+	import::{self, ColumnMapping, ImportKind, ImportOptions, ImportReport},
+	org_chart::{self, ChartSyntax, OrgChartOptions},
+	output,
+	persistence::{self, Backup},
+	search::SearchResult,
+	staff::{
+		Gender, Paging, RemovedStaff, ReportingLine, ReportingTree, Staff, StaffBuilder, StaffChanges,
+		StaffFilter, StaffId, StaffMatch, StaffPage, StaffRemoval, StaffTimeline,
+	},
+	storage::{self, StorageKind},
 	traits::OneLiner,
+	workspace::{MovedStaff, WorkspaceSummary},
 };
 
+/**
+ * An entry of the interactive menu, which prompts for its input. Object-safe, so that the menu can list
+ * items of different types.
+ **/
 pub trait MenuItem {
 	fn menuitem_txt(&self) -> &str;
 	fn shortcut(&self) -> Option<&str>;
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>>;
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>>;
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error>;
 }
 
-pub enum MenuItemInput {
-	String(String),
-	StringVec(Vec<String>),
-	DepartmentBuilder(DepartmentBuilder),
-	StaffBuilder(StaffBuilder),
-	None,
+/**
+ * Carries out a menu item without prompting, for library callers, the commands, the HTTP API and the
+ * line protocol. Each item declares the input it takes and the output it returns, e.g. `NameCompany`
+ * takes a `NameCompanyInput` and returns nothing.
+ **/
+pub trait Execute {
+	type Input;
+	type Output<'a>: Into<MenuItemOutput<'a>>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, input: Self::Input) -> Result<Self::Output<'a>, Error>;
+
+	/// Bind the input, for callers which pick the menu item at runtime
+	fn bind(self, input: Self::Input) -> Box<dyn Invocation>
+	where
+		Self: Sized + 'static,
+		Self::Input: 'static,
+	{
+		Box::new(Bound { item: self, input })
+	}
+}
+
+/**
+ * A menu item bound to its input, e.g. for a command line subcommand or a request. Its output is
+ * widened to a `MenuItemOutput` to be rendered.
+ **/
+pub trait Invocation {
+	fn invoke(self: Box<Self>, ctx: &mut Context) -> Result<MenuItemOutput<'_>, Error>;
+}
+
+struct Bound<T: Execute> {
+	item: T,
+	input: T::Input,
 }
 
+impl<T: Execute> Invocation for Bound<T> {
+	fn invoke(self: Box<Self>, ctx: &mut Context) -> Result<MenuItemOutput<'_>, Error> {
+		Ok(self.item.execute(ctx, self.input)?.into())
+	}
+}
+
+/**
+ * Serializes to the bare payload of each variant, e.g. a staff list serializes as the page of staff,
+ * so the JSON output can be piped into other tools directly.
+ **/
+#[derive(Serialize)]
+#[serde(untagged)]
 pub enum MenuItemOutput<'a> {
 	String(String),
 	Department(&'a Department),
+	DepartmentTree(DepartmentTree<'a>),
+	Departments(Vec<&'a Department>),
+	DeletedDepartments(Vec<Department>),
 	Staff(&'a Staff),
+	DeletedStaff(Staff),
+	StaffMatches(Vec<StaffMatch<'a>>),
+	SearchResults(Vec<SearchResult<'a>>),
+	ImportReport(ImportReport),
 	DepartmentInfo(DepartmentInfo<'a>),
+	StaffPage(StaffPage<'a>),
+	Backups(Vec<Backup>),
+	AuditEntries(Vec<AuditEntry>),
+	Workspaces(Vec<WorkspaceSummary>),
+	MovedStaff(Vec<MovedStaff>),
+	ReportingLine(ReportingLine<'a>),
+	ReportingTree(ReportingTree<'a>),
+	StaffTimeline(StaffTimeline<'a>),
 	None,
 }
 
+// each output type widens to the variant holding it
+macro_rules! impl_from_output {
+	($($variant:ident($type:ty)),* $(,)?) => {
+		$(impl<'a> From<$type> for MenuItemOutput<'a> {
+			fn from(output: $type) -> Self {
+				MenuItemOutput::$variant(output)
+			}
+		})*
+	};
+}
+
+impl_from_output!(
+	String(String),
+	Department(&'a Department),
+	DepartmentTree(DepartmentTree<'a>),
+	Departments(Vec<&'a Department>),
+	DeletedDepartments(Vec<Department>),
+	Staff(&'a Staff),
+	DeletedStaff(Staff),
+	StaffMatches(Vec<StaffMatch<'a>>),
+	SearchResults(Vec<SearchResult<'a>>),
+	ImportReport(ImportReport),
+	DepartmentInfo(DepartmentInfo<'a>),
+	StaffPage(StaffPage<'a>),
+	Backups(Vec<Backup>),
+	AuditEntries(Vec<AuditEntry>),
+	Workspaces(Vec<WorkspaceSummary>),
+	MovedStaff(Vec<MovedStaff>),
+	ReportingLine(ReportingLine<'a>),
+	ReportingTree(ReportingTree<'a>),
+	StaffTimeline(StaffTimeline<'a>),
+);
+
+impl From<()> for MenuItemOutput<'_> {
+	fn from(_: ()) -> Self {
+		MenuItemOutput::None
+	}
+}
+
+impl<'a> From<RemovedStaff<'a>> for MenuItemOutput<'a> {
+	fn from(removed: RemovedStaff<'a>) -> Self {
+		match removed {
+			RemovedStaff::Terminated(staff) => MenuItemOutput::Staff(staff),
+			RemovedStaff::Deleted(staff) => MenuItemOutput::DeletedStaff(staff),
+		}
+	}
+}
+
 /**
  * NameCompany
  **/
+#[derive(Debug)]
+pub struct NameCompanyInput {
+	pub name: String,
+	// only the name is set when there is no domain
+	pub domain: Option<String>,
+	// whether staff emails have to be under the domain, unchanged if not given
+	pub enforce_email_domain: Option<bool>,
+}
+
 pub struct NameCompany {
 	menuitem_txt: String,
 	shortcut: String,
@@ -69,7 +195,7 @@ impl MenuItem for NameCompany {
 		Some(&self.shortcut)
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
 		println!("What is the new name of the company?");
 
 		let mut name = String::new();
@@ -89,35 +215,41 @@ impl MenuItem for NameCompany {
 			}
 		}
 
-		let _ = self.execute(
+		let enforce =
+			!read_line("Require staff emails to be under this domain? (Y/n): ")?.eq_ignore_ascii_case("n");
+
+		self.execute(
 			ctx,
-			MenuItemInput::StringVec(vec![name.trim().to_string(), domain.trim().to_string()]),
-		)?;
-		Ok(())
+			NameCompanyInput {
+				name: name.trim().to_string(),
+				domain: Some(domain.trim().to_string()),
+				enforce_email_domain: Some(enforce),
+			},
+		)
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		params: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		// Set the company name
-		let MenuItemInput::StringVec(string_vec) = params else {
-			Err(Box::new(ApplicationError("Unrecognized params".to_string())))?
-		};
+impl Execute for NameCompany {
+	type Input = NameCompanyInput;
+	type Output<'a> = ();
 
-		let [ref name, ref domain] = string_vec[..] else {
-			Err(Box::new(ApplicationError("Invalid params".to_string())))?
+	fn execute(&self, ctx: &mut Context, input: NameCompanyInput) -> Result<(), Error> {
+		let NameCompanyInput { name, domain, enforce_email_domain } = input;
+		let Some(domain) = domain else {
+			ctx.set_company_name(name);
+			return Ok(());
 		};
 
-		if !NameCompany::validate_domain(domain) {
-			Err(Box::new(ApplicationError("Invalid domain".to_string())))?
+		if !NameCompany::validate_domain(&domain) {
+			return Err(ValidationError::InvalidDomain(domain).into());
 		}
 
-		ctx.set_company_name(name.to_string());
-		ctx.set_domain(domain.to_string());
-
-		Ok(MenuItemOutput::None)
+		ctx.set_company_info(CompanyInfo {
+			name,
+			domain,
+			enforce_email_domain: enforce_email_domain.unwrap_or(*ctx.enforce_email_domain()),
+		});
+		Ok(())
 	}
 }
 
@@ -133,20 +265,6 @@ impl ListDepartments {
 	pub fn new() -> Self {
 		Self { menuitem_txt: "List department hierarchy".to_string(), shortcut: "ld".to_string() }
 	}
-
-	fn department_and_children_one_liners(ctx: &Context, dep: &Department, level: u32) -> String {
-		let mut result: String = format!("{}L {}\n", "  ".repeat(level as usize), dep.one_liner());
-
-		let dep_str = ctx
-			.departments()
-			.iter()
-			.filter(|d| *d.parent() == Some(*dep.id()))
-			.map(|d| Self::department_and_children_one_liners(ctx, d, level + 1))
-			.fold(String::new(), |acc, line| acc + &line); // this is the way to concatenate two strings with a return value, if we don't want to use format!() macro call.
-
-		result.push_str(&dep_str);
-		result
-	}
 }
 
 impl MenuItem for ListDepartments {
@@ -158,32 +276,21 @@ impl MenuItem for ListDepartments {
 		Some(&self.shortcut)
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		let MenuItemOutput::String(result) = self.execute(ctx, MenuItemInput::None)? else {
-			Err(Box::new(ApplicationError("Unrecognized output".to_string())))?
-		};
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ())?;
 
-		println!("{result}");
+		println!("{}", output::render(&output.into(), format)?);
 		Ok(())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		_input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let mut result = format!("{} ({})\n", ctx.company_name(), ctx.domain());
-
-		let dep_str = ctx
-			.departments()
-			.iter()
-			.filter(|dep| dep.parent().is_none())
-			.map(|dep| Self::department_and_children_one_liners(ctx, dep, 0))
-			.fold(String::new(), |acc, line| acc + &line); // this is the way to concatenate two strings with a return value, if we don't want to use format!() macro call.
-
-		result.push_str(dep_str.trim());
+impl Execute for ListDepartments {
+	type Input = ();
+	type Output<'a> = DepartmentTree<'a>;
 
-		Ok(MenuItemOutput::String(result))
+	fn execute<'a>(&self, ctx: &'a mut Context, _: ()) -> Result<DepartmentTree<'a>, Error> {
+		Ok(ctx.department_tree())
 	}
 }
 
@@ -210,7 +317,7 @@ impl MenuItem for CreateDepartment {
 		Some(&self.shortcut)
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
 		println!("What's the name of the new department?");
 		let mut name = String::new();
 		io::stdin().read_line(&mut name)?;
@@ -225,26 +332,30 @@ impl MenuItem for CreateDepartment {
 		let parent =
 			if !parent.is_empty() { Some(<DepartmentId as TryFrom<&str>>::try_from(parent)?) } else { None };
 
-		self.execute(ctx, MenuItemInput::DepartmentBuilder(DepartmentBuilder::new(name, parent))).map(|_| ())
+		self.execute(ctx, DepartmentBuilder::new(name, parent)).map(|_| ())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let MenuItemInput::DepartmentBuilder(builder) = input else {
-			Err(Box::new(ApplicationError("Unrecognized input".to_string())))?
-		};
+impl Execute for CreateDepartment {
+	type Input = DepartmentBuilder;
+	type Output<'a> = &'a Department;
 
-		let dep = ctx.insert_department(builder)?;
-		Ok(MenuItemOutput::Department(dep))
+	fn execute<'a>(&self, ctx: &'a mut Context, builder: DepartmentBuilder) -> Result<&'a Department, Error> {
+		ctx.insert_department(builder)
 	}
 }
 
 /**
  * ShowDepartment
  **/
+#[derive(Debug)]
+pub struct ShowDepartmentInput {
+	pub id: DepartmentId,
+	pub scope: InfoScope,
+	// the figures on the date, if given, or else the current ones
+	pub as_of: Option<NaiveDate>,
+}
+
 pub struct ShowDepartment();
 
 impl MenuItem for ShowDepartment {
@@ -256,7 +367,7 @@ impl MenuItem for ShowDepartment {
 		Some("rd")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
 		let next_dep_id = <DepartmentId as Into<u32>>::into(*ctx.next_department_id());
 		if next_dep_id == 0 {
 			println!("No department exists yet.");
@@ -269,36 +380,194 @@ impl MenuItem for ShowDepartment {
 
 		let mut input = String::new();
 		io::stdin().read_line(&mut input)?;
+		let dep_id = <DepartmentId as TryFrom<&str>>::try_from(input.trim())?;
+
+		let scope = match read_line("Include sub-departments? (y/N): ")?.to_lowercase().as_str() {
+			"y" => InfoScope::RolledUp,
+			_ => InfoScope::Direct,
+		};
+
+		let as_of = loop {
+			let input = read_line("As of date (YYYY-MM-DD, leave it empty for today): ")?;
+			if input.is_empty() {
+				break None;
+			}
+			match NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+				Ok(date) => break Some(date),
+				Err(_) => println!("Please enter a valid date in YYYY-MM-DD format"),
+			}
+		};
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ShowDepartmentInput { id: dep_id, scope, as_of })?;
+
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ShowDepartment {
+	type Input = ShowDepartmentInput;
+	type Output<'a> = DepartmentInfo<'a>;
+
+	fn execute<'a>(
+		&self,
+		ctx: &'a mut Context,
+		input: ShowDepartmentInput,
+	) -> Result<DepartmentInfo<'a>, Error> {
+		let ShowDepartmentInput { id, scope, as_of } = input;
+
+		ctx.department_info_as_of(&id, scope, as_of).ok_or(Error::unknown_department(id))
+	}
+}
+
+/**
+ * UpdateDepartment
+ **/
+#[derive(Debug)]
+pub struct UpdateDepartmentInput {
+	pub id: DepartmentId,
+	pub changes: DepartmentChanges,
+}
+
+pub struct UpdateDepartment();
+
+impl MenuItem for UpdateDepartment {
+	fn menuitem_txt(&self) -> &str {
+		"Update an existing department"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("ud")
+	}
 
-		let MenuItemOutput::DepartmentInfo(dep_info) =
-			self.execute(ctx, MenuItemInput::String(input.trim().to_string()))?
-		else {
-			Err(Box::new(ApplicationError("Unrecognized output".to_string())))?
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(dep) = read_department(ctx)? else {
+			return Ok(());
 		};
+		let dep_id = *dep.id();
+
+		println!("Press \"Enter\" to keep the current value.");
+		let changes = DepartmentChanges {
+			name: read_change("Name", dep.name(), |s| Ok::<_, &str>(s.to_string()))?,
+			parent: read_change(
+				"Parent department (\"-\" for none)",
+				&dep.parent().map_or("none".to_string(), |p| p.0.to_string()),
+				|s| if s == "-" { Ok(None) } else { DepartmentId::try_from(s).map(Some) },
+			)?,
+			head: read_change(
+				"Head staff id (\"-\" for none)",
+				&dep.head().map_or("none".to_string(), |h| h.0.to_string()),
+				|s| if s == "-" { Ok(None) } else { StaffId::try_from(s).map(Some) },
+			)?,
+			active: read_change("Active (y/n)", if *dep.active() { "y" } else { "n" }, |s| {
+				match s.to_lowercase().as_str() {
+					"y" => Ok(true),
+					"n" => Ok(false),
+					_ => Err("Invalid input. Please enter 'y' or 'n' only"),
+				}
+			})?,
+		};
+
+		let dep = self.execute(ctx, UpdateDepartmentInput { id: dep_id, changes })?;
 
-		println!("{dep_info}");
+		println!("Updated {}", dep.one_liner());
 		Ok(())
 	}
+}
+
+impl Execute for UpdateDepartment {
+	type Input = UpdateDepartmentInput;
+	type Output<'a> = &'a Department;
 
 	fn execute<'a>(
 		&self,
 		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let MenuItemInput::String(dep_id) = input else {
-			Err(Box::new(ApplicationError("Unrecognized input".to_string())))?
+		input: UpdateDepartmentInput,
+	) -> Result<&'a Department, Error> {
+		ctx.update_department(&input.id, input.changes)
+	}
+}
+
+/**
+ * RemoveDepartment
+ **/
+#[derive(Debug)]
+pub struct RemoveDepartmentInput {
+	pub id: DepartmentId,
+	pub policy: RemovalPolicy,
+}
+
+pub struct RemoveDepartment();
+
+impl MenuItem for RemoveDepartment {
+	fn menuitem_txt(&self) -> &str {
+		"Remove an existing department"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("dd")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(dep) = read_department(ctx)? else {
+			return Ok(());
 		};
 
-		let Some(dep_info) = ctx.department_info(&DepartmentId(dep_id.parse::<u32>()?)) else {
-			Err(Box::new(ApplicationError("Invalid department ID".to_string())))?
+		println!("What should happen to its sub-departments and staff?");
+		println!("  (r)efuse if there are any [default]");
+		println!("  (c)ascade: remove all sub-departments too, staff are left without a department");
+		println!("  (p)arent: move sub-departments and staff to the parent department");
+		let policy = loop {
+			match read_line("? ")?.to_lowercase().as_str() {
+				"" | "r" => break RemovalPolicy::Refuse,
+				"c" => break RemovalPolicy::Cascade,
+				"p" => break RemovalPolicy::ReassignToParent,
+				_ => println!("Invalid input. Please enter 'r', 'c' or 'p' only"),
+			}
 		};
 
-		Ok(MenuItemOutput::DepartmentInfo(dep_info))
+		let removed = self.execute(ctx, RemoveDepartmentInput { id: *dep.id(), policy })?;
+
+		for dep in removed {
+			println!("Removed {}", dep.one_liner());
+		}
+		Ok(())
+	}
+}
+
+impl Execute for RemoveDepartment {
+	type Input = RemoveDepartmentInput;
+	type Output<'a> = Vec<Department>;
+
+	/// The removed departments
+	fn execute(&self, ctx: &mut Context, input: RemoveDepartmentInput) -> Result<Vec<Department>, Error> {
+		ctx.remove_department(&input.id, input.policy)
 	}
 }
 
+/**
+ * ListStaff
+ **/
 pub struct ListStaff();
 
+impl ListStaff {
+	const PAGE_SIZE: usize = 20;
+
+	fn read_optional_date(prompt: &str) -> Result<Option<NaiveDate>, Error> {
+		loop {
+			let input = read_line(prompt)?;
+			if input.is_empty() {
+				return Ok(None);
+			}
+			if let Ok(d) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+				return Ok(Some(d));
+			}
+			println!("Please enter a valid date in YYYY-MM-DD format");
+		}
+	}
+}
+
 impl MenuItem for ListStaff {
 	fn menuitem_txt(&self) -> &str {
 		"List all staff"
@@ -308,16 +577,70 @@ impl MenuItem for ListStaff {
 		Some("ls")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		Ok(())
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let mut filter = StaffFilter::default();
+
+		let dep = read_line("Department (leave it empty for all): ")?;
+		if !dep.is_empty() {
+			filter.department = Some(<DepartmentId as TryFrom<&str>>::try_from(dep.as_str())?);
+		}
+
+		filter.active = match read_line("Active staff only? (y)es / (n)o, inactive only / Enter for all: ")?
+			.to_lowercase()
+			.as_str()
+		{
+			"y" => Some(true),
+			"n" => Some(false),
+			_ => None,
+		};
+
+		filter.joined_from =
+			Self::read_optional_date("Joined on or after (YYYY-MM-DD, leave it empty for any): ")?;
+		filter.joined_to =
+			Self::read_optional_date("Joined on or before (YYYY-MM-DD, leave it empty for any): ")?;
+		filter.as_of = Self::read_optional_date(
+			"Department and activity as of (YYYY-MM-DD, leave it empty for the current ones): ",
+		)?;
+
+		let mut page = 0;
+		loop {
+			filter.paging = Some(Paging { page, per_page: Self::PAGE_SIZE });
+
+			let format = *ctx.output_format();
+			let staff_page = self.execute(ctx, filter.clone())?;
+			let (total, has_next_page, page_count) =
+				(staff_page.total, staff_page.has_next_page(), staff_page.page_count());
+
+			if total == 0 {
+				println!("No staff found.");
+				return Ok(());
+			}
+
+			println!("{}", output::render(&staff_page.into(), format)?);
+
+			if !has_next_page {
+				println!("({total} staff in total)");
+				return Ok(());
+			}
+
+			let answer = read_line(&format!(
+				"-- page {}/{page_count}, press Enter for the next page or \"q\" to stop -- ",
+				page + 1,
+			))?;
+			if answer.eq_ignore_ascii_case("q") {
+				return Ok(());
+			}
+			page += 1;
+		}
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		Ok(MenuItemOutput::None)
+impl Execute for ListStaff {
+	type Input = StaffFilter;
+	type Output<'a> = StaffPage<'a>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, filter: StaffFilter) -> Result<StaffPage<'a>, Error> {
+		Ok(ctx.list_staff(&filter))
 	}
 }
 
@@ -332,7 +655,7 @@ impl MenuItem for CreateStaff {
 		Some("cs")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
 		let mut first_name = String::new();
 		loop {
 			print!("First name: ");
@@ -413,6 +736,8 @@ impl MenuItem for CreateStaff {
 			println!("Invalid input. Please enter 'm' or 'f' only");
 		}
 
+		let title = Some(read_line("Title (leave it empty if none): ")?).filter(|title| !title.is_empty());
+
 		let dep;
 		loop {
 			input_str.clear();
@@ -432,6 +757,25 @@ impl MenuItem for CreateStaff {
 			println!("Invalid input. Please enter depId or leave it empty");
 		}
 
+		let manager;
+		loop {
+			input_str.clear();
+
+			print!("Manager staff id (leave it empty if none): ");
+			io::stdout().flush().unwrap();
+			io::stdin().read_line(&mut input_str)?;
+			let input = input_str.trim();
+
+			if input.is_empty() {
+				manager = None;
+				break;
+			} else if let Ok(m) = StaffId::try_from(input) {
+				manager = Some(m);
+				break;
+			}
+			println!("Invalid input. Please enter a staff id or leave it empty");
+		}
+
 		let monthly_salary;
 		loop {
 			input_str.clear();
@@ -451,158 +795,1479 @@ impl MenuItem for CreateStaff {
 			println!("Invalid input. Please enter an integer or leave it empty");
 		}
 
-		let builder =
-			StaffBuilder { first_name, last_name, email, dob, doj, gender, department: dep, monthly_salary };
+		let builder = StaffBuilder {
+			first_name,
+			last_name,
+			email,
+			dob,
+			doj,
+			gender,
+			title,
+			department: dep,
+			manager,
+			monthly_salary,
+		};
 
-		self.execute(ctx, MenuItemInput::StaffBuilder(builder)).map(|_| ())
+		self.execute(ctx, builder).map(|_| ())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let MenuItemInput::StaffBuilder(builder) = input else {
-			Err(ApplicationError("Unrecognized input".to_string()))?
-		};
+impl Execute for CreateStaff {
+	type Input = StaffBuilder;
+	type Output<'a> = &'a Staff;
 
-		Ok(ctx.insert_staff(builder).map(MenuItemOutput::Staff)?)
+	fn execute<'a>(&self, ctx: &'a mut Context, builder: StaffBuilder) -> Result<&'a Staff, Error> {
+		ctx.insert_staff(builder)
 	}
 }
 
 /**
- * PrintContext
+ * RetrieveStaff
  **/
-pub struct PrintContext();
+pub struct RetrieveStaff();
 
-impl MenuItem for PrintContext {
+impl MenuItem for RetrieveStaff {
 	fn menuitem_txt(&self) -> &str {
-		"Print application context (debug)"
+		"Retrieve a staff info"
 	}
 
 	fn shortcut(&self) -> Option<&str> {
-		None
+		Some("rs")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		let MenuItemOutput::String(output) = self.execute(ctx, MenuItemInput::None)? else {
-			Err(Box::new(ApplicationError("Unrecognized output".to_string())))?
-		};
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let query = read_line("What is the staff id, first name, last name or email? ")?;
 
-		println!("{output}");
+		let format = *ctx.output_format();
+		let matches = self.execute(ctx, query)?;
+
+		if let Some(staff) = pick_staff(&matches)? {
+			println!("{}", output::render(&MenuItemOutput::Staff(staff), format)?);
+		}
 		Ok(())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		_input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		Ok(MenuItemOutput::String(format!("{:#?}", ctx)))
+impl Execute for RetrieveStaff {
+	// staff id, first name, last name or email
+	type Input = String;
+	type Output<'a> = Vec<StaffMatch<'a>>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, query: String) -> Result<Vec<StaffMatch<'a>>, Error> {
+		Ok(ctx.search_staff(&query))
 	}
 }
 
 /**
- * Save Context
+ * FuzzySearch
  **/
-pub struct SaveContext();
+pub struct FuzzySearch();
 
-impl MenuItem for SaveContext {
+impl MenuItem for FuzzySearch {
 	fn menuitem_txt(&self) -> &str {
-		"Save to a file"
+		"Search staff and departments"
 	}
 
 	fn shortcut(&self) -> Option<&str> {
-		Some("s")
+		Some("f")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		println!("Which file path to save to?");
-		let mut filepath = String::new();
-		io::stdin().read_line(&mut filepath)?;
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let query = read_line("Search for: ")?;
 
-		self.execute(ctx, MenuItemInput::String(filepath.trim().to_string())).map(|_| ())
+		let format = *ctx.output_format();
+		let results = self.execute(ctx, query)?;
+		if results.is_empty() {
+			println!("Nothing found");
+		} else {
+			println!("{}", output::render(&results.into(), format)?);
+		}
+		Ok(())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let MenuItemInput::String(filepath) = input else {
-			Err(Box::new(ApplicationError("Unrecognized input".to_string())))?
-		};
-		let path = Path::new(&filepath);
+impl Execute for FuzzySearch {
+	type Input = String;
+	type Output<'a> = Vec<SearchResult<'a>>;
 
-		let serialized = serde_json::to_string(ctx)?;
-		fs::write(path, serialized).map(|_| MenuItemOutput::None).map_err(|e| e.into())
+	fn execute<'a>(&self, ctx: &'a mut Context, query: String) -> Result<Vec<SearchResult<'a>>, Error> {
+		Ok(ctx.fuzzy_search(&query))
 	}
 }
 
 /**
- * Load Context
+ * UpdateStaff
  **/
-pub struct LoadContext();
+#[derive(Debug)]
+pub struct UpdateStaffInput {
+	pub id: StaffId,
+	pub changes: StaffChanges,
+}
 
-impl MenuItem for LoadContext {
+pub struct UpdateStaff();
+
+impl MenuItem for UpdateStaff {
 	fn menuitem_txt(&self) -> &str {
-		"Load from a file"
+		"Update a staff info"
 	}
 
 	fn shortcut(&self) -> Option<&str> {
-		Some("l")
+		Some("us")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		println!("Which file path to load from?");
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(staff) = read_staff(ctx)? else {
+			return Ok(());
+		};
+		let staff_id = *staff.id();
+
+		println!("Press \"Enter\" to keep the current value.");
+		let parse_string = |s: &str| Ok::<_, &str>(s.to_string());
+		let parse_date = |s: &str| {
+			NaiveDate::parse_from_str(s, "%Y-%m-%d")
+				.map_err(|_| "Please enter a valid date in YYYY-MM-DD format")
+		};
+
+		let mut changes = StaffChanges {
+			first_name: read_change("First name", staff.first_name(), parse_string)?,
+			last_name: read_change("Last name", staff.last_name(), parse_string)?,
+			email: read_change("Email", staff.email(), parse_string)?,
+			dob: read_change("Date of birth", &staff.dob().to_string(), parse_date)?,
+			doj: read_change("Date of joining", &staff.doj().to_string(), parse_date)?,
+			gender: read_change("Gender (m/f)", &format!("{:?}", staff.gender()), |s| Gender::try_from(s))?,
+			title: read_change("Title (\"-\" for none)", staff.title().as_deref().unwrap_or("none"), |s| {
+				Ok::<_, &str>(if s == "-" { None } else { Some(s.to_string()) })
+			})?,
+			department: read_change(
+				"Department (\"-\" for none)",
+				&staff.department().map_or("none".to_string(), |d| d.0.to_string()),
+				|s| if s == "-" { Ok(None) } else { DepartmentId::try_from(s).map(Some) },
+			)?,
+			manager: read_change(
+				"Manager staff id (\"-\" for none)",
+				&staff.manager().map_or("none".to_string(), |m| m.0.to_string()),
+				|s| if s == "-" { Ok(None) } else { StaffId::try_from(s).map(Some) },
+			)?,
+			monthly_salary: read_change(
+				"Monthly salary (\"-\" if not known)",
+				&staff.monthly_salary().map_or("unknown".to_string(), |s| s.to_string()),
+				|s| {
+					if s == "-" {
+						Ok(None)
+					} else {
+						s.parse::<u32>().map(Some).map_err(|_| "Please enter an integer or \"-\"")
+					}
+				},
+			)?,
+			effective: None,
+		};
+		if changes.title.is_some() || changes.department.is_some() || changes.monthly_salary.is_some() {
+			changes.effective =
+				read_change("Effective date of the change (YYYY-MM-DD)", "today", parse_date)?;
+		}
 
-		let mut filepath = String::new();
-		io::stdin().read_line(&mut filepath)?;
+		let staff = self.execute(ctx, UpdateStaffInput { id: staff_id, changes })?;
 
-		self.execute(ctx, MenuItemInput::String(filepath.trim().to_string())).map(|_| ())
+		println!("Updated {}", staff.one_liner());
+		Ok(())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		ctx: &'a mut Context,
-		input: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
-		let MenuItemInput::String(filepath) = input else {
-			Err(Box::new(ApplicationError("Unrecognized input".to_string())))?
+impl Execute for UpdateStaff {
+	type Input = UpdateStaffInput;
+	type Output<'a> = &'a Staff;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, input: UpdateStaffInput) -> Result<&'a Staff, Error> {
+		ctx.update_staff(&input.id, input.changes)
+	}
+}
+
+/**
+ * DeleteStaff
+ **/
+pub struct DeleteStaff();
+
+impl MenuItem for DeleteStaff {
+	fn menuitem_txt(&self) -> &str {
+		"Delete an existing staff"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("ds")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(staff) = read_staff(ctx)? else {
+			return Ok(());
+		};
+		let staff_id = *staff.id();
+
+		let removal = loop {
+			let answer = read_line("(T)erminate the staff, or (d)elete the record permanently? [t]: ")?;
+			match answer.to_lowercase().as_str() {
+				"" | "t" => {
+					let dot = loop {
+						let input =
+							read_line("Date of termination (YYYY-MM-DD, leave it empty for today): ")?;
+						if input.is_empty() {
+							break Local::now().date_naive();
+						}
+						if let Ok(d) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+							break d;
+						}
+						println!("Please enter a valid date in YYYY-MM-DD format");
+					};
+					break StaffRemoval::Terminate(staff_id, dot);
+				}
+				"d" => {
+					let confirm = read_line(&format!(
+						"Type \"yes\" to confirm deleting the record of {}: ",
+						staff.one_liner()
+					))?;
+					if confirm != "yes" {
+						return Ok(());
+					}
+					break StaffRemoval::Delete(staff_id);
+				}
+				_ => println!("Invalid input. Please enter 't' or 'd' only"),
+			}
 		};
 
-		let data_filepath = Path::new(&filepath);
-		let content = fs::read_to_string(data_filepath)?;
+		match self.execute(ctx, removal)? {
+			RemovedStaff::Terminated(staff) => println!("Terminated {}", staff.one_liner()),
+			RemovedStaff::Deleted(staff) => println!("Deleted {}", staff.one_liner()),
+		}
+		Ok(())
+	}
+}
 
-		*ctx = serde_json::from_str::<Context>(&content)?;
+impl Execute for DeleteStaff {
+	type Input = StaffRemoval;
+	type Output<'a> = RemovedStaff<'a>;
 
-		Ok(MenuItemOutput::None)
+	fn execute<'a>(&self, ctx: &'a mut Context, removal: StaffRemoval) -> Result<RemovedStaff<'a>, Error> {
+		match removal {
+			StaffRemoval::Terminate(staff_id, dot) => {
+				ctx.terminate_staff(&staff_id, dot).map(RemovedStaff::Terminated)
+			}
+			StaffRemoval::Delete(staff_id) => ctx.delete_staff(&staff_id).map(RemovedStaff::Deleted),
+		}
 	}
 }
 
 /**
- * Quit
+ * ShowStaffHistory
  **/
-pub struct Quit();
+pub struct ShowStaffHistory();
 
-impl MenuItem for Quit {
+impl MenuItem for ShowStaffHistory {
 	fn menuitem_txt(&self) -> &str {
-		"Quit"
+		"Show the employment history of a staff"
 	}
 
 	fn shortcut(&self) -> Option<&str> {
-		Some("q")
+		Some("sh")
 	}
 
-	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Box<dyn error::Error>> {
-		self.execute(ctx, MenuItemInput::None).map(|_| ())
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(staff) = read_staff(ctx)? else {
+			return Ok(());
+		};
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, *staff.id())?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
 	}
+}
 
-	fn execute<'a>(
-		&self,
-		_: &'a mut Context,
-		_: MenuItemInput,
-	) -> Result<MenuItemOutput<'a>, Box<dyn error::Error>> {
+impl Execute for ShowStaffHistory {
+	type Input = StaffId;
+	type Output<'a> = StaffTimeline<'a>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, staff_id: StaffId) -> Result<StaffTimeline<'a>, Error> {
+		ctx.staff_timeline(&staff_id).ok_or(Error::unknown_staff(staff_id))
+	}
+}
+
+/**
+ * ShowReportingLine
+ **/
+pub struct ShowReportingLine();
+
+impl MenuItem for ShowReportingLine {
+	fn menuitem_txt(&self) -> &str {
+		"Show whom a staff reports to, and who reports to them"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("rl")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(staff) = read_staff(ctx)? else {
+			return Ok(());
+		};
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, *staff.id())?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ShowReportingLine {
+	type Input = StaffId;
+	type Output<'a> = ReportingLine<'a>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, staff_id: StaffId) -> Result<ReportingLine<'a>, Error> {
+		ctx.reporting_line(&staff_id).ok_or(Error::unknown_staff(staff_id))
+	}
+}
+
+/**
+ * ListReportingLines
+ **/
+pub struct ListReportingLines();
+
+impl MenuItem for ListReportingLines {
+	fn menuitem_txt(&self) -> &str {
+		"List reporting lines"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("lr")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		println!("Whose reports do you want to list? Leave it empty for the whole company.");
+		let root = read_staff(ctx)?.map(|staff| *staff.id());
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, root)?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ListReportingLines {
+	// the staff whose reports to list, or else everyone
+	type Input = Option<StaffId>;
+	type Output<'a> = ReportingTree<'a>;
+
+	fn execute<'a>(&self, ctx: &'a mut Context, root: Option<StaffId>) -> Result<ReportingTree<'a>, Error> {
+		ctx.reporting_tree(root)
+	}
+}
+
+/**
+ * ImportCsv
+ **/
+#[derive(Debug)]
+pub struct ImportCsvInput {
+	pub kind: ImportKind,
+	pub file: PathBuf,
+	pub options: ImportOptions,
+}
+
+pub struct ImportCsv();
+
+impl MenuItem for ImportCsv {
+	fn menuitem_txt(&self) -> &str {
+		"Import staff or departments from a CSV file"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("i")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let kind = loop {
+			match read_line("Import (s)taff or (d)epartments? ")?.to_lowercase().as_str() {
+				"s" => break ImportKind::Staff,
+				"d" => break ImportKind::Departments,
+				_ => println!("Invalid input. Please enter 's' or 'd' only"),
+			}
+		};
+		let filepath = read_line("Which CSV file to import from? ")?;
+
+		let mapping = loop {
+			let input = read_line(
+				"Column mapping, e.g. \"first_name=Given Name,email=E-mail\" (leave it empty if the columns are named after the fields): ",
+			)?;
+			match ColumnMapping::try_from(input.as_str()) {
+				Ok(mapping) => break mapping,
+				Err(err) => println!("{err}"),
+			}
+		};
+		let dry_run = read_line("Dry run, without creating anything? (y/N): ")?.eq_ignore_ascii_case("y");
+
+		let format = *ctx.output_format();
+		let options = ImportOptions { mapping, dry_run, ..Default::default() };
+		let output = self.execute(ctx, ImportCsvInput { kind, file: PathBuf::from(filepath), options })?;
+
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ImportCsv {
+	type Input = ImportCsvInput;
+	type Output<'a> = ImportReport;
+
+	fn execute(&self, ctx: &mut Context, input: ImportCsvInput) -> Result<ImportReport, Error> {
+		let file = fs::File::open(input.file)?;
+		import::import_csv(ctx, input.kind, file, &input.options)
+	}
+}
+
+/**
+ * ExportCsv
+ **/
+#[derive(Debug)]
+pub struct ExportCsvInput {
+	pub options: ExportOptions,
+	// the CSV is written to the file if one is given, or else returned as the output
+	pub output: Option<PathBuf>,
+}
+
+pub struct ExportCsv();
+
+impl MenuItem for ExportCsv {
+	fn menuitem_txt(&self) -> &str {
+		"Export staff to a CSV file"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("x")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let department = loop {
+			let input = read_line(
+				"Only export staff of department (id or name, leave it empty to export everyone): ",
+			)?;
+			if input.is_empty() {
+				break None;
+			}
+			match ctx.resolve_department(&input) {
+				Ok(dep_id) => break Some(dep_id),
+				Err(err) => println!("{err}"),
+			}
+		};
+		let active_only = read_line("Only export active staff? (y/N): ")?.eq_ignore_ascii_case("y");
+
+		let columns = loop {
+			let input = read_line(&format!(
+				"Columns, separated by comma (leave it empty for all of: {}): ",
+				ExportColumn::all().iter().map(|c| c.header()).collect::<Vec<_>>().join(", ")
+			))?;
+			if input.is_empty() {
+				break ExportColumn::all();
+			}
+			match input.split(',').map(ExportColumn::try_from).collect::<Result<Vec<_>, _>>() {
+				Ok(columns) => break columns,
+				Err(err) => println!("{err}"),
+			}
+		};
+		let date_format = match read_line("Date format (leave it empty for YYYY-MM-DD): ")? {
+			input if input.is_empty() => ExportOptions::default().date_format,
+			input => input,
+		};
+		let filepath = read_line("Which CSV file to export to? (leave it empty to print the CSV): ")?;
+
+		let options = ExportOptions { department, active_only, columns, date_format };
+		let filepath = Some(PathBuf::from(filepath)).filter(|path| !path.as_os_str().is_empty());
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ExportCsvInput { options, output: filepath })?;
+
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ExportCsv {
+	type Input = ExportCsvInput;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, input: ExportCsvInput) -> Result<String, Error> {
+		let ExportCsvInput { options, output } = input;
+
+		if let Some(dep_id) = options.department {
+			ctx.department(&dep_id).ok_or(Error::unknown_department(dep_id))?;
+		}
+		let content = export::export_staff_csv(ctx, &options)?;
+
+		match output {
+			Some(filepath) => {
+				fs::write(&filepath, content)?;
+				Ok(format!("Staff exported to {}", filepath.display()))
+			}
+			None => Ok(content.trim_end().to_string()),
+		}
+	}
+}
+
+/**
+ * PrintContext
+ **/
+pub struct PrintContext();
+
+impl MenuItem for PrintContext {
+	fn menuitem_txt(&self) -> &str {
+		"Print application context (debug)"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		None
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		println!("{}", self.execute(ctx, ())?);
+		Ok(())
+	}
+}
+
+impl Execute for PrintContext {
+	type Input = ();
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, _: ()) -> Result<String, Error> {
+		Ok(format!("{:#?}", ctx))
+	}
+}
+
+/**
+ * OrgChart
+ **/
+#[derive(Debug)]
+pub struct OrgChartInput {
+	pub options: OrgChartOptions,
+	// the chart is written to the file if one is given, or else returned as the output
+	pub output: Option<PathBuf>,
+}
+
+pub struct OrgChart();
+
+impl MenuItem for OrgChart {
+	fn menuitem_txt(&self) -> &str {
+		"Export the department hierarchy as a chart"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("oc")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let syntax = loop {
+			match read_line("(D)OT for Graphviz, or (m)ermaid? [d]: ")?.to_lowercase().as_str() {
+				"" | "d" => break ChartSyntax::Dot,
+				"m" => break ChartSyntax::Mermaid,
+				_ => println!("Invalid input. Please enter 'd' or 'm' only"),
+			}
+		};
+		let root = loop {
+			let input =
+				read_line("Only chart department (id or name, leave it empty for the whole hierarchy): ")?;
+			if input.is_empty() {
+				break None;
+			}
+			match ctx.resolve_department(&input) {
+				Ok(dep_id) => break Some(dep_id),
+				Err(err) => println!("{err}"),
+			}
+		};
+		let depth = loop {
+			let input = read_line("Levels of sub-departments to chart (leave it empty for all): ")?;
+			if input.is_empty() {
+				break None;
+			}
+			match input.parse::<usize>() {
+				Ok(depth) => break Some(depth),
+				Err(_) => println!("Please enter a number"),
+			}
+		};
+		let staff = read_line("List the staff in each department? (y/N): ")?.eq_ignore_ascii_case("y");
+		let heads = read_line("Name the head of each department? (y/N): ")?.eq_ignore_ascii_case("y");
+		let filepath = read_line("Which file to export to? (leave it empty to print the chart): ")?;
+
+		let options = OrgChartOptions { syntax, root, depth, staff, heads };
+		let filepath = Some(PathBuf::from(filepath)).filter(|path| !path.as_os_str().is_empty());
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, OrgChartInput { options, output: filepath })?;
+
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for OrgChart {
+	type Input = OrgChartInput;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, input: OrgChartInput) -> Result<String, Error> {
+		let content = org_chart::org_chart(ctx, &input.options)?;
+		match input.output {
+			Some(filepath) => {
+				fs::write(&filepath, content)?;
+				Ok(format!("Chart exported to {}", filepath.display()))
+			}
+			None => Ok(content.trim_end().to_string()),
+		}
+	}
+}
+
+/**
+ * ListWorkspaces
+ **/
+pub struct ListWorkspaces();
+
+impl MenuItem for ListWorkspaces {
+	fn menuitem_txt(&self) -> &str {
+		"List workspaces"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("lw")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ())?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for ListWorkspaces {
+	type Input = ();
+	type Output<'a> = Vec<WorkspaceSummary>;
+
+	fn execute(&self, ctx: &mut Context, _: ()) -> Result<Vec<WorkspaceSummary>, Error> {
+		Ok(ctx.workspace_summaries())
+	}
+}
+
+/**
+ * CreateWorkspace
+ **/
+pub struct CreateWorkspace();
+
+impl MenuItem for CreateWorkspace {
+	fn menuitem_txt(&self) -> &str {
+		"Create a workspace for another company"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("cw")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let name = read_line("Name of the workspace (leave it empty to cancel): ")?;
+		if name.is_empty() {
+			return Ok(());
+		}
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, name)?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for CreateWorkspace {
+	// name of the workspace
+	type Input = String;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, name: String) -> Result<String, Error> {
+		ctx.create_workspace(&name)?;
+		Ok(format!("Created workspace {}", name.trim()))
+	}
+}
+
+/**
+ * SwitchWorkspace
+ **/
+pub struct SwitchWorkspace();
+
+impl MenuItem for SwitchWorkspace {
+	fn menuitem_txt(&self) -> &str {
+		"Switch to another workspace"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("sw")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(name) = read_workspace(ctx)? else {
+			return Ok(());
+		};
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, name)?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for SwitchWorkspace {
+	// name of the workspace
+	type Input = String;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, name: String) -> Result<String, Error> {
+		ctx.switch_workspace(&name)?;
+		let message = match ctx.company_name().as_str() {
+			"" => format!("Switched to workspace {name}"),
+			company => format!("Switched to workspace {name} ({company})"),
+		};
+		Ok(message)
+	}
+}
+
+/**
+ * RenameWorkspace
+ **/
+#[derive(Debug)]
+pub struct RenameWorkspaceInput {
+	pub name: String,
+	pub new_name: String,
+}
+
+pub struct RenameWorkspace();
+
+impl MenuItem for RenameWorkspace {
+	fn menuitem_txt(&self) -> &str {
+		"Rename a workspace"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("rw")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(name) = read_workspace(ctx)? else {
+			return Ok(());
+		};
+		let new_name = read_line("New name (leave it empty to cancel): ")?;
+		if new_name.is_empty() {
+			return Ok(());
+		}
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, RenameWorkspaceInput { name, new_name })?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for RenameWorkspace {
+	type Input = RenameWorkspaceInput;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, input: RenameWorkspaceInput) -> Result<String, Error> {
+		let RenameWorkspaceInput { name, new_name } = input;
+
+		ctx.rename_workspace(&name, &new_name)?;
+		Ok(format!("Renamed workspace {name} to {}", new_name.trim()))
+	}
+}
+
+/**
+ * DeleteWorkspace
+ **/
+pub struct DeleteWorkspace();
+
+impl MenuItem for DeleteWorkspace {
+	fn menuitem_txt(&self) -> &str {
+		"Delete a workspace"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("dw")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(name) = read_workspace(ctx)? else {
+			return Ok(());
+		};
+		let confirm = read_line(&format!(
+			"Workspace {name} and all of its departments and staff will be deleted, which cannot be undone. Type \"yes\" to confirm: "
+		))?;
+		if confirm != "yes" {
+			return Ok(());
+		}
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, name)?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for DeleteWorkspace {
+	// name of the workspace
+	type Input = String;
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, name: String) -> Result<String, Error> {
+		ctx.delete_workspace(&name)?;
+		Ok(format!("Deleted workspace {name}"))
+	}
+}
+
+/**
+ * MoveStaff
+ **/
+#[derive(Debug)]
+pub struct MoveStaffInput {
+	pub ids: Vec<StaffId>,
+	pub workspace: String,
+	// the department in the workspace to move the staff to, if any
+	pub department: Option<DepartmentId>,
+}
+
+pub struct MoveStaff();
+
+impl MenuItem for MoveStaff {
+	fn menuitem_txt(&self) -> &str {
+		"Move a staff to another workspace"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("ms")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(staff) = read_staff(ctx)? else {
+			return Ok(());
+		};
+		let Some(workspace) = read_workspace(ctx)? else {
+			return Ok(());
+		};
+		let department = loop {
+			let input = read_line(&format!("Department id in {workspace} (leave it empty for none): "))?;
+			if input.is_empty() {
+				break None;
+			}
+			match DepartmentId::try_from(input.as_str()) {
+				Ok(dep_id) => break Some(dep_id),
+				Err(err) => println!("{err}"),
+			}
+		};
+
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, MoveStaffInput { ids: vec![*staff.id()], workspace, department })?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for MoveStaff {
+	type Input = MoveStaffInput;
+	type Output<'a> = Vec<MovedStaff>;
+
+	fn execute(&self, ctx: &mut Context, input: MoveStaffInput) -> Result<Vec<MovedStaff>, Error> {
+		ctx.move_staff(&input.ids, &input.workspace, input.department)
+	}
+}
+
+/**
+ * QueryAuditLog
+ **/
+pub struct QueryAuditLog();
+
+impl MenuItem for QueryAuditLog {
+	fn menuitem_txt(&self) -> &str {
+		"Query the audit log"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("a")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let entity = loop {
+			let input = read_line(
+				"Entity: (s)taff, (d)epartment, (c)ompany, (w)orkspace (leave it empty for all): ",
+			)?;
+			match input.to_lowercase().as_str() {
+				"" => break None,
+				"s" => break Some(AuditEntity::Staff),
+				"d" => break Some(AuditEntity::Department),
+				"c" => break Some(AuditEntity::Company),
+				"w" => break Some(AuditEntity::Workspace),
+				_ => println!("Invalid input. Please enter 's', 'd', 'c' or 'w' only"),
+			}
+		};
+		let id = match entity {
+			Some(AuditEntity::Staff | AuditEntity::Department) => loop {
+				let input = read_line("Id (leave it empty for all): ")?;
+				if input.is_empty() {
+					break None;
+				}
+				match input.parse::<u32>() {
+					Ok(id) => break Some(id),
+					Err(_) => println!("Please enter a number"),
+				}
+			},
+			_ => None,
+		};
+		let read_date = |prompt: &str| -> io::Result<Option<NaiveDate>> {
+			loop {
+				let input = read_line(prompt)?;
+				if input.is_empty() {
+					return Ok(None);
+				}
+				match NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+					Ok(date) => return Ok(Some(date)),
+					Err(_) => println!("Please enter a valid date in YYYY-MM-DD format"),
+				}
+			}
+		};
+		let from = read_date("From date (YYYY-MM-DD, leave it empty for the beginning): ")?;
+		let to = read_date("To date (YYYY-MM-DD, leave it empty for today): ")?;
+
+		let format = *ctx.output_format();
+		let entries = self.execute(ctx, AuditQuery { workspace: None, entity, id, from, to })?;
+		if entries.is_empty() {
+			println!("No changes found");
+		} else {
+			println!("{}", output::render(&entries.into(), format)?);
+		}
+		Ok(())
+	}
+}
+
+impl Execute for QueryAuditLog {
+	type Input = AuditQuery;
+	type Output<'a> = Vec<AuditEntry>;
+
+	fn execute(&self, ctx: &mut Context, query: AuditQuery) -> Result<Vec<AuditEntry>, Error> {
+		let Some(log) = ctx.audit_log() else { Err(PersistenceError::NoAuditLog)? };
+
+		audit::query(log.path(), &query)
+	}
+}
+
+/**
+ * Undo
+ **/
+pub struct Undo();
+
+impl MenuItem for Undo {
+	fn menuitem_txt(&self) -> &str {
+		"Undo the last change"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("u")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ())?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for Undo {
+	type Input = ();
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, _: ()) -> Result<String, Error> {
+		let message = match ctx.undo() {
+			Some(summary) => format!("Undone: {summary}"),
+			None => "Nothing to undo".to_string(),
+		};
+		Ok(message)
+	}
+}
+
+/**
+ * Redo
+ **/
+pub struct Redo();
+
+impl MenuItem for Redo {
+	fn menuitem_txt(&self) -> &str {
+		"Redo the last undone change"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("r")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let format = *ctx.output_format();
+		let output = self.execute(ctx, ())?;
+		println!("{}", output::render(&output.into(), format)?);
+		Ok(())
+	}
+}
+
+impl Execute for Redo {
+	type Input = ();
+	type Output<'a> = String;
+
+	fn execute(&self, ctx: &mut Context, _: ()) -> Result<String, Error> {
+		let message = match ctx.redo() {
+			Some(summary) => format!("Redone: {summary}"),
+			None => "Nothing to redo".to_string(),
+		};
+		Ok(message)
+	}
+}
+
+/**
+ * Save Context
+ **/
+pub struct SaveContext();
+
+impl MenuItem for SaveContext {
+	fn menuitem_txt(&self) -> &str {
+		"Save"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("s")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let Some(data_file) = ctx.data_file().clone() else {
+			return SaveContextAs().execute_interactive(ctx);
+		};
+
+		self.execute(ctx, ())?;
+		println!("Saved to {}", data_file.display());
+		Ok(())
+	}
+}
+
+impl Execute for SaveContext {
+	type Input = ();
+	type Output<'a> = ();
+
+	fn execute(&self, ctx: &mut Context, _: ()) -> Result<(), Error> {
+		let Some(data_file) = ctx.data_file() else { Err(PersistenceError::NoDataFile)? };
+
+		// the context was loaded from, or last saved to, this file, so only the changes need writing
+		storage::open(*ctx.storage(), data_file)?.save_changes(ctx)?;
+		ctx.flush_audit_log()?;
+		ctx.mark_saved();
+		Ok(())
+	}
+}
+
+impl SaveContext {
+	/// Save the changes made since `snapshot` was taken, with their audit log entries held back since. If
+	/// they can't be saved, the context goes back to `snapshot` and the entries are dropped, so that the
+	/// context never holds changes the data file doesn't.
+	pub fn execute_or_roll_back(&self, ctx: &mut Context, snapshot: Context) -> Result<(), Error> {
+		if ctx.is_dirty() {
+			let Some(data_file) = ctx.data_file() else { Err(PersistenceError::NoDataFile)? };
+			let saved =
+				storage::open(*ctx.storage(), data_file).and_then(|mut storage| storage.save_changes(ctx));
+			if let Err(err) = saved {
+				ctx.roll_back(snapshot);
+				return Err(err);
+			}
+			ctx.mark_saved();
+		}
+		ctx.release_audit_log()?;
+		Ok(())
+	}
+}
+
+/**
+ * Save Context As
+ **/
+pub struct SaveContextAs();
+
+impl MenuItem for SaveContextAs {
+	fn menuitem_txt(&self) -> &str {
+		"Save as a new file"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("sa")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let filepath = read_line("Which file path to save to? (leave it empty to cancel): ")?;
+		if filepath.is_empty() {
+			return Ok(());
+		}
+
+		self.execute(ctx, PathBuf::from(&filepath))?;
+		println!("Saved to {filepath}");
+		Ok(())
+	}
+}
+
+impl Execute for SaveContextAs {
+	type Input = PathBuf;
+	type Output<'a> = ();
+
+	/// Save the whole directory to the file, which becomes the file "Save" writes to
+	fn execute(&self, ctx: &mut Context, path: PathBuf) -> Result<(), Error> {
+		storage::open(*ctx.storage(), &path)?.save(ctx)?;
+		ctx.flush_audit_log()?;
+		ctx.set_data_file(Some(path));
+		ctx.mark_saved();
+		Ok(())
+	}
+}
+
+/**
+ * Load Context
+ **/
+pub struct LoadContext();
+
+impl MenuItem for LoadContext {
+	fn menuitem_txt(&self) -> &str {
+		"Load from a file"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("l")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		if ctx.is_dirty() {
+			let confirm = read_line("There are unsaved changes, which will be lost. Continue? (y/N): ")?;
+			if !confirm.eq_ignore_ascii_case("y") {
+				return Ok(());
+			}
+		}
+
+		let filepath = read_line("Which file path to load from? ")?;
+		self.execute(ctx, PathBuf::from(filepath))
+	}
+}
+
+impl Execute for LoadContext {
+	type Input = PathBuf;
+	type Output<'a> = ();
+
+	fn execute(&self, ctx: &mut Context, path: PathBuf) -> Result<(), Error> {
+		// opening an SQLite database that doesn't exist would create an empty one
+		if !path.exists() {
+			Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())))?
+		}
+
+		let loaded = storage::open(*ctx.storage(), &path)?.load()?;
+		ctx.replace_directory(loaded, path);
+		Ok(())
+	}
+}
+
+/**
+ * ListBackups
+ **/
+pub struct ListBackups();
+
+impl MenuItem for ListBackups {
+	fn menuitem_txt(&self) -> &str {
+		"List backups of a data file"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("lb")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let filepath = read_line("Which data file? ")?;
+		let format = *ctx.output_format();
+		let backups = self.execute(ctx, PathBuf::from(filepath))?;
+
+		if backups.is_empty() {
+			println!("No backups found");
+		} else {
+			println!("{}", output::render(&backups.into(), format)?);
+		}
+		Ok(())
+	}
+}
+
+impl Execute for ListBackups {
+	// the data file
+	type Input = PathBuf;
+	type Output<'a> = Vec<Backup>;
+
+	fn execute(&self, _: &mut Context, data_file: PathBuf) -> Result<Vec<Backup>, Error> {
+		Ok(persistence::list_backups(&data_file)?)
+	}
+}
+
+/**
+ * RestoreBackup
+ **/
+#[derive(Debug)]
+pub struct RestoreBackupInput {
+	pub data_file: PathBuf,
+	pub backup: BackupRef,
+}
+
+/// A backup, by its number in `ListBackups`, 1 being the latest, or by its path
+#[derive(Debug, PartialEq, Eq)]
+pub enum BackupRef {
+	Number(usize),
+	Path(PathBuf),
+}
+
+impl From<&str> for BackupRef {
+	fn from(backup: &str) -> Self {
+		match backup.parse::<usize>() {
+			Ok(number) => BackupRef::Number(number),
+			Err(_) => BackupRef::Path(PathBuf::from(backup)),
+		}
+	}
+}
+
+pub struct RestoreBackup();
+
+impl MenuItem for RestoreBackup {
+	fn menuitem_txt(&self) -> &str {
+		"Restore a data file from a backup"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("rb")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		let filepath = read_line("Which data file? ")?;
+		let backups = persistence::list_backups(Path::new(&filepath))?;
+		if backups.is_empty() {
+			println!("No backups found");
+			return Ok(());
+		}
+		for (idx, backup) in backups.iter().enumerate() {
+			println!("{}. saved at {}", idx + 1, backup.saved_at.format("%Y-%m-%d %H:%M:%S"));
+		}
+
+		let number = loop {
+			let input = read_line("Which backup to restore? (leave it empty to cancel): ")?;
+			if input.is_empty() {
+				return Ok(());
+			}
+			match input.parse::<usize>() {
+				Ok(n) if (1..=backups.len()).contains(&n) => break n,
+				_ => println!("Please enter a number between 1 and {}", backups.len()),
+			}
+		};
+		let confirm = read_line(&format!(
+			"{filepath} will be replaced, and the directory loaded from the backup. Continue? (y/N): "
+		))?;
+		if !confirm.eq_ignore_ascii_case("y") {
+			return Ok(());
+		}
+
+		let input =
+			RestoreBackupInput { data_file: PathBuf::from(filepath), backup: BackupRef::Number(number) };
+		println!("{}", self.execute(ctx, input)?);
+		Ok(())
+	}
+}
+
+impl Execute for RestoreBackup {
+	type Input = RestoreBackupInput;
+	type Output<'a> = String;
+
+	/// The restored directory is also loaded into the context
+	fn execute(&self, ctx: &mut Context, input: RestoreBackupInput) -> Result<String, Error> {
+		if *ctx.storage() != StorageKind::Json {
+			Err(PersistenceError::BackupsUnsupported)?
+		}
+		let filepath = input.data_file.as_path();
+
+		let backup = match input.backup {
+			BackupRef::Number(number) => {
+				persistence::list_backups(filepath)?
+					.into_iter()
+					.nth(number.wrapping_sub(1))
+					.ok_or_else(|| Error::NotFound { entity: Entity::Backup, id: number.to_string() })?
+					.path
+			}
+			BackupRef::Path(path) => path,
+		};
+
+		let restored = persistence::restore_backup(filepath, &backup)?;
+		let message = format!("Restored {} from {}", filepath.display(), backup.display());
+		ctx.replace_directory(restored, input.data_file);
+		Ok(message)
+	}
+}
+
+/**
+ * Quit
+ **/
+pub struct Quit();
+
+impl MenuItem for Quit {
+	fn menuitem_txt(&self) -> &str {
+		"Quit"
+	}
+
+	fn shortcut(&self) -> Option<&str> {
+		Some("q")
+	}
+
+	fn execute_interactive(&self, ctx: &mut Context) -> Result<(), Error> {
+		if ctx.is_dirty() {
+			loop {
+				match read_line("There are unsaved changes. (S)ave, (d)iscard them, or (c)ancel? ")?
+					.to_lowercase()
+					.as_str()
+				{
+					"s" => {
+						SaveContext().execute_interactive(ctx)?;
+						// saving as a new file can be cancelled
+						if ctx.is_dirty() {
+							return Ok(());
+						}
+						break;
+					}
+					"d" => break,
+					"c" => return Ok(()),
+					_ => println!("Invalid input. Please enter 's', 'd' or 'c' only"),
+				}
+			}
+		}
+
+		self.execute(ctx, ())
+	}
+}
+
+impl Execute for Quit {
+	type Input = ();
+	type Output<'a> = ();
+
+	fn execute(&self, _: &mut Context, _: ()) -> Result<(), Error> {
 		std::process::exit(0);
 	}
 }
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn read_line(prompt: &str) -> Result<String, io::Error> {
+	print!("{prompt}");
+	io::stdout().flush()?;
+
+	let mut input = String::new();
+	io::stdin().read_line(&mut input)?;
+	Ok(input.trim().to_string())
+}
+
+/// Ask for a department id, and look the department up. Returns `None` when the user enters nothing.
+fn read_department(ctx: &Context) -> Result<Option<Department>, Error> {
+	loop {
+		let input = read_line("Department id (leave it empty to cancel): ")?;
+		if input.is_empty() {
+			return Ok(None);
+		}
+
+		match DepartmentId::try_from(input.as_str()).map(|id| ctx.department(&id)) {
+			Ok(Some(dep)) => {
+				println!("{}", dep.one_liner());
+				return Ok(Some(dep.clone()));
+			}
+			Ok(None) => println!("No department with id {input}"),
+			Err(err) => println!("{err}"),
+		}
+	}
+}
+
+/// Ask for a staff id, name or email, and let the user pick one staff among the matches. Returns `None`
+/// when the user enters nothing.
+fn read_staff(ctx: &Context) -> Result<Option<Staff>, Error> {
+	loop {
+		let query =
+			read_line("What is the staff id, first name, last name or email? (leave it empty to cancel): ")?;
+		if query.is_empty() {
+			return Ok(None);
+		}
+
+		let matches = ctx.search_staff(&query);
+		if let Some(staff) = pick_staff(&matches)? {
+			return Ok(Some(staff.clone()));
+		}
+	}
+}
+
+/// Ask for the name of a workspace other than the current one. Returns `None` when the user enters
+/// nothing.
+fn read_workspace(ctx: &Context) -> Result<Option<String>, io::Error> {
+	let others = ctx.workspaces().into_keys().filter(|name| name != ctx.workspace()).collect::<Vec<_>>();
+	if others.is_empty() {
+		println!("There is no other workspace");
+		return Ok(None);
+	}
+	println!("Workspaces: {}", others.join(", "));
+
+	loop {
+		let name = read_line("Which workspace? (leave it empty to cancel): ")?;
+		if name.is_empty() {
+			return Ok(None);
+		}
+		if others.contains(&name.as_str()) {
+			return Ok(Some(name));
+		}
+		println!("No workspace named {name}");
+	}
+}
+
+/// Let the user pick one staff among search matches. Returns `None` when there is no match, or the user
+/// picks none of them.
+fn pick_staff<'a>(matches: &[StaffMatch<'a>]) -> Result<Option<&'a Staff>, io::Error> {
+	match matches {
+		[] => {
+			println!("No staff found");
+			Ok(None)
+		}
+		[only] => {
+			println!("Found {}", only.staff.one_liner());
+			Ok(Some(only.staff))
+		}
+		_ => {
+			println!("Found the following, which one do you mean?");
+			for (idx, m) in matches.iter().enumerate() {
+				println!("{}. {}", idx + 1, m.staff.one_liner());
+			}
+
+			loop {
+				let input = read_line("? (leave it empty for none of them): ")?;
+				if input.is_empty() {
+					return Ok(None);
+				}
+				match input.parse::<usize>().ok().and_then(|choice| choice.checked_sub(1)) {
+					Some(choice) if choice < matches.len() => return Ok(Some(matches[choice].staff)),
+					_ => println!("Invalid choice"),
+				}
+			}
+		}
+	}
+}
+
+/// Prompt for a new value of a field. Returns `None` when the user keeps the current value.
+fn read_change<T, E: fmt::Display>(
+	field: &str,
+	current: &str,
+	parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<Option<T>, io::Error> {
+	loop {
+		let input = read_line(&format!("{field} [{current}]: "))?;
+		if input.is_empty() {
+			return Ok(None);
+		}
+		match parse(&input) {
+			Ok(value) => return Ok(Some(value)),
+			Err(err) => println!("{err}"),
+		}
+	}
+}
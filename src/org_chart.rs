@@ -0,0 +1,158 @@
+use clap::ValueEnum;
+
+use crate::{
+	context::Context,
+	department::{DepartmentId, DepartmentNode, InfoScope},
+	errors::Error,
+	staff::StaffFilter,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChartSyntax {
+	/// Graphviz DOT, e.g. for `dot -Tsvg`
+	#[default]
+	Dot,
+	/// A Mermaid flowchart
+	Mermaid,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OrgChartOptions {
+	pub syntax: ChartSyntax,
+	// only chart this department and its sub-departments
+	pub root: Option<DepartmentId>,
+	// levels of sub-departments charted below the top departments. The headcount of a department whose
+	// sub-departments are left out includes theirs.
+	pub depth: Option<usize>,
+	// list the active staff in each department
+	pub staff: bool,
+	// name the head of each department
+	pub heads: bool,
+}
+
+/// The department hierarchy as a chart in the syntax of the options, with a trailing newline
+pub fn org_chart(ctx: &Context, options: &OrgChartOptions) -> Result<String, Error> {
+	let tree = ctx.department_tree();
+	let roots = match options.root {
+		Some(dep_id) => {
+			vec![find_node(&tree.roots, dep_id).ok_or(Error::unknown_department(dep_id))?]
+		}
+		None => tree.roots.iter().collect(),
+	};
+
+	let mut nodes = vec![];
+	for root in roots {
+		chart_nodes(ctx, options, root, None, 0, &mut nodes);
+	}
+
+	Ok(match options.syntax {
+		ChartSyntax::Dot => dot(ctx.company_name(), &nodes),
+		ChartSyntax::Mermaid => mermaid(ctx.company_name(), &nodes),
+	})
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+/// A department in the chart, with the lines of its label
+struct ChartNode {
+	id: DepartmentId,
+	parent: Option<DepartmentId>,
+	label: Vec<String>,
+}
+
+fn find_node<'t, 'a>(
+	nodes: &'t [DepartmentNode<'a>],
+	dep_id: DepartmentId,
+) -> Option<&'t DepartmentNode<'a>> {
+	nodes.iter().find_map(|node| {
+		if *node.department.id() == dep_id { Some(node) } else { find_node(&node.children, dep_id) }
+	})
+}
+
+fn chart_nodes(
+	ctx: &Context,
+	options: &OrgChartOptions,
+	node: &DepartmentNode,
+	parent: Option<DepartmentId>,
+	depth: usize,
+	nodes: &mut Vec<ChartNode>,
+) {
+	let dep = node.department;
+	let folded = !node.children.is_empty() && options.depth.is_some_and(|max_depth| depth >= max_depth);
+	let scope = if folded { InfoScope::RolledUp } else { InfoScope::Direct };
+	let headcount = ctx.department_info(dep.id(), scope).map_or(0, |info| info.headcount);
+
+	let mut label =
+		vec![if *dep.active() { dep.name().clone() } else { format!("{} (inactive)", dep.name()) }];
+	label.push(if folded {
+		format!("{headcount} staff incl. sub-departments")
+	} else {
+		format!("{headcount} staff")
+	});
+	if options.heads {
+		if let Some(head) = dep.head().and_then(|staff_id| ctx.staff_member(&staff_id)) {
+			label.push(format!("Head: {} {}", head.first_name(), head.last_name()));
+		}
+	}
+	if options.staff {
+		let filter = StaffFilter { department: Some(*dep.id()), active: Some(true), ..Default::default() };
+		label.extend(
+			ctx.list_staff(&filter).staff.iter().map(|s| format!("{} {}", s.first_name(), s.last_name())),
+		);
+	}
+	nodes.push(ChartNode { id: *dep.id(), parent, label });
+
+	if !folded {
+		for child in &node.children {
+			chart_nodes(ctx, options, child, Some(*dep.id()), depth + 1, nodes);
+		}
+	}
+}
+
+fn dot(company_name: &str, nodes: &[ChartNode]) -> String {
+	let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+
+	let mut lines = vec!["digraph org_chart {".to_string()];
+	if !company_name.is_empty() {
+		lines.push(format!("\tlabel=\"{}\";", escape(company_name)));
+		lines.push("\tlabelloc=t;".to_string());
+	}
+	lines.push("\tnode [shape=box];".to_string());
+	for node in nodes {
+		let label = node.label.iter().map(|line| escape(line)).collect::<Vec<_>>().join("\\n");
+		lines.push(format!("\td{} [label=\"{label}\"];", node.id.0));
+	}
+	for node in nodes {
+		if let Some(parent) = node.parent {
+			lines.push(format!("\td{} -> d{};", parent.0, node.id.0));
+		}
+	}
+	lines.push("}".to_string());
+
+	lines.join("\n") + "\n"
+}
+
+fn mermaid(company_name: &str, nodes: &[ChartNode]) -> String {
+	// entity codes, as quotes end a label and angle brackets are read as HTML
+	let escape = |s: &str| s.replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;");
+
+	let mut lines = vec![];
+	if !company_name.is_empty() {
+		// a JSON string is a valid YAML one, whatever the name holds
+		let title = serde_json::to_string(company_name).unwrap_or_default();
+		lines.extend(["---".to_string(), format!("title: {title}"), "---".to_string()]);
+	}
+	lines.push("flowchart TD".to_string());
+	for node in nodes {
+		let label = node.label.iter().map(|line| escape(line)).collect::<Vec<_>>().join("<br>");
+		lines.push(format!("\td{}[\"{label}\"]", node.id.0));
+	}
+	for node in nodes {
+		if let Some(parent) = node.parent {
+			lines.push(format!("\td{} --> d{}", parent.0, node.id.0));
+		}
+	}
+
+	lines.join("\n") + "\n"
+}
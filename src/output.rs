@@ -0,0 +1,249 @@
+use crate::{
+	audit::AuditEntry,
+	department::{Department, DepartmentInfo},
+	errors::Error,
+	import::ImportRow,
+	menu_items::MenuItemOutput,
+	persistence::Backup,
+	search::SearchEntity,
+	staff::{ReportingLine, Staff, TimelineEntry},
+	traits::{OneLiner, Tabular},
+	workspace::{MovedStaff, WorkspaceSummary},
+};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+	/// Human readable text
+	#[default]
+	Text,
+	/// JSON, for `jq` and other programs
+	Json,
+	/// CSV with a header row, for spreadsheets
+	Csv,
+	/// Text table with aligned columns
+	Table,
+}
+
+/// Render the output of a menu item in the given format. The result has no trailing newline.
+pub fn render(output: &MenuItemOutput, format: OutputFormat) -> Result<String, Error> {
+	match format {
+		OutputFormat::Text => Ok(text(output)),
+		OutputFormat::Json => Ok(serde_json::to_string_pretty(output)?),
+		OutputFormat::Csv => {
+			let (headers, rows) = rows(output);
+			csv(&headers, &rows)
+		}
+		OutputFormat::Table => {
+			let (headers, rows) = rows(output);
+			Ok(table(&headers, &rows))
+		}
+	}
+}
+
+pub fn csv<S: AsRef<str>>(headers: &[S], rows: &[Vec<String>]) -> Result<String, Error> {
+	if headers.is_empty() {
+		return Ok("".to_string());
+	}
+
+	let mut writer = csv::Writer::from_writer(vec![]);
+	writer.write_record(headers.iter().map(|h| h.as_ref()))?;
+	for row in rows {
+		writer.write_record(row)?;
+	}
+
+	let content = String::from_utf8(writer.into_inner().map_err(|err| Error::from(err.into_error()))?)?;
+	Ok(content.trim_end().to_string())
+}
+
+pub fn table<S: AsRef<str>>(headers: &[S], rows: &[Vec<String>]) -> String {
+	if headers.is_empty() {
+		return "".to_string();
+	}
+
+	let mut widths = headers.iter().map(|h| h.as_ref().chars().count()).collect::<Vec<_>>();
+	for row in rows {
+		for (width, cell) in widths.iter_mut().zip(row) {
+			*width = (*width).max(cell.chars().count());
+		}
+	}
+
+	let line = |cells: Vec<&str>| {
+		cells
+			.iter()
+			.zip(&widths)
+			.map(|(cell, width)| format!("{cell:width$}"))
+			.collect::<Vec<_>>()
+			.join(" | ")
+	};
+
+	let mut lines = vec![
+		line(headers.iter().map(|h| h.as_ref()).collect()),
+		widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-"),
+	];
+	lines.extend(rows.iter().map(|row| line(row.iter().map(|c| c.as_str()).collect())));
+
+	lines.iter().map(|l| l.trim_end()).collect::<Vec<_>>().join("\n")
+}
+
+fn text(output: &MenuItemOutput) -> String {
+	let lines = match output {
+		MenuItemOutput::String(s) => vec![s.clone()],
+		MenuItemOutput::Department(dep) => vec![dep.one_liner()],
+		MenuItemOutput::DepartmentTree(tree) => vec![tree.to_string()],
+		MenuItemOutput::Departments(deps) => deps.iter().map(|d| d.one_liner()).collect(),
+		MenuItemOutput::DeletedDepartments(deps) => deps.iter().map(|d| d.one_liner()).collect(),
+		MenuItemOutput::Staff(staff) => vec![staff.to_string()],
+		MenuItemOutput::DeletedStaff(staff) => vec![staff.to_string()],
+		MenuItemOutput::StaffMatches(matches) => matches.iter().map(|m| m.staff.one_liner()).collect(),
+		MenuItemOutput::SearchResults(results) => results
+			.iter()
+			.map(|r| {
+				let line = match r.entity {
+					SearchEntity::Staff(staff) => staff.one_liner(),
+					SearchEntity::Department(dep) => dep.one_liner(),
+				};
+				format!("{line} [{}%]", r.score)
+			})
+			.collect(),
+		MenuItemOutput::ImportReport(report) => vec![report.to_string()],
+		MenuItemOutput::DepartmentInfo(info) => vec![info.to_string()],
+		MenuItemOutput::StaffPage(page) => page.staff.iter().map(|s| s.one_liner()).collect(),
+		MenuItemOutput::Backups(backups) => backups
+			.iter()
+			.enumerate()
+			.map(|(idx, b)| {
+				format!(
+					"{}. saved at {}: {}",
+					idx + 1,
+					b.saved_at.format("%Y-%m-%d %H:%M:%S"),
+					b.path.display()
+				)
+			})
+			.collect(),
+		MenuItemOutput::AuditEntries(entries) => entries.iter().map(|e| e.to_string()).collect(),
+		MenuItemOutput::Workspaces(workspaces) => workspaces
+			.iter()
+			.map(|w| {
+				format!(
+					"{} {}: {} ({} departments, {} staff)",
+					if w.current { "*" } else { " " },
+					w.name,
+					w.company_name,
+					w.departments,
+					w.staff
+				)
+			})
+			.collect(),
+		MenuItemOutput::MovedStaff(moved) => {
+			moved.iter().map(|m| format!("Moved staff {}, now with id {}", m.from.0, m.to.0)).collect()
+		}
+		MenuItemOutput::ReportingLine(line) => vec![line.to_string()],
+		MenuItemOutput::ReportingTree(tree) => vec![tree.to_string()],
+		MenuItemOutput::StaffTimeline(timeline) => vec![timeline.to_string()],
+		MenuItemOutput::None => vec![],
+	};
+
+	lines.join("\n")
+}
+
+/// The output laid out as a header row and data rows
+fn rows(output: &MenuItemOutput) -> (Vec<&'static str>, Vec<Vec<String>>) {
+	match output {
+		MenuItemOutput::String(s) => (vec!["message"], vec![vec![s.clone()]]),
+		MenuItemOutput::Department(dep) => (Department::headers(), vec![dep.row()]),
+		MenuItemOutput::DepartmentTree(tree) => {
+			let mut headers = Department::headers();
+			headers.push("depth");
+			let rows = tree
+				.flatten()
+				.into_iter()
+				.map(|(depth, dep)| {
+					let mut row = dep.row();
+					row.push(depth.to_string());
+					row
+				})
+				.collect();
+			(headers, rows)
+		}
+		MenuItemOutput::Departments(deps) => (Department::headers(), deps.iter().map(|d| d.row()).collect()),
+		MenuItemOutput::DeletedDepartments(deps) => {
+			(Department::headers(), deps.iter().map(|d| d.row()).collect())
+		}
+		MenuItemOutput::Staff(staff) => (Staff::headers(), vec![staff.row()]),
+		MenuItemOutput::DeletedStaff(staff) => (Staff::headers(), vec![staff.row()]),
+		MenuItemOutput::StaffMatches(matches) => {
+			let mut headers = Staff::headers();
+			headers.extend(["match_kind", "match_field"]);
+			let rows = matches
+				.iter()
+				.map(|m| {
+					let mut row = m.staff.row();
+					row.extend([format!("{:?}", m.kind).to_lowercase(), m.field.to_string()]);
+					row
+				})
+				.collect();
+			(headers, rows)
+		}
+		MenuItemOutput::SearchResults(results) => {
+			let rows = results
+				.iter()
+				.map(|r| {
+					let (kind, id, name) = match r.entity {
+						SearchEntity::Staff(staff) => {
+							("staff", staff.id().0, format!("{} {}", staff.first_name(), staff.last_name()))
+						}
+						SearchEntity::Department(dep) => ("department", dep.id().0, dep.name().clone()),
+					};
+					vec![kind.to_string(), id.to_string(), name, r.score.to_string()]
+				})
+				.collect();
+			(vec!["type", "id", "name", "score"], rows)
+		}
+		MenuItemOutput::ImportReport(report) => {
+			(ImportRow::headers(), report.rows.iter().map(|r| r.row()).collect())
+		}
+		MenuItemOutput::DepartmentInfo(info) => (DepartmentInfo::headers(), vec![info.row()]),
+		MenuItemOutput::StaffPage(page) => (Staff::headers(), page.staff.iter().map(|s| s.row()).collect()),
+		MenuItemOutput::Backups(backups) => {
+			let mut headers = vec!["number"];
+			headers.extend(Backup::headers());
+			let rows = backups
+				.iter()
+				.enumerate()
+				.map(|(idx, b)| {
+					let mut row = vec![(idx + 1).to_string()];
+					row.extend(b.row());
+					row
+				})
+				.collect();
+			(headers, rows)
+		}
+		MenuItemOutput::AuditEntries(entries) => {
+			(AuditEntry::headers(), entries.iter().map(|e| e.row()).collect())
+		}
+		MenuItemOutput::Workspaces(workspaces) => {
+			(WorkspaceSummary::headers(), workspaces.iter().map(|w| w.row()).collect())
+		}
+		MenuItemOutput::MovedStaff(moved) => (MovedStaff::headers(), moved.iter().map(|m| m.row()).collect()),
+		MenuItemOutput::ReportingLine(line) => (ReportingLine::headers(), vec![line.row()]),
+		MenuItemOutput::ReportingTree(tree) => {
+			let mut headers = Staff::headers();
+			headers.push("depth");
+			let rows = tree
+				.flatten()
+				.into_iter()
+				.map(|(depth, staff)| {
+					let mut row = staff.row();
+					row.push(depth.to_string());
+					row
+				})
+				.collect();
+			(headers, rows)
+		}
+		MenuItemOutput::StaffTimeline(timeline) => {
+			(TimelineEntry::headers(), timeline.entries.iter().map(|e| e.row()).collect())
+		}
+		MenuItemOutput::None => (vec![], vec![]),
+	}
+}
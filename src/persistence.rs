@@ -0,0 +1,336 @@
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	context::Context,
+	errors::{DataFileError, Error},
+	traits::Tabular,
+	workspace::DEFAULT_WORKSPACE,
+};
+
+/// Upgrades the fields of a data file by one version
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/**
+ * `MIGRATIONS[n]` upgrades a data file from version `n` to `n + 1`. Files saved before the format
+ * was versioned have no `format_version` field and are version 0.
+ *
+ * To change the format, append a migration here rather than editing an existing one: files already
+ * saved in every older version have to keep loading.
+ **/
+const MIGRATIONS: &[Migration] = &[v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4];
+
+/// Version of the data files written by this build
+pub const FORMAT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// How many backups of a data file are kept, the oldest being removed first
+pub const BACKUPS_KEPT: usize = 5;
+
+// timestamp in backup file names, which sorts in chronological order
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%6f";
+
+#[derive(Serialize)]
+struct VersionedContext<'a> {
+	format_version: u32,
+	// the workspace worked on when the file is loaded
+	workspace: &'a str,
+	workspaces: BTreeMap<&'a str, &'a Context>,
+}
+
+impl<'a> VersionedContext<'a> {
+	fn new(ctx: &'a Context) -> Self {
+		Self { format_version: FORMAT_VERSION, workspace: ctx.workspace(), workspaces: ctx.workspaces() }
+	}
+}
+
+#[derive(Deserialize)]
+struct Workspaces {
+	workspace: String,
+	workspaces: BTreeMap<String, Context>,
+}
+
+/**
+ * Deserializes a field that is present into `Some`. Along with `#[serde(default)]`, a missing field
+ * stays `None` while an explicit `null` becomes `Some(None)`, as change sets need.
+ **/
+pub(crate) fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+	T: Deserialize<'de>,
+	D: Deserializer<'de>,
+{
+	Option::<T>::deserialize(deserializer).map(Some)
+}
+
+pub fn to_json(ctx: &Context) -> Result<String, Error> {
+	Ok(serde_json::to_string(&VersionedContext::new(ctx))?)
+}
+
+/// The context in the current format, including its `format_version`
+pub fn to_value(ctx: &Context) -> Result<Value, Error> {
+	Ok(serde_json::to_value(VersionedContext::new(ctx))?)
+}
+
+/// Parse a data file of any version up to `FORMAT_VERSION`, migrating it to the current format
+pub fn from_json(content: &str) -> Result<Context, Error> {
+	from_value(
+		serde_json::from_str::<Value>(content).map_err(|err| DataFileError::Malformed(err.to_string()))?,
+	)
+}
+
+pub fn from_value(mut value: Value) -> Result<Context, Error> {
+	let mut migrated = false;
+	if let Some(fields) = value.as_object_mut() {
+		let version = match fields.remove("format_version") {
+			None => 0,
+			Some(version) => {
+				version.as_u64().and_then(|v| u32::try_from(v).ok()).ok_or(DataFileError::InvalidVersion)?
+			}
+		};
+		if version > FORMAT_VERSION {
+			Err(DataFileError::UnsupportedVersion { found: version, supported: FORMAT_VERSION })?
+		}
+
+		for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+			migrate(fields).map_err(|reason| DataFileError::Migration { from: from as u32, reason })?;
+		}
+		migrated = version < FORMAT_VERSION;
+	}
+
+	let Workspaces { workspace, workspaces } = serde_json::from_value::<Workspaces>(value)
+		.map_err(|err| DataFileError::Malformed(err.to_string()))?;
+	let mut ctx =
+		Context::from_workspaces(&workspace, workspaces).ok_or(DataFileError::MissingWorkspace(workspace))?;
+	if migrated {
+		ctx.mark_migrated();
+	}
+	Ok(ctx)
+}
+
+pub fn load(path: &Path) -> Result<Context, Error> {
+	from_json(&fs::read_to_string(path)?)
+}
+
+/**
+ * Save the context without ever leaving a partially written data file behind: the content goes to a
+ * temporary file in the same directory, is flushed to disk, and then renamed over the data file.
+ *
+ * The data file being replaced is first copied to a timestamped backup next to it, and only the latest
+ * `BACKUPS_KEPT` backups are kept.
+ **/
+pub fn save(ctx: &Context, path: &Path) -> Result<(), Error> {
+	let content = to_json(ctx)?;
+	if path.exists() {
+		backup(path)?;
+	}
+	write_atomically(path, content.as_bytes())?;
+	Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Backup {
+	pub path: PathBuf,
+	pub saved_at: NaiveDateTime,
+}
+
+impl Tabular for Backup {
+	fn headers() -> Vec<&'static str> {
+		vec!["saved_at", "path"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![self.saved_at.format("%Y-%m-%d %H:%M:%S").to_string(), self.path.display().to_string()]
+	}
+}
+
+/// Backups of a data file, the latest first
+pub fn list_backups(path: &Path) -> io::Result<Vec<Backup>> {
+	let (dir, file_name) = split_path(path)?;
+	let prefix = format!("{file_name}.");
+
+	let mut backups = fs::read_dir(dir)?
+		.filter_map(|entry| {
+			let path = entry.ok()?.path();
+			let stamp = path.file_name()?.to_str()?.strip_prefix(&prefix)?.strip_suffix(".bak")?;
+			let saved_at = NaiveDateTime::parse_from_str(stamp, BACKUP_TIMESTAMP_FORMAT).ok()?;
+			Some(Backup { path, saved_at })
+		})
+		.collect::<Vec<_>>();
+
+	backups.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+	Ok(backups)
+}
+
+/// Replace the data file with one of its backups. The data file being replaced is backed up in
+/// turn, so a restore can itself be undone.
+pub fn restore_backup(path: &Path, backup: &Path) -> Result<Context, Error> {
+	// loading first makes sure a corrupted backup never replaces the data file
+	let ctx = load(backup)?;
+	save(&ctx, path)?;
+	Ok(ctx)
+}
+
+/// Unversioned files: fill in `enforce_email_domain` and the `active` flags, which older builds
+/// didn't write, and convert staff records laid out as in the original README (string ids, `salary`,
+/// `ranking` and `age`).
+fn v0_to_v1(fields: &mut Map<String, Value>) -> Result<(), String> {
+	fields.entry("enforce_email_domain").or_insert(Value::Bool(true));
+	for key in ["next_department_id", "next_staff_id"] {
+		numeric_id(fields, key)?;
+	}
+
+	for dep in array_of_objects(fields, "departments")? {
+		numeric_id(dep, "id")?;
+		numeric_id(dep, "parent")?;
+		dep.entry("active").or_insert(Value::Bool(true));
+	}
+
+	for staff in array_of_objects(fields, "staff")? {
+		numeric_id(staff, "id")?;
+		numeric_id(staff, "department")?;
+		if let Some(salary) = staff.remove("salary") {
+			staff.entry("monthly_salary").or_insert(salary);
+		}
+		staff.remove("ranking");
+		staff.remove("age");
+		staff.entry("dot").or_insert(Value::Null);
+		staff.entry("active").or_insert(Value::Bool(true));
+	}
+
+	Ok(())
+}
+
+/// Files holding the directory of a single company: it becomes the default workspace
+fn v1_to_v2(fields: &mut Map<String, Value>) -> Result<(), String> {
+	let directory = std::mem::take(fields);
+	fields.insert("workspace".to_string(), Value::from(DEFAULT_WORKSPACE));
+	fields.insert(
+		"workspaces".to_string(),
+		Value::Object(Map::from_iter([(DEFAULT_WORKSPACE.to_string(), Value::Object(directory))])),
+	);
+	Ok(())
+}
+
+/// Reporting lines: no staff has a manager yet, and no department a head
+fn v2_to_v3(fields: &mut Map<String, Value>) -> Result<(), String> {
+	for directory in directories(fields)? {
+		for dep in array_of_objects(directory, "departments")? {
+			dep.entry("head").or_insert(Value::Null);
+		}
+		for staff in array_of_objects(directory, "staff")? {
+			staff.entry("manager").or_insert(Value::Null);
+		}
+	}
+	Ok(())
+}
+
+/// Employment history: what each staff has now is taken as what they joined with, and none has a title
+fn v3_to_v4(fields: &mut Map<String, Value>) -> Result<(), String> {
+	for directory in directories(fields)? {
+		for staff in array_of_objects(directory, "staff")? {
+			staff.entry("title").or_insert(Value::Null);
+			if staff.contains_key("employment_history") {
+				continue;
+			}
+
+			let doj = staff.get("doj").cloned().unwrap_or(Value::Null);
+			let history = ["department", "monthly_salary"]
+				.into_iter()
+				.filter_map(|key| {
+					let value = staff.get(key).filter(|value| !value.is_null())?;
+					let change = Map::from_iter([(key.to_string(), value.clone())]);
+					Some(Value::Object(Map::from_iter([
+						("effective".to_string(), doj.clone()),
+						("change".to_string(), Value::Object(change)),
+					])))
+				})
+				.collect();
+			staff.insert("employment_history".to_string(), Value::Array(history));
+		}
+	}
+	Ok(())
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn split_path(path: &Path) -> io::Result<(&Path, &str)> {
+	let file_name = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid data file path"))?;
+	let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+	Ok((dir, file_name))
+}
+
+fn backup(path: &Path) -> io::Result<()> {
+	let (dir, file_name) = split_path(path)?;
+	let stamp = Local::now().naive_local().format(BACKUP_TIMESTAMP_FORMAT);
+	fs::copy(path, dir.join(format!("{file_name}.{stamp}.bak")))?;
+
+	for stale in list_backups(path)?.iter().skip(BACKUPS_KEPT) {
+		fs::remove_file(&stale.path)?;
+	}
+	Ok(())
+}
+
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+	let (dir, file_name) = split_path(path)?;
+	let tmp_path = dir.join(format!(".{file_name}.{}.tmp", std::process::id()));
+
+	let result = File::create(&tmp_path).and_then(|mut file| {
+		file.write_all(content)?;
+		file.sync_all()?;
+		fs::rename(&tmp_path, path)
+	});
+	if result.is_err() {
+		let _ = fs::remove_file(&tmp_path);
+	}
+	result?;
+
+	// make the rename itself durable. Directories can't be opened as files on every platform, in
+	// which case the rename is as durable as the platform makes it.
+	if let Ok(dir) = File::open(dir) {
+		let _ = dir.sync_all();
+	}
+	Ok(())
+}
+
+/// The directory of each workspace, in files with workspaces
+fn directories(
+	fields: &mut Map<String, Value>,
+) -> Result<impl Iterator<Item = &mut Map<String, Value>>, String> {
+	let directories = match fields.get_mut("workspaces") {
+		Some(Value::Object(workspaces)) => workspaces.values_mut(),
+		Some(_) => return Err("\"workspaces\" is not an object".to_string()),
+		None => return Ok(None.into_iter().flatten()),
+	};
+	Ok(Some(directories.filter_map(|directory| directory.as_object_mut())).into_iter().flatten())
+}
+
+fn array_of_objects<'a>(
+	fields: &'a mut Map<String, Value>,
+	key: &str,
+) -> Result<impl Iterator<Item = &'a mut Map<String, Value>>, String> {
+	let items = match fields.get_mut(key) {
+		Some(Value::Array(items)) => items.iter_mut(),
+		Some(_) => return Err(format!("\"{key}\" is not a list")),
+		None => [].iter_mut(),
+	};
+	Ok(items.filter_map(|item| item.as_object_mut()))
+}
+
+/// Convert an id saved as a zero-padded string, e.g. "007", to a number
+fn numeric_id(fields: &mut Map<String, Value>, key: &str) -> Result<(), String> {
+	if let Some(Value::String(id)) = fields.get(key) {
+		let id = id.parse::<u32>().map_err(|_| format!("invalid {key} \"{id}\""))?;
+		fields.insert(key.to_string(), Value::from(id));
+	}
+	Ok(())
+}
@@ -0,0 +1,247 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+use crate::{
+	context::Context,
+	department::{DepartmentBuilder, DepartmentChanges, DepartmentId, InfoScope, RemovalPolicy},
+	errors::Error,
+	menu_items::{
+		CreateDepartment, CreateStaff, CreateWorkspace, DeleteStaff, DeleteWorkspace, Execute, FuzzySearch,
+		Invocation, ListDepartments, ListReportingLines, ListStaff, ListWorkspaces, MoveStaff,
+		MoveStaffInput, NameCompany, NameCompanyInput, Redo, RemoveDepartment, RemoveDepartmentInput,
+		RenameWorkspace, RenameWorkspaceInput, RetrieveStaff, SaveContext, ShowDepartment,
+		ShowDepartmentInput, ShowReportingLine, ShowStaffHistory, SwitchWorkspace, Undo, UpdateDepartment,
+		UpdateDepartmentInput, UpdateStaff, UpdateStaffInput,
+	},
+	staff::{StaffBuilder, StaffChanges, StaffFilter, StaffId, StaffRemoval},
+};
+
+/**
+ * A request read from a line of input, e.g.
+ *
+ * {"id": 1, "op": "update_staff", "params": {"id": 3, "changes": {"monthly_salary": 4000}}}
+ *
+ * `id` is any JSON value, echoed back in the response so that clients can match the two up.
+ * `params` is left out for the operations without any, e.g. `{"op": "undo"}`, and is `{}` for an
+ * operation whose parameters are all optional, e.g. `{"op": "list_staff", "params": {}}`.
+ **/
+#[derive(Debug, Deserialize)]
+pub struct Request {
+	#[serde(default)]
+	pub id: Value,
+	#[serde(flatten)]
+	pub operation: Operation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", content = "params", rename_all = "snake_case")]
+pub enum Operation {
+	SetCompany {
+		name: String,
+		domain: String,
+		enforce_email_domain: Option<bool>,
+	},
+	DepartmentTree,
+	CreateDepartment(DepartmentBuilder),
+	UpdateDepartment {
+		id: DepartmentId,
+		changes: DepartmentChanges,
+	},
+	RemoveDepartment {
+		id: DepartmentId,
+		#[serde(default)]
+		policy: RemovalPolicy,
+	},
+	DepartmentInfo {
+		id: DepartmentId,
+		#[serde(default)]
+		scope: InfoScope,
+		as_of: Option<NaiveDate>,
+	},
+	ListStaff(StaffFilter),
+	CreateStaff(StaffBuilder),
+	// staff by id, first name, last name or email
+	FindStaff {
+		query: String,
+	},
+	// staff and departments by approximate name
+	Search {
+		query: String,
+	},
+	UpdateStaff {
+		id: StaffId,
+		changes: StaffChanges,
+	},
+	DeleteStaff {
+		id: StaffId,
+	},
+	TerminateStaff {
+		id: StaffId,
+		date: NaiveDate,
+	},
+	StaffHistory {
+		id: StaffId,
+	},
+	ReportingLine {
+		id: StaffId,
+	},
+	ReportingTree {
+		root: Option<StaffId>,
+	},
+	MoveStaff {
+		ids: Vec<StaffId>,
+		workspace: String,
+		department: Option<DepartmentId>,
+	},
+	ListWorkspaces,
+	CreateWorkspace {
+		name: String,
+	},
+	SwitchWorkspace {
+		name: String,
+	},
+	RenameWorkspace {
+		name: String,
+		new_name: String,
+	},
+	DeleteWorkspace {
+		name: String,
+	},
+	Undo,
+	Redo,
+}
+
+impl Operation {
+	/// Whether the operation may change the context
+	fn mutates(&self) -> bool {
+		!matches!(
+			self,
+			Operation::DepartmentTree
+				| Operation::DepartmentInfo { .. }
+				| Operation::ListStaff(_)
+				| Operation::FindStaff { .. }
+				| Operation::Search { .. }
+				| Operation::StaffHistory { .. }
+				| Operation::ReportingLine { .. }
+				| Operation::ReportingTree { .. }
+				| Operation::ListWorkspaces
+		)
+	}
+
+	/// The menu item carrying out the operation, bound to its input
+	fn into_invocation(self) -> Box<dyn Invocation> {
+		match self {
+			Operation::SetCompany { name, domain, enforce_email_domain } => {
+				NameCompany::new().bind(NameCompanyInput { name, domain: Some(domain), enforce_email_domain })
+			}
+			Operation::DepartmentTree => ListDepartments::new().bind(()),
+			Operation::CreateDepartment(builder) => CreateDepartment::new().bind(builder),
+			Operation::UpdateDepartment { id, changes } => {
+				UpdateDepartment().bind(UpdateDepartmentInput { id, changes })
+			}
+			Operation::RemoveDepartment { id, policy } => {
+				RemoveDepartment().bind(RemoveDepartmentInput { id, policy })
+			}
+			Operation::DepartmentInfo { id, scope, as_of } => {
+				ShowDepartment().bind(ShowDepartmentInput { id, scope, as_of })
+			}
+			Operation::ListStaff(filter) => ListStaff().bind(filter),
+			Operation::CreateStaff(builder) => CreateStaff().bind(builder),
+			Operation::FindStaff { query } => RetrieveStaff().bind(query),
+			Operation::Search { query } => FuzzySearch().bind(query),
+			Operation::UpdateStaff { id, changes } => UpdateStaff().bind(UpdateStaffInput { id, changes }),
+			Operation::DeleteStaff { id } => DeleteStaff().bind(StaffRemoval::Delete(id)),
+			Operation::TerminateStaff { id, date } => DeleteStaff().bind(StaffRemoval::Terminate(id, date)),
+			Operation::StaffHistory { id } => ShowStaffHistory().bind(id),
+			Operation::ReportingLine { id } => ShowReportingLine().bind(id),
+			Operation::ReportingTree { root } => ListReportingLines().bind(root),
+			Operation::MoveStaff { ids, workspace, department } => {
+				MoveStaff().bind(MoveStaffInput { ids, workspace, department })
+			}
+			Operation::ListWorkspaces => ListWorkspaces().bind(()),
+			Operation::CreateWorkspace { name } => CreateWorkspace().bind(name),
+			Operation::SwitchWorkspace { name } => SwitchWorkspace().bind(name),
+			Operation::RenameWorkspace { name, new_name } => {
+				RenameWorkspace().bind(RenameWorkspaceInput { name, new_name })
+			}
+			Operation::DeleteWorkspace { name } => DeleteWorkspace().bind(name),
+			Operation::Undo => Undo().bind(()),
+			Operation::Redo => Redo().bind(()),
+		}
+	}
+}
+
+/**
+ * Written as a line of output for every request: `{"id": 1, "result": ...}` with the output of the
+ * operation as `--format json` prints it, or `{"id": 1, "error": {"code": ..., "message": ...}}` with
+ * the field at fault, if any, as `field`
+ **/
+#[derive(Debug, Serialize)]
+pub struct Response {
+	pub id: Value,
+	#[serde(flatten)]
+	pub outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+	Result(Value),
+	Error(Error),
+}
+
+/**
+ * Answer each line of `input` with a line of `output`, until the input ends. Blank lines are skipped.
+ * Changes are saved to the data file of `ctx`, if it has one, before they are answered. A change that
+ * can't be saved is rolled back.
+ **/
+pub fn run(ctx: &mut Context, input: impl BufRead, mut output: impl Write) -> Result<(), Error> {
+	for line in input.lines() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let response = handle(ctx, &line);
+		writeln!(output, "{}", serde_json::to_string(&response)?)?;
+		output.flush()?;
+	}
+	Ok(())
+}
+
+/// Answer a single request line
+pub fn handle(ctx: &mut Context, line: &str) -> Response {
+	let request = match serde_json::from_str::<Request>(line) {
+		Ok(request) => request,
+		Err(err) => {
+			// echo the id back if the request is JSON with an id at least
+			let id = serde_json::from_str::<Value>(line).ok().and_then(|v| v.get("id").cloned());
+			return Response { id: id.unwrap_or_default(), outcome: Outcome::Error(err.into()) };
+		}
+	};
+
+	let outcome = match execute(ctx, request.operation) {
+		Ok(result) => Outcome::Result(result),
+		Err(err) => Outcome::Error(err),
+	};
+	Response { id: request.id, outcome }
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn execute(ctx: &mut Context, operation: Operation) -> Result<Value, Error> {
+	// reads change nothing to roll back
+	let snapshot = (operation.mutates() && ctx.data_file().is_some()).then(|| ctx.clone());
+	if snapshot.is_some() {
+		ctx.hold_audit_log();
+	}
+
+	let result = operation.into_invocation().invoke(ctx).and_then(|output| Ok(serde_json::to_value(output)?));
+	if let Some(snapshot) = snapshot {
+		SaveContext().execute_or_roll_back(ctx, snapshot)?;
+	}
+	result
+}
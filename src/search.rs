@@ -0,0 +1,194 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+	department::{Department, DepartmentId},
+	staff::{Staff, StaffId},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SearchKey {
+	Staff(StaffId),
+	Department(DepartmentId),
+}
+
+/**
+ * Typo-tolerant, in-memory index over staff and department names.
+ *
+ * Each entry is split into lowercase terms, and every term is broken into trigrams. A query
+ * first collects the entries sharing a trigram with it, then ranks them by edit distance.
+ **/
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+	terms: HashMap<SearchKey, Vec<String>>,
+	trigrams: HashMap<String, HashSet<SearchKey>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchHit {
+	pub key: SearchKey,
+	// 0 - 100, where 100 is an exact match on every query term
+	pub score: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchEntity<'a> {
+	Staff(&'a Staff),
+	Department(&'a Department),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult<'a> {
+	pub entity: SearchEntity<'a>,
+	// 0 - 100, higher is closer
+	pub score: u32,
+}
+
+impl SearchIndex {
+	// entries scoring below this are not considered a match
+	const MIN_SCORE: u32 = 60;
+
+	pub fn new() -> Self {
+		Self { terms: HashMap::new(), trigrams: HashMap::new() }
+	}
+
+	pub fn build<'a>(
+		departments: impl IntoIterator<Item = &'a Department>,
+		staff: impl IntoIterator<Item = &'a Staff>,
+	) -> Self {
+		let mut index = Self::new();
+		departments.into_iter().for_each(|dep| index.upsert_department(dep));
+		staff.into_iter().for_each(|s| index.upsert_staff(s));
+		index
+	}
+
+	pub fn len(&self) -> usize {
+		self.terms.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.terms.is_empty()
+	}
+
+	pub fn upsert_staff(&mut self, staff: &Staff) {
+		let mut terms = tokenize(staff.first_name());
+		terms.extend(tokenize(staff.last_name()));
+		// the local part of the email is often "first.last", so index its pieces as well
+		terms.extend(tokenize(staff.email().split('@').next().unwrap_or_default()));
+
+		self.upsert(SearchKey::Staff(*staff.id()), terms);
+	}
+
+	pub fn upsert_department(&mut self, dep: &Department) {
+		self.upsert(SearchKey::Department(*dep.id()), tokenize(dep.name()));
+	}
+
+	pub fn remove(&mut self, key: &SearchKey) {
+		let Some(terms) = self.terms.remove(key) else {
+			return;
+		};
+
+		for trigram in terms.iter().flat_map(|t| trigrams(t)) {
+			if let Some(keys) = self.trigrams.get_mut(&trigram) {
+				keys.remove(key);
+				if keys.is_empty() {
+					self.trigrams.remove(&trigram);
+				}
+			}
+		}
+	}
+
+	/// Entries matching the query, best first
+	pub fn search(&self, query: &str) -> Vec<SearchHit> {
+		let query_terms = tokenize(query);
+		if query_terms.is_empty() {
+			return vec![];
+		}
+
+		let candidates = query_terms
+			.iter()
+			.flat_map(|t| trigrams(t))
+			.filter_map(|trigram| self.trigrams.get(&trigram))
+			.flatten()
+			.collect::<HashSet<_>>();
+
+		let mut hits = candidates
+			.into_iter()
+			.filter_map(|key| {
+				let terms = &self.terms[key];
+				// every query term is scored against its closest term in the entry
+				let total = query_terms
+					.iter()
+					.map(|q| terms.iter().map(|t| similarity(q, t)).max().unwrap_or(0))
+					.sum::<u32>();
+				let score = total / query_terms.len() as u32;
+
+				(score >= Self::MIN_SCORE).then_some(SearchHit { key: *key, score })
+			})
+			.collect::<Vec<_>>();
+
+		hits.sort_by_key(|hit| (std::cmp::Reverse(hit.score), hit.key));
+		hits
+	}
+
+	fn upsert(&mut self, key: SearchKey, terms: Vec<String>) {
+		self.remove(&key);
+
+		for trigram in terms.iter().flat_map(|t| trigrams(t)) {
+			self.trigrams.entry(trigram).or_default().insert(key);
+		}
+		self.terms.insert(key, terms);
+	}
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+	text.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).map(|t| t.to_lowercase()).collect()
+}
+
+/// Trigrams of a term, padded so that short terms and word boundaries get trigrams too
+fn trigrams(term: &str) -> Vec<String> {
+	let chars = format!("  {term} ").chars().collect::<Vec<_>>();
+	chars.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+/// How similar a query term is to an indexed term, from 0 to 100
+fn similarity(query: &str, term: &str) -> u32 {
+	if query == term {
+		return 100;
+	}
+	if term.starts_with(query) {
+		return 90;
+	}
+
+	let max_len = query.chars().count().max(term.chars().count()) as u32;
+	let distance = edit_distance(query, term) as u32;
+	100 * max_len.saturating_sub(distance) / max_len
+}
+
+/// Levenshtein distance, counting the transposition of two adjacent characters as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+	let a = a.chars().collect::<Vec<_>>();
+	let b = b.chars().collect::<Vec<_>>();
+
+	// d[i][j] is the distance between the first i chars of a and the first j chars of b
+	let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in d.iter_mut().enumerate() {
+		row[0] = i;
+	}
+	for j in 0..=b.len() {
+		d[0][j] = j;
+	}
+
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+			d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+			}
+		}
+	}
+
+	d[a.len()][b.len()]
+}
@@ -0,0 +1,257 @@
+use chrono::NaiveDate;
+use serde_json::json;
+use std::{collections::HashMap, io, net::SocketAddr, path::Path, str::FromStr};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+	audit::AuditLog,
+	context::Context,
+	department::{DepartmentId, InfoScope, RemovalPolicy},
+	errors::{Entity, Error},
+	menu_items::{
+		CreateDepartment, CreateStaff, DeleteStaff, Execute, Invocation, ListDepartments, ListStaff,
+		MenuItemOutput, RemoveDepartment, RemoveDepartmentInput, SaveContext, ShowDepartment,
+		ShowDepartmentInput, UpdateDepartment, UpdateDepartmentInput, UpdateStaff, UpdateStaffInput,
+	},
+	output::{self, OutputFormat},
+	staff::{Paging, StaffFilter, StaffId, StaffRemoval},
+	storage::{self, StorageKind},
+};
+
+/**
+ * Serves a context over a local HTTP JSON API:
+ *
+ * GET    /staff                   list staff. Takes the filters of `staff list` as query parameters.
+ * POST   /staff                   create a staff from a JSON body
+ * GET    /staff/{id}              a staff
+ * PATCH  /staff/{id}              change a staff. Fields set to `null` are cleared.
+ * DELETE /staff/{id}              delete a staff, or terminate them with `?terminated_on=DATE`
+ * GET    /departments             list departments
+ * POST   /departments             create a department from a JSON body
+ * GET    /departments/tree        the department hierarchy
+ * GET    /departments/{id}        a department
+ * PATCH  /departments/{id}        change a department. Fields set to `null` are cleared.
+ * DELETE /departments/{id}        remove a department. `?policy=refuse|cascade|reassign_to_parent`
+ * GET    /departments/{id}/info   headcount and payroll. `?scope=direct|rolled_up&as_of=DATE`
+ *
+ * Requests are handled one at a time, so writes never interleave. Every change is saved to the data
+ * file of the context, if it has one, before the response is sent. A change that can't be saved is
+ * rolled back.
+ **/
+pub struct ApiServer {
+	http: Server,
+	ctx: Context,
+}
+
+#[derive(Debug)]
+pub struct ApiResponse {
+	pub status: u16,
+	pub body: String,
+}
+
+impl ApiResponse {
+	fn error(err: &Error) -> Self {
+		Self { status: status_code(err), body: json!({ "error": err }).to_string() }
+	}
+}
+
+impl ApiServer {
+	/// Listen on `addr`, e.g. "127.0.0.1:8080". Port 0 picks a free port, see `local_addr()`.
+	pub fn bind(addr: &str, ctx: Context) -> Result<Self, Error> {
+		let http = Server::http(addr).map_err(|err| Error::Io {
+			kind: io::ErrorKind::AddrNotAvailable,
+			message: format!("cannot listen on {addr}: {err}"),
+		})?;
+		Ok(Self { http, ctx })
+	}
+
+	pub fn local_addr(&self) -> Option<SocketAddr> {
+		self.http.server_addr().to_ip()
+	}
+
+	/// Serve requests until the process is stopped
+	pub fn run(&mut self) -> Result<(), Error> {
+		loop {
+			let mut request = self.http.recv()?;
+			let mut body = String::new();
+			let response = match request.as_reader().read_to_string(&mut body) {
+				Ok(_) => self.handle(request.method(), request.url(), &body),
+				Err(err) => ApiResponse::error(&Error::parse("request body", err)),
+			};
+
+			// a client hanging up doesn't concern the other clients
+			if let Err(err) = respond(request, response) {
+				eprintln!("{err}");
+			}
+		}
+	}
+
+	/// Handle one request, saving the context if it changed. If it can't be saved, the change is rolled
+	/// back, so that the context never holds changes the data file doesn't.
+	pub fn handle(&mut self, method: &Method, url: &str, body: &str) -> ApiResponse {
+		let (path, query) = url.split_once('?').unwrap_or((url, ""));
+		let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+		let query = query.split('&').filter_map(|pair| pair.split_once('=')).collect::<HashMap<_, _>>();
+		// reads change nothing to roll back
+		let snapshot = (*method != Method::Get && self.ctx.data_file().is_some()).then(|| self.ctx.clone());
+		if snapshot.is_some() {
+			self.ctx.hold_audit_log();
+		}
+
+		let routed = self.route(method, &segments, &query, body);
+		if let Some(snapshot) = snapshot {
+			if let Err(err) = SaveContext().execute_or_roll_back(&mut self.ctx, snapshot) {
+				return ApiResponse::error(&err);
+			}
+		}
+		match routed {
+			Ok(Some(response)) => response,
+			Ok(None) => {
+				let route = Error::NotFound { entity: Entity::Route, id: format!("{method} {path}") };
+				ApiResponse::error(&route)
+			}
+			Err(err) => ApiResponse::error(&err),
+		}
+	}
+
+	fn route(
+		&mut self,
+		method: &Method,
+		segments: &[&str],
+		query: &HashMap<&str, &str>,
+		body: &str,
+	) -> Result<Option<ApiResponse>, Error> {
+		let (invocation, status): (Box<dyn Invocation>, u16) = match (method, segments) {
+			(Method::Get, ["staff"]) => (ListStaff().bind(staff_filter(query)?), 200),
+			(Method::Post, ["staff"]) => (CreateStaff().bind(serde_json::from_str(body)?), 201),
+			(Method::Get, ["staff", id]) => {
+				let staff_id = StaffId(parse("staff id", id)?);
+				let staff = self.ctx.staff_member(&staff_id).ok_or(Error::unknown_staff(staff_id))?;
+				return Ok(Some(render(&MenuItemOutput::Staff(staff), 200)?));
+			}
+			(Method::Patch, ["staff", id]) => {
+				let changes = serde_json::from_str(body)?;
+				(UpdateStaff().bind(UpdateStaffInput { id: StaffId(parse("staff id", id)?), changes }), 200)
+			}
+			(Method::Delete, ["staff", id]) => {
+				let staff_id = StaffId(parse("staff id", id)?);
+				let removal = match query.get("terminated_on") {
+					Some(date) => StaffRemoval::Terminate(staff_id, parse("date", date)?),
+					None => StaffRemoval::Delete(staff_id),
+				};
+				(DeleteStaff().bind(removal), 200)
+			}
+			(Method::Get, ["departments"]) => {
+				let deps = self.ctx.departments().iter().collect();
+				return Ok(Some(render(&MenuItemOutput::Departments(deps), 200)?));
+			}
+			(Method::Post, ["departments"]) => {
+				(CreateDepartment::new().bind(serde_json::from_str(body)?), 201)
+			}
+			(Method::Get, ["departments", "tree"]) => (ListDepartments::new().bind(()), 200),
+			(Method::Get, ["departments", id]) => {
+				let dep_id = DepartmentId(parse("department id", id)?);
+				let dep = self.ctx.department(&dep_id).ok_or(Error::unknown_department(dep_id))?;
+				return Ok(Some(render(&MenuItemOutput::Department(dep), 200)?));
+			}
+			(Method::Patch, ["departments", id]) => {
+				let changes = serde_json::from_str(body)?;
+				let dep_id = DepartmentId(parse("department id", id)?);
+				(UpdateDepartment().bind(UpdateDepartmentInput { id: dep_id, changes }), 200)
+			}
+			(Method::Delete, ["departments", id]) => {
+				let policy = match query.get("policy").copied() {
+					None | Some("refuse") => RemovalPolicy::Refuse,
+					Some("cascade") => RemovalPolicy::Cascade,
+					Some("reassign_to_parent") => RemovalPolicy::ReassignToParent,
+					Some(policy) => Err(Error::parse("policy", format!("\"{policy}\"")))?,
+				};
+				let dep_id = DepartmentId(parse("department id", id)?);
+				(RemoveDepartment().bind(RemoveDepartmentInput { id: dep_id, policy }), 200)
+			}
+			(Method::Get, ["departments", id, "info"]) => {
+				let scope = match query.get("scope").copied() {
+					None | Some("direct") => InfoScope::Direct,
+					Some("rolled_up") => InfoScope::RolledUp,
+					Some(scope) => Err(Error::parse("scope", format!("\"{scope}\"")))?,
+				};
+				let as_of = query.get("as_of").map(|date| parse("date", date)).transpose()?;
+				let dep_id = DepartmentId(parse("department id", id)?);
+				(ShowDepartment().bind(ShowDepartmentInput { id: dep_id, scope, as_of }), 200)
+			}
+			_ => return Ok(None),
+		};
+
+		let output = invocation.invoke(&mut self.ctx)?;
+		Ok(Some(render(&output, status)?))
+	}
+}
+
+/// Serve the data file on `addr`, creating the file on the first change if it doesn't exist yet
+pub fn serve(
+	addr: &str,
+	data_file: &Path,
+	storage: StorageKind,
+	audit_log: Option<AuditLog>,
+) -> Result<(), Error> {
+	let mut ctx =
+		if data_file.exists() { storage::open(storage, data_file)?.load()? } else { Context::new() };
+	ctx.set_data_file(Some(data_file.to_path_buf()));
+	ctx.set_storage(storage);
+	ctx.set_audit_log(audit_log);
+
+	let mut server = ApiServer::bind(addr, ctx)?;
+	if let Some(addr) = server.local_addr() {
+		eprintln!("Serving {} on http://{addr}", data_file.display());
+	}
+	server.run()
+}
+
+/// HTTP status for a failed request
+pub fn status_code(err: &Error) -> u16 {
+	match err {
+		Error::Parse { .. } => 400,
+		Error::NotFound { .. } => 404,
+		Error::Conflict(_) => 409,
+		Error::Validation(_) => 422,
+		Error::Persistence(_) | Error::Io { .. } => 500,
+	}
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn render(output: &MenuItemOutput, status: u16) -> Result<ApiResponse, Error> {
+	Ok(ApiResponse { status, body: output::render(output, OutputFormat::Json)? })
+}
+
+fn respond(request: Request, response: ApiResponse) -> Result<(), Error> {
+	// a valid header, which can't fail to parse
+	let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+	Ok(request.respond(
+		Response::from_string(response.body).with_status_code(response.status).with_header(content_type),
+	)?)
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, Error> {
+	value.parse().map_err(|_| Error::parse(name, format!("\"{value}\"")))
+}
+
+fn staff_filter(query: &HashMap<&str, &str>) -> Result<StaffFilter, Error> {
+	let date = |key| query.get(key).map(|date| parse::<NaiveDate>("date", date)).transpose();
+	let per_page = query.get("per_page").map(|n| parse::<usize>("per_page", n)).transpose()?;
+	let page = query.get("page").map(|n| parse::<usize>("page", n)).transpose()?;
+
+	Ok(StaffFilter {
+		department: query
+			.get("department")
+			.map(|id| parse("department id", id).map(DepartmentId))
+			.transpose()?,
+		active: query.get("active").map(|active| parse("active", active)).transpose()?,
+		joined_from: date("joined_from")?,
+		joined_to: date("joined_to")?,
+		as_of: date("as_of")?,
+		// pages are numbered from 1, as in `staff list`
+		paging: per_page.map(|per_page| Paging { page: page.unwrap_or(1).saturating_sub(1), per_page }),
+	})
+}
@@ -1,11 +1,38 @@
-use crate::department::DepartmentId;
-use chrono::NaiveDate;
+use crate::{
+	department::DepartmentId,
+	errors::Error,
+	persistence::double_option,
+	traits::{OneLiner, Tabular},
+};
+use chrono::{Local, NaiveDate};
 use getset::{Getters, Setters};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct StaffId(pub u32);
 
+impl From<u32> for StaffId {
+	fn from(value: u32) -> Self {
+		StaffId(value)
+	}
+}
+
+impl From<StaffId> for u32 {
+	fn from(sid: StaffId) -> Self {
+		sid.0
+	}
+}
+
+impl TryFrom<&str> for StaffId {
+	type Error = Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		let value = value.parse::<u32>().map_err(|_| Error::parse("staff id", format!("\"{value}\"")))?;
+		Ok(StaffId(value))
+	}
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Gender {
 	Male,
@@ -13,13 +40,13 @@ pub enum Gender {
 }
 
 impl TryFrom<&str> for Gender {
-	type Error = &'static str;
+	type Error = Error;
 
 	fn try_from(value: &str) -> Result<Self, Self::Error> {
 		match value.to_lowercase().as_str() {
-			"m" => Ok(Gender::Male),
-			"f" => Ok(Gender::Female),
-			_ => Err("Invalid input. Please enter 'm' or 'f' only"),
+			"m" | "male" => Ok(Gender::Male),
+			"f" | "female" => Ok(Gender::Female),
+			_ => Err(Error::parse("gender", "please enter 'm' or 'f' only")),
 		}
 	}
 }
@@ -38,15 +65,45 @@ pub struct Staff {
 	// date of termination
 	dot: Option<NaiveDate>,
 	gender: Gender,
+	// job title
+	title: Option<String>,
 	department: Option<DepartmentId>,
+	// the staff this one reports to
+	manager: Option<StaffId>,
 	monthly_salary: Option<u32>,
 	active: bool,
+	// department, salary and title changes, oldest first. `department`, `monthly_salary` and `title` are
+	// the values recorded here as of today, so a change dated later is pending until then. Data files
+	// written before it existed have theirs filled in by the migration.
+	employment_history: Vec<EmploymentEvent>,
 }
 
 impl Staff {
 	pub fn new(id: StaffId, builder: StaffBuilder) -> Staff {
-		let StaffBuilder { first_name, last_name, email, dob, doj, gender, department, monthly_salary } =
-			builder;
+		let StaffBuilder {
+			first_name,
+			last_name,
+			email,
+			dob,
+			doj,
+			gender,
+			title,
+			department,
+			manager,
+			monthly_salary,
+		} = builder;
+
+		// what the staff joined with, as far as it is known
+		let employment_history = [
+			department.map(|d| EmploymentChange::Department(Some(d))),
+			monthly_salary.map(|s| EmploymentChange::MonthlySalary(Some(s))),
+			title.clone().map(|t| EmploymentChange::Title(Some(t))),
+		]
+		.into_iter()
+		.flatten()
+		.map(|change| EmploymentEvent { effective: doj, change })
+		.collect();
+
 		Staff {
 			id,
 			first_name,
@@ -56,13 +113,305 @@ impl Staff {
 			doj,
 			dot: None,
 			gender,
+			title,
 			department,
+			manager,
 			monthly_salary,
 			active: true,
+			employment_history,
+		}
+	}
+
+	/// The current staff info, in a form that can be validated and written back with `update()`
+	pub fn to_builder(&self) -> StaffBuilder {
+		StaffBuilder {
+			first_name: self.first_name.clone(),
+			last_name: self.last_name.clone(),
+			email: self.email.clone(),
+			dob: self.dob,
+			doj: self.doj,
+			gender: self.gender,
+			title: self.title.clone(),
+			department: self.department,
+			manager: self.manager,
+			monthly_salary: self.monthly_salary,
+		}
+	}
+
+	/// Write the staff info back. Changes of department, salary or title are recorded in the employment
+	/// history as of `effective`.
+	pub(crate) fn update(&mut self, builder: StaffBuilder, effective: NaiveDate) {
+		let StaffBuilder {
+			first_name,
+			last_name,
+			email,
+			dob,
+			doj,
+			gender,
+			title,
+			department,
+			manager,
+			monthly_salary,
+		} = builder;
+
+		if doj != self.doj {
+			// what the staff joined with moves along with the date of joining, and nothing can predate it
+			for event in self.employment_history.iter_mut() {
+				if event.effective == self.doj || event.effective < doj {
+					event.effective = doj;
+				}
+			}
+			self.employment_history.sort_by_key(|event| event.effective);
+		}
+
+		let changes = [
+			(department != self.department).then_some(EmploymentChange::Department(department)),
+			(monthly_salary != self.monthly_salary)
+				.then_some(EmploymentChange::MonthlySalary(monthly_salary)),
+			(title != self.title).then_some(EmploymentChange::Title(title)),
+		];
+		for change in changes.into_iter().flatten() {
+			// after the changes of the same day, so that the latest one recorded wins
+			let idx = self.employment_history.partition_point(|event| event.effective <= effective);
+			self.employment_history.insert(idx, EmploymentEvent { effective, change });
+		}
+
+		self.first_name = first_name;
+		self.last_name = last_name;
+		self.email = email;
+		self.dob = dob;
+		self.doj = doj;
+		self.gender = gender;
+		self.manager = manager;
+		self.set_current(Local::now().date_naive());
+	}
+
+	/// Bring `department`, `monthly_salary` and `title` to their values on `today`. A change recorded as of
+	/// an earlier date than the latest one doesn't change them, nor does one yet to take effect.
+	pub(crate) fn set_current(&mut self, today: NaiveDate) {
+		self.department = self.department_on(today);
+		self.monthly_salary = self.monthly_salary_on(today);
+		self.title = self.title_on(today).cloned();
+	}
+
+	/// Whether a change of department to one of `dep_ids` is yet to take effect after `today`
+	pub fn has_pending_transfer(&self, dep_ids: &[DepartmentId], today: NaiveDate) -> bool {
+		self.employment_history.iter().any(|event| {
+			event.effective > today
+				&& matches!(event.change, EmploymentChange::Department(Some(dep_id)) if dep_ids.contains(&dep_id))
+		})
+	}
+
+	/// Redirect the changes of department to one of `dep_ids` yet to take effect after `today`, e.g.
+	/// because those departments are removed. Past changes are kept, as they happened.
+	pub(crate) fn redirect_pending_transfers(
+		&mut self,
+		dep_ids: &[DepartmentId],
+		department: Option<DepartmentId>,
+		today: NaiveDate,
+	) {
+		for event in self.employment_history.iter_mut().filter(|event| event.effective > today) {
+			if let EmploymentChange::Department(Some(dep_id)) = event.change {
+				if dep_ids.contains(&dep_id) {
+					event.change = EmploymentChange::Department(department);
+				}
+			}
+		}
+	}
+
+	/// Whether the staff was with the company on `date`. The date of termination is their first day
+	/// away.
+	pub fn employed_on(&self, date: NaiveDate) -> bool {
+		self.doj <= date && self.dot.is_none_or(|dot| date < dot)
+	}
+
+	pub fn department_on(&self, date: NaiveDate) -> Option<DepartmentId> {
+		self.latest_change_on(date, |change| match change {
+			EmploymentChange::Department(department) => Some(*department),
+			_ => None,
+		})
+		.flatten()
+	}
+
+	pub fn monthly_salary_on(&self, date: NaiveDate) -> Option<u32> {
+		self.latest_change_on(date, |change| match change {
+			EmploymentChange::MonthlySalary(salary) => Some(*salary),
+			_ => None,
+		})
+		.flatten()
+	}
+
+	pub fn title_on(&self, date: NaiveDate) -> Option<&String> {
+		self.latest_change_on(date, |change| match change {
+			EmploymentChange::Title(title) => Some(title.as_ref()),
+			_ => None,
+		})
+		.flatten()
+	}
+
+	fn latest_change_on<'a, T>(
+		&'a self,
+		date: NaiveDate,
+		pick: impl Fn(&'a EmploymentChange) -> Option<T>,
+	) -> Option<T> {
+		self.employment_history
+			.iter()
+			.rev()
+			.filter(|event| event.effective <= date)
+			.find_map(|e| pick(&e.change))
+	}
+
+	/// Joining, the employment history and termination, in chronological order. `department_name` looks
+	/// up the name of a department, if it still exists.
+	pub fn timeline(&self, department_name: impl Fn(DepartmentId) -> Option<String>) -> Vec<TimelineEntry> {
+		let mut entries = vec![TimelineEntry { date: self.doj, event: "joined", value: "".to_string() }];
+		entries.extend(self.employment_history.iter().map(|event| {
+			let (name, value) = match &event.change {
+				EmploymentChange::Department(department) => (
+					"department",
+					department.map_or("none".to_string(), |dep_id| match department_name(dep_id) {
+						Some(name) => format!("{name} (id: {})", dep_id.0),
+						None => format!("id: {}", dep_id.0),
+					}),
+				),
+				EmploymentChange::MonthlySalary(salary) => {
+					("monthly_salary", salary.map_or("unknown".to_string(), |s| s.to_string()))
+				}
+				EmploymentChange::Title(title) => ("title", title.clone().unwrap_or("none".to_string())),
+			};
+			TimelineEntry { date: event.effective, event: name, value }
+		}));
+		entries.extend(self.dot.map(|dot| TimelineEntry {
+			date: dot,
+			event: "terminated",
+			value: "".to_string(),
+		}));
+		// a stable sort keeps the order of the entries of the same day
+		entries.sort_by_key(|entry| entry.date);
+		entries
+	}
+
+	/// How well the staff matches a search query on id, first name, last name, full name or email.
+	/// The query is matched case-insensitively. Returns the best match over all fields.
+	pub fn match_query(&self, query: &str) -> Option<(MatchKind, &'static str)> {
+		let query = query.trim().to_lowercase();
+		if query.is_empty() {
+			return None;
 		}
+
+		if query.parse::<u32>().is_ok_and(|id| id == self.id.0) {
+			return Some((MatchKind::Exact, "id"));
+		}
+
+		let full_name = format!("{} {}", self.first_name, self.last_name);
+		[
+			("first_name", self.first_name.as_str()),
+			("last_name", self.last_name.as_str()),
+			("name", full_name.as_str()),
+			("email", self.email.as_str()),
+		]
+		.into_iter()
+		.filter_map(|(field, value)| {
+			let value = value.to_lowercase();
+			let kind = if value == query {
+				MatchKind::Exact
+			} else if value.starts_with(&query) {
+				MatchKind::Prefix
+			} else if value.contains(&query) {
+				MatchKind::Substring
+			} else {
+				return None;
+			};
+			Some((kind, field))
+		})
+		.max_by_key(|(kind, _)| *kind)
+	}
+
+	pub(crate) fn terminate(&mut self, date: NaiveDate) {
+		self.dot = Some(date);
+		self.active = false;
+	}
+}
+
+impl OneLiner for Staff {
+	fn one_liner(&self) -> String {
+		format!("{}, {} <{}> (id: {})", self.last_name, self.first_name, self.email, self.id.0)
+	}
+}
+
+impl Tabular for Staff {
+	fn headers() -> Vec<&'static str> {
+		vec![
+			"id",
+			"first_name",
+			"last_name",
+			"email",
+			"dob",
+			"doj",
+			"dot",
+			"gender",
+			"title",
+			"department",
+			"manager",
+			"monthly_salary",
+			"active",
+		]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![
+			self.id.0.to_string(),
+			self.first_name.clone(),
+			self.last_name.clone(),
+			self.email.clone(),
+			self.dob.to_string(),
+			self.doj.to_string(),
+			self.dot.map_or("".to_string(), |d| d.to_string()),
+			format!("{:?}", self.gender),
+			self.title.clone().unwrap_or_default(),
+			self.department.map_or("".to_string(), |d| d.0.to_string()),
+			self.manager.map_or("".to_string(), |m| m.0.to_string()),
+			self.monthly_salary.map_or("".to_string(), |s| s.to_string()),
+			self.active.to_string(),
+		]
+	}
+}
+
+impl fmt::Display for Staff {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			r#"Staff
+Id: {}
+Name: {} {}
+Email: {}
+Date of birth: {}
+Gender: {:?}
+Title: {}
+Department: {}
+Manager: {}
+Monthly salary: {}
+Date of joining: {}
+Date of termination: {}
+Active: {}"#,
+			self.id.0,
+			self.first_name,
+			self.last_name,
+			self.email,
+			self.dob,
+			self.gender,
+			self.title.as_deref().unwrap_or("none"),
+			self.department.map_or("none".to_string(), |d| d.0.to_string()),
+			self.manager.map_or("none".to_string(), |m| m.0.to_string()),
+			self.monthly_salary.map_or("unknown".to_string(), |s| s.to_string()),
+			self.doj,
+			self.dot.map_or("none".to_string(), |d| d.to_string()),
+			self.active,
+		)
 	}
 }
 
+#[derive(Clone, Debug, Deserialize)]
 pub struct StaffBuilder {
 	pub first_name: String,
 	pub last_name: String,
@@ -70,6 +419,296 @@ pub struct StaffBuilder {
 	pub dob: NaiveDate,
 	pub doj: NaiveDate,
 	pub gender: Gender,
+	pub title: Option<String>,
 	pub department: Option<DepartmentId>,
+	pub manager: Option<StaffId>,
 	pub monthly_salary: Option<u32>,
 }
+
+/**
+ * A change set for an existing staff. Only the fields set to `Some` are changed.
+ **/
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StaffChanges {
+	pub first_name: Option<String>,
+	pub last_name: Option<String>,
+	pub email: Option<String>,
+	pub dob: Option<NaiveDate>,
+	pub doj: Option<NaiveDate>,
+	pub gender: Option<Gender>,
+	// `Some(None)` clears the title
+	#[serde(default, deserialize_with = "double_option")]
+	pub title: Option<Option<String>>,
+	// `Some(None)` removes the staff from their department
+	#[serde(default, deserialize_with = "double_option")]
+	pub department: Option<Option<DepartmentId>>,
+	// `Some(None)` leaves the staff without a manager
+	#[serde(default, deserialize_with = "double_option")]
+	pub manager: Option<Option<StaffId>>,
+	// `Some(None)` marks the salary as unknown
+	#[serde(default, deserialize_with = "double_option")]
+	pub monthly_salary: Option<Option<u32>>,
+	// when the department, salary or title changes take effect. Defaults to today.
+	pub effective: Option<NaiveDate>,
+}
+
+impl StaffChanges {
+	pub fn apply_to(self, builder: StaffBuilder) -> StaffBuilder {
+		StaffBuilder {
+			first_name: self.first_name.unwrap_or(builder.first_name),
+			last_name: self.last_name.unwrap_or(builder.last_name),
+			email: self.email.unwrap_or(builder.email),
+			dob: self.dob.unwrap_or(builder.dob),
+			doj: self.doj.unwrap_or(builder.doj),
+			gender: self.gender.unwrap_or(builder.gender),
+			title: self.title.unwrap_or(builder.title),
+			department: self.department.unwrap_or(builder.department),
+			manager: self.manager.unwrap_or(builder.manager),
+			monthly_salary: self.monthly_salary.unwrap_or(builder.monthly_salary),
+		}
+	}
+}
+
+/**
+ * A change of department, salary or title, taking effect on `effective`
+ **/
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmploymentEvent {
+	pub effective: NaiveDate,
+	pub change: EmploymentChange,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmploymentChange {
+	Department(Option<DepartmentId>),
+	MonthlySalary(Option<u32>),
+	Title(Option<String>),
+}
+
+/**
+ * How a staff leaves the directory. Termination keeps the record around, marked inactive.
+ **/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaffRemoval {
+	Terminate(StaffId, NaiveDate),
+	Delete(StaffId),
+}
+
+/// A staff after their `StaffRemoval`. A terminated staff stays in the directory, a deleted one doesn't.
+#[derive(Debug)]
+pub enum RemovedStaff<'a> {
+	Terminated(&'a Staff),
+	Deleted(Staff),
+}
+
+/**
+ * Options for listing staff. Every `None` field means "don't filter on it".
+ **/
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StaffFilter {
+	pub department: Option<DepartmentId>,
+	pub active: Option<bool>,
+	// inclusive range on the date of joining
+	pub joined_from: Option<NaiveDate>,
+	pub joined_to: Option<NaiveDate>,
+	// match `department` and `active` against the employment on this date rather than the current one
+	pub as_of: Option<NaiveDate>,
+	pub paging: Option<Paging>,
+}
+
+impl StaffFilter {
+	pub fn matches(&self, staff: &Staff) -> bool {
+		let (department, active) = match self.as_of {
+			Some(date) => (staff.department_on(date), staff.employed_on(date)),
+			None => (staff.department, staff.active),
+		};
+		self.department.is_none_or(|dep_id| department == Some(dep_id))
+			&& self.active.is_none_or(|a| active == a)
+			&& self.joined_from.is_none_or(|from| staff.doj >= from)
+			&& self.joined_to.is_none_or(|to| staff.doj <= to)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Paging {
+	// zero-offset page number
+	pub page: usize,
+	pub per_page: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaffPage<'a> {
+	pub staff: Vec<&'a Staff>,
+	// number of staff matching the filter, across all pages
+	pub total: usize,
+	pub paging: Option<Paging>,
+}
+
+impl StaffPage<'_> {
+	pub fn page_count(&self) -> usize {
+		match self.paging {
+			Some(Paging { per_page, .. }) if per_page > 0 => self.total.div_ceil(per_page),
+			_ => 1,
+		}
+	}
+
+	pub fn has_next_page(&self) -> bool {
+		self.paging.is_some_and(|p| p.page.saturating_add(1) < self.page_count())
+	}
+}
+
+/**
+ * The employment of a staff over time, e.g. for a profile view
+ **/
+#[derive(Debug, Serialize)]
+pub struct StaffTimeline<'a> {
+	pub staff: &'a Staff,
+	pub entries: Vec<TimelineEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TimelineEntry {
+	pub date: NaiveDate,
+	// "joined", "terminated", or the field changed, e.g. "monthly_salary"
+	pub event: &'static str,
+	pub value: String,
+}
+
+impl Tabular for TimelineEntry {
+	fn headers() -> Vec<&'static str> {
+		vec!["date", "event", "value"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![self.date.to_string(), self.event.to_string(), self.value.clone()]
+	}
+}
+
+impl fmt::Display for StaffTimeline<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.staff.one_liner())?;
+		for entry in &self.entries {
+			match entry.value.as_str() {
+				"" => write!(f, "\n  {} {}", entry.date, entry.event)?,
+				value => write!(f, "\n  {} {}: {value}", entry.date, entry.event)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/**
+ * How closely a staff matched a search query, from the weakest to the strongest
+ **/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+	Substring,
+	Prefix,
+	Exact,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaffMatch<'a> {
+	pub staff: &'a Staff,
+	pub kind: MatchKind,
+	// the field that matched best, e.g. "email"
+	pub field: &'static str,
+}
+
+/**
+ * Where a staff sits in the reporting lines: whom they report to, up to the top, and who reports to them
+ **/
+#[derive(Debug, Serialize)]
+pub struct ReportingLine<'a> {
+	pub staff: &'a Staff,
+	// the manager of the staff first, then the manager's manager, and so on
+	pub chain_of_command: Vec<&'a Staff>,
+	pub direct_reports: Vec<&'a Staff>,
+	// number of direct reports
+	pub span_of_control: usize,
+	// number of staff reporting to the staff directly or through their reports
+	pub total_reports: usize,
+}
+
+impl Tabular for ReportingLine<'_> {
+	fn headers() -> Vec<&'static str> {
+		let mut headers = Staff::headers();
+		headers.extend(["chain_of_command", "direct_reports", "span_of_control", "total_reports"]);
+		headers
+	}
+
+	fn row(&self) -> Vec<String> {
+		let ids = |staff: &[&Staff]| staff.iter().map(|s| s.id.0.to_string()).collect::<Vec<_>>().join(";");
+		let mut row = self.staff.row();
+		row.extend([
+			ids(&self.chain_of_command),
+			ids(&self.direct_reports),
+			self.span_of_control.to_string(),
+			self.total_reports.to_string(),
+		]);
+		row
+	}
+}
+
+impl fmt::Display for ReportingLine<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.staff.one_liner())?;
+		write!(f, "\nReports to:")?;
+		if self.chain_of_command.is_empty() {
+			write!(f, " nobody")?;
+		}
+		for manager in &self.chain_of_command {
+			write!(f, "\n  {}", manager.one_liner())?;
+		}
+		write!(f, "\nDirect reports ({}):", self.span_of_control)?;
+		for report in &self.direct_reports {
+			write!(f, "\n  {}", report.one_liner())?;
+		}
+		write!(f, "\nTotal reports: {}", self.total_reports)
+	}
+}
+
+/**
+ * The reporting lines of a company, staff without a manager first
+ **/
+#[derive(Debug, Serialize)]
+pub struct ReportingTree<'a> {
+	pub company_name: &'a str,
+	pub domain: &'a str,
+	pub roots: Vec<ReportingNode<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportingNode<'a> {
+	pub staff: &'a Staff,
+	pub reports: Vec<ReportingNode<'a>>,
+}
+
+impl<'a> ReportingTree<'a> {
+	/// All staff with their depth in the tree, in depth-first order
+	pub fn flatten(&self) -> Vec<(usize, &'a Staff)> {
+		fn visit<'a>(node: &ReportingNode<'a>, depth: usize, result: &mut Vec<(usize, &'a Staff)>) {
+			result.push((depth, node.staff));
+			node.reports.iter().for_each(|report| visit(report, depth + 1, result));
+		}
+
+		let mut result = vec![];
+		self.roots.iter().for_each(|root| visit(root, 0, &mut result));
+		result
+	}
+}
+
+impl fmt::Display for ReportingTree<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} ({})", self.company_name, self.domain)?;
+		for (depth, staff) in self.flatten() {
+			let terminated = if staff.active { "" } else { " (terminated)" };
+			write!(f, "\n{}L {}{terminated}", "  ".repeat(depth), staff.one_liner())?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,391 @@
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use serde_json::{Map, Value};
+use std::{
+	collections::BTreeSet,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	context::Context,
+	department::{Department, DepartmentId},
+	errors::{DataFileError, Error},
+	persistence,
+	staff::{Staff, StaffId},
+	workspace::DEFAULT_WORKSPACE,
+};
+
+/**
+ * Where a context is loaded from and saved to
+ **/
+pub trait Storage {
+	fn load(&mut self) -> Result<Context, Error>;
+	/// Write the whole context, replacing what was stored
+	fn save(&mut self, ctx: &Context) -> Result<(), Error>;
+	/// Write the records in `ctx.unsaved_changes()` and the company info. The context must have been
+	/// loaded from, or last saved to, this storage.
+	fn save_changes(&mut self, ctx: &Context) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StorageKind {
+	/// A JSON file, rewritten as a whole on every save
+	#[default]
+	Json,
+	/// An SQLite database, where only changed records are written
+	Sqlite,
+}
+
+/// Records inserted, updated or removed since the context was loaded or last saved
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+	// the company name, domain or email domain rule
+	pub company: bool,
+	pub departments: BTreeSet<DepartmentId>,
+	pub staff: BTreeSet<StaffId>,
+	// workspaces created, renamed, removed or switched to
+	pub workspaces: bool,
+	// the data file was migrated on load, so every record has to be written again in the current format
+	pub migrated: bool,
+}
+
+impl ChangeSet {
+	/// Whether nothing changed. A migration alone isn't a change: the data file reads the same.
+	pub fn is_empty(&self) -> bool {
+		!self.company && self.departments.is_empty() && self.staff.is_empty() && !self.workspaces
+	}
+}
+
+pub fn open(kind: StorageKind, path: &Path) -> Result<Box<dyn Storage>, Error> {
+	match kind {
+		StorageKind::Json => Ok(Box::new(JsonFileStorage::new(path))),
+		StorageKind::Sqlite => Ok(Box::new(SqliteStorage::open(path)?)),
+	}
+}
+
+/**
+ * JsonFileStorage
+ **/
+pub struct JsonFileStorage {
+	path: PathBuf,
+}
+
+impl JsonFileStorage {
+	pub fn new(path: &Path) -> Self {
+		Self { path: path.to_path_buf() }
+	}
+}
+
+impl Storage for JsonFileStorage {
+	fn load(&mut self) -> Result<Context, Error> {
+		persistence::load(&self.path)
+	}
+
+	fn save(&mut self, ctx: &Context) -> Result<(), Error> {
+		persistence::save(ctx, &self.path)
+	}
+
+	// a JSON file can only be written as a whole
+	fn save_changes(&mut self, ctx: &Context) -> Result<(), Error> {
+		self.save(ctx)
+	}
+}
+
+/**
+ * SqliteStorage
+ *
+ * Every department and staff is a row holding the record as JSON in `data`, along with the columns
+ * worth querying with SQL. Each workspace is a row holding its company info as JSON, and the records
+ * are keyed by workspace and id.
+ **/
+pub struct SqliteStorage {
+	conn: Connection,
+	// written before workspaces existed, with a single directory and records keyed by id alone
+	legacy_layout: bool,
+}
+
+const SQLITE_SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS meta (
+		key TEXT PRIMARY KEY,
+		value TEXT NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS workspaces (
+		name TEXT PRIMARY KEY,
+		company TEXT NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS departments (
+		workspace TEXT NOT NULL,
+		id INTEGER NOT NULL,
+		name TEXT NOT NULL,
+		parent INTEGER,
+		head INTEGER,
+		active INTEGER NOT NULL,
+		data TEXT NOT NULL,
+		PRIMARY KEY (workspace, id)
+	);
+	CREATE TABLE IF NOT EXISTS staff (
+		workspace TEXT NOT NULL,
+		id INTEGER NOT NULL,
+		first_name TEXT NOT NULL,
+		last_name TEXT NOT NULL,
+		email TEXT NOT NULL,
+		title TEXT,
+		department INTEGER,
+		manager INTEGER,
+		monthly_salary INTEGER,
+		active INTEGER NOT NULL,
+		data TEXT NOT NULL,
+		PRIMARY KEY (workspace, id)
+	);
+	CREATE INDEX IF NOT EXISTS staff_department ON staff (workspace, department);
+	CREATE INDEX IF NOT EXISTS staff_last_name ON staff (last_name);
+";
+
+// columns added to tables of the current layout since it was introduced
+const SQLITE_ADDED_COLUMNS: &[(&str, &str, &str)] =
+	&[("departments", "head", "INTEGER"), ("staff", "manager", "INTEGER"), ("staff", "title", "TEXT")];
+
+// replaced by the current layout on the first save
+const SQLITE_LEGACY_TABLES: &str = "
+	DROP TABLE IF EXISTS departments;
+	DROP TABLE IF EXISTS staff;
+	DELETE FROM meta WHERE key = 'company';
+";
+
+impl SqliteStorage {
+	/// Open the database, creating it if it doesn't exist
+	pub fn open(path: &Path) -> Result<Self, rusqlite::Error> {
+		let conn = Connection::open(path)?;
+		let staff_columns = table_columns(&conn, "staff")?;
+		let legacy_layout = !staff_columns.is_empty() && !staff_columns.iter().any(|c| c == "workspace");
+		if !legacy_layout {
+			conn.execute_batch(SQLITE_SCHEMA)?;
+			for (table, column, kind) in SQLITE_ADDED_COLUMNS {
+				if !table_columns(&conn, table)?.iter().any(|c| c == column) {
+					conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {kind}"))?;
+				}
+			}
+		}
+		Ok(Self { conn, legacy_layout })
+	}
+
+	fn meta(&self, key: &str) -> Result<Option<String>, rusqlite::Error> {
+		self.conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0)).optional()
+	}
+
+	fn records(&self, table: &str, workspace: Option<&str>) -> Result<Vec<Value>, Error> {
+		let data = match workspace {
+			Some(workspace) => {
+				let mut stmt = self
+					.conn
+					.prepare(&format!("SELECT data FROM {table} WHERE workspace = ?1 ORDER BY id"))?;
+				stmt.query_map([workspace], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?
+			}
+			None => {
+				let mut stmt = self.conn.prepare(&format!("SELECT data FROM {table} ORDER BY id"))?;
+				stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?
+			}
+		};
+		data.iter().map(|data| Ok(parse_json(data)?)).collect()
+	}
+
+	/// The fields of a data file holding a single directory, as written before workspaces existed
+	fn legacy_fields(&self) -> Result<Option<Map<String, Value>>, Error> {
+		let Some(company) = self.meta("company")? else {
+			return Ok(None);
+		};
+		let Value::Object(mut fields) = parse_json(&company)? else {
+			return Ok(None);
+		};
+		fields.insert("departments".to_string(), Value::Array(self.records("departments", None)?));
+		fields.insert("staff".to_string(), Value::Array(self.records("staff", None)?));
+		Ok(Some(fields))
+	}
+
+	/// The fields of a data file holding every workspace
+	fn workspace_fields(&self) -> Result<Option<Map<String, Value>>, Error> {
+		let companies = self
+			.conn
+			.prepare("SELECT name, company FROM workspaces ORDER BY name")?
+			.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+			.collect::<Result<Vec<_>, _>>()?;
+		if companies.is_empty() {
+			return Ok(None);
+		}
+
+		let mut workspaces = Map::new();
+		for (name, company) in companies {
+			let mut directory = parse_json(&company)?;
+			if let Some(fields) = directory.as_object_mut() {
+				fields.insert(
+					"departments".to_string(),
+					Value::Array(self.records("departments", Some(&name))?),
+				);
+				fields.insert("staff".to_string(), Value::Array(self.records("staff", Some(&name))?));
+			}
+			workspaces.insert(name, directory);
+		}
+
+		let mut fields = Map::new();
+		let workspace = self.meta("workspace")?.unwrap_or(DEFAULT_WORKSPACE.to_string());
+		fields.insert("workspace".to_string(), Value::from(workspace));
+		fields.insert("workspaces".to_string(), Value::Object(workspaces));
+		Ok(Some(fields))
+	}
+}
+
+impl Storage for SqliteStorage {
+	fn load(&mut self) -> Result<Context, Error> {
+		let fields = if self.legacy_layout { self.legacy_fields()? } else { self.workspace_fields()? };
+		// a new database holds an empty directory
+		let Some(mut fields) = fields else {
+			return Ok(Context::new());
+		};
+
+		let version = match self.meta("format_version")? {
+			Some(version) => version.parse::<u32>().map_err(|_| DataFileError::InvalidVersion)?,
+			None => 0,
+		};
+		fields.insert("format_version".to_string(), Value::from(version));
+
+		persistence::from_value(Value::Object(fields))
+	}
+
+	fn save(&mut self, ctx: &Context) -> Result<(), Error> {
+		let tx = self.conn.transaction()?;
+		if self.legacy_layout {
+			tx.execute_batch(SQLITE_LEGACY_TABLES)?;
+			tx.execute_batch(SQLITE_SCHEMA)?;
+		}
+		tx.execute("DELETE FROM workspaces", [])?;
+		tx.execute("DELETE FROM departments", [])?;
+		tx.execute("DELETE FROM staff", [])?;
+		for (workspace, directory) in ctx.workspaces() {
+			for dep in directory.departments() {
+				upsert_department(&tx, workspace, dep)?;
+			}
+			for staff in directory.staff() {
+				upsert_staff(&tx, workspace, staff)?;
+			}
+		}
+		save_companies(&tx, ctx)?;
+		tx.commit()?;
+
+		self.legacy_layout = false;
+		Ok(())
+	}
+
+	fn save_changes(&mut self, ctx: &Context) -> Result<(), Error> {
+		let workspaces = ctx.workspaces();
+		// the context may have been loaded by another instance, so a migration is told by the context
+		let full_save = workspaces.values().any(|directory| {
+			let changes = directory.unsaved_changes();
+			changes.workspaces || changes.migrated
+		});
+		if self.legacy_layout || full_save {
+			return self.save(ctx);
+		}
+
+		let tx = self.conn.transaction()?;
+		for (workspace, directory) in workspaces {
+			let changes = directory.unsaved_changes();
+			for dep_id in &changes.departments {
+				match directory.department(dep_id) {
+					Some(dep) => upsert_department(&tx, workspace, dep)?,
+					None => {
+						tx.execute(
+							"DELETE FROM departments WHERE workspace = ?1 AND id = ?2",
+							params![workspace, dep_id.0],
+						)?;
+					}
+				}
+			}
+			for staff_id in &changes.staff {
+				match directory.staff_member(staff_id) {
+					Some(staff) => upsert_staff(&tx, workspace, staff)?,
+					None => {
+						tx.execute(
+							"DELETE FROM staff WHERE workspace = ?1 AND id = ?2",
+							params![workspace, staff_id.0],
+						)?;
+					}
+				}
+			}
+		}
+		save_companies(&tx, ctx)?;
+		tx.commit()?;
+		Ok(())
+	}
+}
+
+/**
+ * Un-export / internal helper methods below
+ **/
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+	conn.prepare("SELECT name FROM pragma_table_info(?1)")?
+		.query_map([table], |row| row.get::<_, String>(0))?
+		.collect()
+}
+
+fn upsert_department(tx: &Transaction, workspace: &str, dep: &Department) -> Result<(), Error> {
+	tx.execute(
+		"INSERT OR REPLACE INTO departments (workspace, id, name, parent, head, active, data)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+		params![
+			workspace,
+			dep.id().0,
+			dep.name(),
+			dep.parent().map(|p| p.0),
+			dep.head().map(|h| h.0),
+			dep.active(),
+			serde_json::to_string(dep)?
+		],
+	)?;
+	Ok(())
+}
+
+fn upsert_staff(tx: &Transaction, workspace: &str, staff: &Staff) -> Result<(), Error> {
+	tx.execute(
+		"INSERT OR REPLACE INTO staff
+			(workspace, id, first_name, last_name, email, title, department, manager, monthly_salary, active, data)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+		params![
+			workspace,
+			staff.id().0,
+			staff.first_name(),
+			staff.last_name(),
+			staff.email(),
+			staff.title(),
+			staff.department().map(|d| d.0),
+			staff.manager().map(|m| m.0),
+			staff.monthly_salary(),
+			staff.active(),
+			serde_json::to_string(staff)?
+		],
+	)?;
+	Ok(())
+}
+
+/// Everything in each workspace but its records, i.e. the company info and the id counters
+fn save_companies(tx: &Transaction, ctx: &Context) -> Result<(), Error> {
+	let mut stmt = tx.prepare("INSERT OR REPLACE INTO workspaces (name, company) VALUES (?1, ?2)")?;
+	for (workspace, directory) in ctx.workspaces() {
+		let mut company = serde_json::to_value(directory)?;
+		if let Some(fields) = company.as_object_mut() {
+			for key in ["departments", "staff"] {
+				fields.remove(key);
+			}
+		}
+		stmt.execute(params![workspace, company.to_string()])?;
+	}
+
+	let mut stmt = tx.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?;
+	stmt.execute(params!["format_version", persistence::FORMAT_VERSION.to_string()])?;
+	stmt.execute(params!["workspace", ctx.workspace()])?;
+	Ok(())
+}
+
+/// Rows hold JSON, which is only malformed if the database was changed by hand or corrupted
+fn parse_json(data: &str) -> Result<Value, DataFileError> {
+	serde_json::from_str(data).map_err(|err| DataFileError::Malformed(err.to_string()))
+}
@@ -1,27 +1,77 @@
 use super::*;
 use crate::{
+	audit::{self, AuditAction, AuditEntity, AuditLog, AuditOrigin, AuditQuery},
 	context::Context,
-	department::{DepartmentBuilder, DepartmentId},
-	menu_items::MenuItemInput,
+	department::{DepartmentBuilder, DepartmentChanges, DepartmentId, InfoScope, RemovalPolicy},
+	errors::{ConflictError, DataFileError, Error, PersistenceError, ValidationError},
+	export::{self, ExportColumn, ExportOptions},
+	history::{CompanyInfo, History},
+	import::{self, ColumnMapping, ImportKind, ImportOptions},
+	menu_items::{
+		BackupRef, Execute, MenuItemOutput, NameCompanyInput, RestoreBackup, RestoreBackupInput, SaveContext,
+	},
+	org_chart::{self, ChartSyntax, OrgChartOptions},
+	output::{self, OutputFormat},
+	persistence,
+	search::SearchEntity,
+	staff::{
+		Gender, MatchKind, Paging, RemovedStaff, Staff, StaffBuilder, StaffChanges, StaffFilter, StaffId,
+		StaffRemoval,
+	},
+	storage::{self, StorageKind},
+	workspace::{self, MovedStaff},
 };
+use chrono::NaiveDate;
+use std::{fs, path::PathBuf};
+
+fn date(s: &str) -> NaiveDate {
+	NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+fn staff_builder(first_name: &str, last_name: &str, department: Option<DepartmentId>) -> StaffBuilder {
+	StaffBuilder {
+		first_name: first_name.to_string(),
+		last_name: last_name.to_string(),
+		email: format!("{}.{}@example.com", first_name.to_lowercase(), last_name.to_lowercase()),
+		dob: date("1990-01-01"),
+		doj: date("2020-01-01"),
+		gender: Gender::Female,
+		title: None,
+		department,
+		manager: None,
+		monthly_salary: None,
+	}
+}
 
 #[test]
 fn name_a_company() {
 	let mut ctx = Context::new();
-	let name_company: Box<dyn MenuItem> = Box::new(NameCompany::new());
+	let name_company = NameCompany::new();
 
 	let compay_name = String::from("MyCompany");
-	let _ = name_company.execute(&mut ctx, MenuItemInput::String(compay_name.clone()));
+	let input = NameCompanyInput { name: compay_name.clone(), domain: None, enforce_email_domain: None };
+	let _ = name_company.execute(&mut ctx, input);
 
 	assert_eq!(*ctx.company_name(), compay_name);
+
+	let input = NameCompanyInput {
+		name: "Acme".to_string(),
+		domain: Some("not a domain".to_string()),
+		enforce_email_domain: None,
+	};
+	assert_eq!(
+		name_company.execute(&mut ctx, input),
+		Err(ValidationError::InvalidDomain("not a domain".to_string()).into())
+	);
+	assert_eq!(*ctx.company_name(), compay_name);
 }
 
 #[test]
 fn create_a_department() {
 	let mut ctx = Context::new();
-	let create_department: Box<dyn MenuItem> = Box::new(CreateDepartment::new());
+	let create_department = CreateDepartment::new();
 
-	let dept1_params = MenuItemInput::DepartmentBuilder(DepartmentBuilder::new("Dept1".to_string(), None));
+	let dept1_params = DepartmentBuilder::new("Dept1".to_string(), None);
 	let _ = create_department.execute(&mut ctx, dept1_params);
 
 	let next_dep_id = ctx.next_department_id();
@@ -35,3 +85,1363 @@ fn create_a_department() {
 	assert_eq!(*dept1.name(), "Dept1".to_string());
 	assert_eq!(*dept1.parent(), None);
 }
+
+#[test]
+fn list_staff_sorted_by_last_then_first_name() {
+	let mut ctx = Context::new();
+	ctx.insert_staff(staff_builder("Zoe", "Adams", None)).unwrap();
+	ctx.insert_staff(staff_builder("Bob", "Young", None)).unwrap();
+	ctx.insert_staff(staff_builder("Amy", "adams", None)).unwrap();
+
+	let page = ListStaff().execute(&mut ctx, StaffFilter::default()).unwrap();
+
+	let names = page.staff.iter().map(|s| s.first_name().as_str()).collect::<Vec<_>>();
+	assert_eq!(names, vec!["Amy", "Zoe", "Bob"]);
+	assert_eq!(page.total, 3);
+}
+
+#[test]
+fn list_staff_filters_and_pages() {
+	let mut ctx = Context::new();
+	let dep_id = *ctx.insert_department(DepartmentBuilder::new("Dept1".to_string(), None)).unwrap().id();
+	for name in ["A", "B", "C", "D", "E"] {
+		ctx.insert_staff(staff_builder(name, "InDept", Some(dep_id))).unwrap();
+	}
+	ctx.insert_staff(staff_builder("F", "NoDept", None)).unwrap();
+	let mut late_joiner = staff_builder("G", "InDept", Some(dep_id));
+	late_joiner.doj = date("2024-06-01");
+	ctx.insert_staff(late_joiner).unwrap();
+
+	let filter = StaffFilter {
+		department: Some(dep_id),
+		joined_to: Some(date("2023-12-31")),
+		paging: Some(Paging { page: 1, per_page: 2 }),
+		..Default::default()
+	};
+	let page = ctx.list_staff(&filter);
+
+	assert_eq!(page.total, 5);
+	assert_eq!(page.page_count(), 3);
+	assert!(page.has_next_page());
+	assert_eq!(page.staff.iter().map(|s| s.first_name().as_str()).collect::<Vec<_>>(), vec!["C", "D"]);
+
+	// a page past the end is empty, however far
+	for page in [3, usize::MAX / 2 + 1, usize::MAX] {
+		let filter = StaffFilter { paging: Some(Paging { page, per_page: 2 }), ..filter.clone() };
+		let page = ctx.list_staff(&filter);
+		assert!(page.staff.is_empty() && !page.has_next_page());
+		assert_eq!(page.total, 5);
+	}
+}
+
+#[test]
+fn department_info_counts_active_staff_and_rolls_up() {
+	let mut ctx = Context::new();
+	let parent = *ctx.insert_department(DepartmentBuilder::new("Parent".to_string(), None)).unwrap().id();
+	let child =
+		*ctx.insert_department(DepartmentBuilder::new("Child".to_string(), Some(parent))).unwrap().id();
+
+	for (name, dep, salary) in [("A", parent, Some(1000)), ("B", parent, None), ("C", child, Some(500))] {
+		let mut builder = staff_builder(name, "Staff", Some(dep));
+		builder.monthly_salary = salary;
+		ctx.insert_staff(builder).unwrap();
+	}
+
+	let direct = ctx.department_info(&parent, InfoScope::Direct).unwrap();
+	assert_eq!(direct.headcount, 2);
+	assert_eq!(direct.monthly_expense, 1000);
+	assert_eq!(direct.unknown_salary_count, 1);
+
+	let rolled_up = ctx.department_info(&parent, InfoScope::RolledUp).unwrap();
+	assert_eq!(rolled_up.headcount, 3);
+	assert_eq!(rolled_up.monthly_expense, 1500);
+	assert_eq!(rolled_up.unknown_salary_count, 1);
+}
+
+#[test]
+fn insert_staff_validates_builder() {
+	let mut ctx = Context::new();
+
+	let err = ctx.insert_staff(staff_builder("A", "Staff", Some(DepartmentId(9)))).unwrap_err();
+	assert_eq!(err, Error::unknown_department(DepartmentId(9)));
+
+	let mut builder = staff_builder("A", "Staff", None);
+	builder.doj = date("1980-01-01");
+	assert!(matches!(
+		ctx.insert_staff(builder),
+		Err(Error::Validation(ValidationError::JoinedBeforeBirth { .. }))
+	));
+
+	ctx.insert_staff(staff_builder("A", "Staff", None)).unwrap();
+	let mut builder = staff_builder("B", "Staff", None);
+	builder.email = "A.Staff@example.com".to_string();
+	assert!(matches!(ctx.insert_staff(builder), Err(Error::Conflict(ConflictError::DuplicateEmail(_)))));
+
+	assert_eq!(ctx.staff().len(), 1);
+}
+
+#[test]
+fn insert_staff_enforces_domain_when_configured() {
+	let mut ctx = Context::new();
+	ctx.set_domain("mycompany.com".to_string());
+
+	let err = ctx.insert_staff(staff_builder("A", "Staff", None)).unwrap_err();
+	assert!(matches!(err, Error::Validation(ValidationError::EmailOutsideDomain { .. })));
+
+	ctx.set_enforce_email_domain(false);
+	assert!(ctx.insert_staff(staff_builder("A", "Staff", None)).is_ok());
+}
+
+#[test]
+fn update_staff_applies_change_set_with_validation() {
+	let mut ctx = Context::new();
+	let dep_id = *ctx.insert_department(DepartmentBuilder::new("Dept1".to_string(), None)).unwrap().id();
+	let staff_id = *ctx.insert_staff(staff_builder("A", "Staff", None)).unwrap().id();
+
+	let changes = StaffChanges {
+		last_name: Some("Renamed".to_string()),
+		department: Some(Some(dep_id)),
+		..Default::default()
+	};
+	let staff = ctx.update_staff(&staff_id, changes).unwrap();
+	assert_eq!(staff.last_name(), "Renamed");
+	assert_eq!(*staff.first_name(), "A");
+	assert_eq!(*staff.department(), Some(dep_id));
+
+	let changes = StaffChanges { department: Some(Some(DepartmentId(9))), ..Default::default() };
+	assert_eq!(ctx.update_staff(&staff_id, changes).unwrap_err(), Error::unknown_department(DepartmentId(9)));
+	assert_eq!(
+		ctx.update_staff(&StaffId(9), StaffChanges::default()).unwrap_err(),
+		Error::unknown_staff(StaffId(9))
+	);
+}
+
+#[test]
+fn delete_staff_terminates_or_removes() {
+	let mut ctx = Context::new();
+	let staff_id = *ctx.insert_staff(staff_builder("A", "Staff", None)).unwrap().id();
+	let other_id = *ctx.insert_staff(staff_builder("B", "Staff", None)).unwrap().id();
+	let delete_staff = DeleteStaff();
+
+	let removal = StaffRemoval::Terminate(staff_id, date("2024-01-31"));
+	let Ok(RemovedStaff::Terminated(staff)) = delete_staff.execute(&mut ctx, removal) else {
+		panic!("expected the terminated staff");
+	};
+	assert!(!*staff.active());
+	assert_eq!(*staff.dot(), Some(date("2024-01-31")));
+	assert_eq!(
+		ctx.terminate_staff(&staff_id, date("2024-02-01")).unwrap_err(),
+		ConflictError::AlreadyTerminated(staff_id).into()
+	);
+
+	let removal = StaffRemoval::Delete(other_id);
+	assert!(matches!(delete_staff.execute(&mut ctx, removal), Ok(RemovedStaff::Deleted(_))));
+	assert_eq!(ctx.staff().len(), 1);
+	assert!(ctx.staff_member(&other_id).is_none());
+}
+
+#[test]
+fn update_department_rejects_cycles() {
+	let mut ctx = Context::new();
+	let root = *ctx.insert_department(DepartmentBuilder::new("Root".to_string(), None)).unwrap().id();
+	let child = *ctx.insert_department(DepartmentBuilder::new("Child".to_string(), Some(root))).unwrap().id();
+	let grandchild =
+		*ctx.insert_department(DepartmentBuilder::new("Grandchild".to_string(), Some(child))).unwrap().id();
+
+	let changes = DepartmentChanges { parent: Some(Some(grandchild)), ..Default::default() };
+	assert_eq!(
+		ctx.update_department(&root, changes).unwrap_err(),
+		ConflictError::DepartmentCycle { department: root, parent: grandchild }.into()
+	);
+
+	let changes = DepartmentChanges {
+		name: Some("Renamed".to_string()),
+		parent: Some(None),
+		head: None,
+		active: Some(false),
+	};
+	let dep = ctx.update_department(&grandchild, changes).unwrap();
+	assert_eq!(dep.name(), "Renamed");
+	assert_eq!(*dep.parent(), None);
+	assert!(!*dep.active());
+}
+
+#[test]
+fn remove_department_policies() {
+	let mut ctx = Context::new();
+	let root = *ctx.insert_department(DepartmentBuilder::new("Root".to_string(), None)).unwrap().id();
+	let child = *ctx.insert_department(DepartmentBuilder::new("Child".to_string(), Some(root))).unwrap().id();
+	let grandchild =
+		*ctx.insert_department(DepartmentBuilder::new("Grandchild".to_string(), Some(child))).unwrap().id();
+	let staff_id = *ctx.insert_staff(staff_builder("A", "Staff", Some(child))).unwrap().id();
+
+	assert_eq!(
+		ctx.remove_department(&child, RemovalPolicy::Refuse).unwrap_err(),
+		ConflictError::DepartmentHasChildren(child).into()
+	);
+
+	let removed = ctx.remove_department(&child, RemovalPolicy::ReassignToParent).unwrap();
+	assert_eq!(removed.len(), 1);
+	assert_eq!(*ctx.department(&grandchild).unwrap().parent(), Some(root));
+	assert_eq!(*ctx.staff_member(&staff_id).unwrap().department(), Some(root));
+
+	let removed = ctx.remove_department(&root, RemovalPolicy::Cascade).unwrap();
+	assert_eq!(removed.len(), 2);
+	assert!(ctx.departments().is_empty());
+	assert_eq!(*ctx.staff_member(&staff_id).unwrap().department(), None);
+}
+
+#[test]
+fn search_staff_ranks_exact_then_prefix_then_substring() {
+	let mut ctx = Context::new();
+	ctx.insert_staff(staff_builder("Annabel", "Smith", None)).unwrap();
+	ctx.insert_staff(staff_builder("Ann", "Jones", None)).unwrap();
+	ctx.insert_staff(staff_builder("Joanne", "Brown", None)).unwrap();
+	ctx.insert_staff(staff_builder("Bob", "Green", None)).unwrap();
+
+	let matches = ctx.search_staff("ANN");
+	let found = matches.iter().map(|m| (m.staff.first_name().as_str(), m.kind)).collect::<Vec<_>>();
+	assert_eq!(
+		found,
+		vec![("Ann", MatchKind::Exact), ("Annabel", MatchKind::Prefix), ("Joanne", MatchKind::Substring)]
+	);
+
+	let matches = RetrieveStaff().execute(&mut ctx, "bob.green@".to_string()).unwrap();
+	assert_eq!(matches.len(), 1);
+	assert_eq!(matches[0].field, "email");
+}
+
+#[test]
+fn fuzzy_search_tolerates_typos_and_tracks_mutations() {
+	let mut ctx = Context::new();
+	ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap();
+	let john = *ctx.insert_staff(staff_builder("John", "Smith", None)).unwrap().id();
+	ctx.insert_staff(staff_builder("Mary", "Jones", None)).unwrap();
+
+	let results = ctx.fuzzy_search("jonh smtih");
+	assert!(matches!(results.first().map(|r| &r.entity), Some(SearchEntity::Staff(s)) if *s.id() == john));
+
+	let results = ctx.fuzzy_search("enginering");
+	assert!(matches!(results.first().map(|r| &r.entity), Some(SearchEntity::Department(_))));
+
+	let changes = StaffChanges { first_name: Some("Jonathan".to_string()), ..Default::default() };
+	ctx.update_staff(&john, changes).unwrap();
+	assert!(
+		ctx.fuzzy_search("jonathon")
+			.iter()
+			.any(|r| matches!(r.entity, SearchEntity::Staff(s) if *s.id() == john))
+	);
+
+	ctx.delete_staff(&john).unwrap();
+	assert!(ctx.fuzzy_search("jonathan").is_empty());
+
+	// a deserialized context has no index until it is rebuilt, but searching still works
+	let mut loaded = serde_json::from_str::<Context>(&serde_json::to_string(&ctx).unwrap()).unwrap();
+	assert_eq!(loaded.fuzzy_search("mray").len(), 1);
+	loaded.rebuild_search_index();
+	assert_eq!(loaded.fuzzy_search("mray").len(), 1);
+}
+
+#[test]
+fn subcommands_operate_on_the_data_file() {
+	let data_file = std::env::temp_dir().join(format!("staff-directory-test-{}.json", std::process::id()));
+	let _ = fs::remove_file(&data_file);
+
+	for args in [
+		vec!["company", "set", "--name", "Acme", "--domain", "acme.com"],
+		vec!["dept", "add", "--name", "Engineering"],
+		vec![
+			"staff",
+			"add",
+			"--first-name",
+			"Jo",
+			"--last-name",
+			"Bloggs",
+			"--email",
+			"jo@acme.com",
+			"--dob",
+			"1990-01-01",
+			"--doj",
+			"2020-01-01",
+			"--gender",
+			"f",
+			"--department",
+			"0",
+		],
+		vec!["staff", "list", "--active"],
+	] {
+		let cli = Cli::try_parse_from([vec!["staff-directory", data_file.to_str().unwrap()], args].concat())
+			.unwrap();
+		run(Some(cli)).unwrap();
+	}
+
+	let ctx = persistence::load(&data_file).unwrap();
+	assert_eq!(ctx.company_name(), "Acme");
+	assert_eq!(ctx.departments().len(), 1);
+	assert_eq!(*ctx.staff()[0].department(), Some(DepartmentId(0)));
+
+	// a staff outside the company domain is rejected, and the file is left untouched
+	let cli = Cli::try_parse_from([
+		"staff-directory",
+		data_file.to_str().unwrap(),
+		"staff",
+		"add",
+		"--first-name",
+		"Al",
+		"--last-name",
+		"Bloggs",
+		"--email",
+		"al@elsewhere.com",
+		"--dob",
+		"1990-01-01",
+		"--doj",
+		"2020-01-01",
+		"--gender",
+		"m",
+	])
+	.unwrap();
+	let err = run(Some(cli)).unwrap_err();
+	assert_eq!(commands::exit_code(&err), 65);
+
+	let ctx = persistence::load(&data_file).unwrap();
+	assert_eq!(ctx.staff().len(), 1);
+
+	fs::remove_file(&data_file).unwrap();
+}
+
+#[test]
+fn outputs_render_in_every_format() {
+	let mut ctx = Context::new();
+	let dep_id =
+		*ctx.insert_department(DepartmentBuilder::new("Sales, EMEA".to_string(), None)).unwrap().id();
+	let mut builder = staff_builder("A", "Staff", Some(dep_id));
+	builder.monthly_salary = Some(1000);
+	ctx.insert_staff(builder).unwrap();
+
+	let output = MenuItemOutput::DepartmentInfo(ctx.department_info(&dep_id, InfoScope::Direct).unwrap());
+
+	let json =
+		serde_json::from_str::<serde_json::Value>(&output::render(&output, OutputFormat::Json).unwrap())
+			.unwrap();
+	assert_eq!(json["department"]["name"], "Sales, EMEA");
+	assert_eq!(json["scope"], "direct");
+	assert_eq!(json["headcount"], 1);
+	assert_eq!(json["monthly_expense"], 1000);
+
+	assert_eq!(
+		output::render(&output, OutputFormat::Csv).unwrap(),
+		"id,name,parent,head,active,scope,as_of,headcount,monthly_expense,unknown_salary_count\n\
+		 0,\"Sales, EMEA\",,,true,direct,,1,1000,0"
+	);
+
+	let table = output::render(&output, OutputFormat::Table).unwrap();
+	let lines = table.lines().collect::<Vec<_>>();
+	assert_eq!(lines.len(), 3);
+	assert!(lines[0].starts_with("id | name        | parent"));
+
+	assert!(output::render(&output, OutputFormat::Text).unwrap().contains("Head count: 1"));
+}
+
+#[test]
+fn import_csv_reports_per_row_and_supports_dry_run() {
+	let mut ctx = Context::new();
+
+	let departments = "name,parent\nEngineering,\nWeb,Engineering\nMobile,Nowhere\n";
+	let report = import::import_csv(
+		&mut ctx,
+		ImportKind::Departments,
+		departments.as_bytes(),
+		&ImportOptions::default(),
+	)
+	.unwrap();
+	assert_eq!(report.created(), 2);
+	assert_eq!(report.rows[2].line, 4);
+	assert!(report.rows[2].result.as_ref().unwrap_err().contains("Nowhere"));
+	assert_eq!(*ctx.department(&DepartmentId(1)).unwrap().parent(), Some(DepartmentId(0)));
+
+	// a malformed record, or a department without a name, is reported on its line, and the rows after it
+	// are still imported
+	let departments = "name,parent\nOps,\nSupport,,extra\nHR,\n,Ops\n";
+	let report = import::import_csv(
+		&mut ctx,
+		ImportKind::Departments,
+		departments.as_bytes(),
+		&ImportOptions::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		report.rows.iter().map(|r| (r.line, r.result.is_ok())).collect::<Vec<_>>(),
+		vec![(2, true), (3, false), (4, true), (5, false)]
+	);
+	assert!(report.rows[3].result.as_ref().unwrap_err().contains("name must not be empty"));
+	assert_eq!(ctx.departments().len(), 4);
+
+	let staff = "Given Name,Family Name,email,dob,doj,gender,Team\n\
+		Jo,Bloggs,jo@example.com,01/02/1990,01/03/2020,f,web\n\
+		Al,Bloggs,al@example.com,01/02/2010,01/03/2000,m,0\n";
+	let options = ImportOptions {
+		mapping: ColumnMapping::try_from("first_name=Given Name, last_name=Family Name, department=Team")
+			.unwrap(),
+		dry_run: true,
+		date_format: "%d/%m/%Y".to_string(),
+	};
+
+	let report = import::import_csv(&mut ctx, ImportKind::Staff, staff.as_bytes(), &options).unwrap();
+	assert_eq!(report.created(), 1);
+	assert_eq!(report.failed(), 1);
+	assert!(ctx.staff().is_empty());
+
+	let options = ImportOptions { dry_run: false, ..options };
+	import::import_csv(&mut ctx, ImportKind::Staff, staff.as_bytes(), &options).unwrap();
+	assert_eq!(ctx.staff().len(), 1);
+	assert_eq!(*ctx.staff()[0].department(), Some(DepartmentId(1)));
+	assert_eq!(*ctx.staff()[0].dob(), date("1990-02-01"));
+
+	// managers are referred to by staff id, and must exist
+	let staff = "first_name,last_name,email,dob,doj,gender,manager\n\
+		Al,Smith,al@example.com,1990-01-01,2020-01-01,m,0\n\
+		Mo,Smith,mo@example.com,1990-01-01,2020-01-01,m,7\n\
+		Ed,Smith,ed@example.com,1990-01-01,2020-01-01,m,Jo\n";
+	let report =
+		import::import_csv(&mut ctx, ImportKind::Staff, staff.as_bytes(), &ImportOptions::default()).unwrap();
+	assert_eq!(report.created(), 1);
+	assert_eq!(*ctx.staff_member(&StaffId(1)).unwrap().manager(), Some(StaffId(0)));
+	assert!(report.rows[1].result.as_ref().unwrap_err().contains("unknown staff 7"));
+	assert!(report.rows[2].result.as_ref().unwrap_err().contains("\"Jo\""));
+
+	let err =
+		import::import_csv(&mut ctx, ImportKind::Staff, "name\nx\n".as_bytes(), &ImportOptions::default());
+	assert!(err.is_err());
+}
+
+#[test]
+fn export_csv_filters_by_subtree_and_picks_columns() {
+	let mut ctx = Context::new();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let web = *ctx.insert_department(DepartmentBuilder::new("Web, UI".to_string(), Some(eng))).unwrap().id();
+	let sales = *ctx.insert_department(DepartmentBuilder::new("Sales".to_string(), None)).unwrap().id();
+
+	ctx.insert_staff(staff_builder("Zoe", "Adams", Some(web))).unwrap();
+	let ben = *ctx.insert_staff(staff_builder("Ben", "Carter", Some(eng))).unwrap().id();
+	ctx.insert_staff(staff_builder("Amy", "Baker", Some(sales))).unwrap();
+	ctx.terminate_staff(&ben, date("2021-06-30")).unwrap();
+
+	let options = ExportOptions {
+		department: Some(eng),
+		columns: vec![ExportColumn::LastName, ExportColumn::Department, ExportColumn::Dot],
+		date_format: "%d/%m/%Y".to_string(),
+		..Default::default()
+	};
+	let content = export::export_staff_csv(&ctx, &options).unwrap();
+	assert_eq!(content, "last_name,department,dot\nAdams,\"Web, UI\",\nCarter,Engineering,30/06/2021\n");
+
+	let options = ExportOptions { active_only: true, ..options };
+	let content = export::export_staff_csv(&ctx, &options).unwrap();
+	assert_eq!(content, "last_name,department,dot\nAdams,\"Web, UI\",\n");
+
+	let content = export::export_staff_csv(&ctx, &ExportOptions::default()).unwrap();
+	assert!(
+		content.starts_with("id,first_name,last_name,email,dob,doj,dot,gender,department,department_id,")
+	);
+	assert_eq!(content.lines().count(), 4);
+}
+
+#[test]
+fn data_files_are_versioned_and_migrated() {
+	// laid out as in the original README, without a format version
+	let legacy = r#"{
+		"company_name": "Acme", "domain": "acme.com",
+		"next_department_id": "001", "next_staff_id": "00001",
+		"departments": [{ "id": "000", "name": "Engineering", "parent": null }],
+		"staff": [{
+			"id": "00000", "first_name": "Jo", "last_name": "Bloggs", "email": "jo@acme.com",
+			"ranking": 3, "age": 30, "dob": "1990-01-01", "doj": "2020-01-01", "gender": "Female",
+			"department": "000", "salary": 5000
+		}]
+	}"#;
+
+	let ctx = persistence::from_json(legacy).unwrap();
+	assert_eq!(ctx.workspace(), workspace::DEFAULT_WORKSPACE);
+	assert!(*ctx.enforce_email_domain());
+	assert_eq!(*ctx.next_staff_id(), StaffId(1));
+	assert_eq!(*ctx.staff()[0].monthly_salary(), Some(5000));
+	assert_eq!(*ctx.staff()[0].department(), Some(DepartmentId(0)));
+	assert!(ctx.departments()[0].active());
+	assert_eq!(ctx.fuzzy_search("blogs").len(), 1);
+
+	// saved files carry the current version, and load back unchanged
+	let saved = persistence::to_json(&ctx).unwrap();
+	let value = serde_json::from_str::<serde_json::Value>(&saved).unwrap();
+	assert_eq!(value["format_version"], persistence::FORMAT_VERSION);
+	assert_eq!(persistence::to_json(&persistence::from_json(&saved).unwrap()).unwrap(), saved);
+
+	// a current staff record without an employment history would lose its department, salary and title
+	let mut value = value;
+	value["workspaces"]["default"]["staff"][0].as_object_mut().unwrap().remove("employment_history");
+	let err = persistence::from_json(&value.to_string()).unwrap_err();
+	assert_eq!(err.code(), "malformed_data_file");
+
+	let newer = saved.replacen(
+		&format!("\"format_version\":{}", persistence::FORMAT_VERSION),
+		&format!("\"format_version\":{}", persistence::FORMAT_VERSION + 1),
+		1,
+	);
+	let err = persistence::from_json(&newer).unwrap_err();
+	assert_eq!(
+		err,
+		Error::Persistence(PersistenceError::DataFile(DataFileError::UnsupportedVersion {
+			found: persistence::FORMAT_VERSION + 1,
+			supported: persistence::FORMAT_VERSION
+		}))
+	);
+	assert_eq!(commands::exit_code(&err), 66);
+}
+
+#[test]
+fn saving_keeps_rotating_backups_that_can_be_restored() {
+	let dir = std::env::temp_dir().join(format!("staff-directory-backups-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let data_file = dir.join("data.json");
+
+	let mut ctx = Context::new();
+	for n in 0..persistence::BACKUPS_KEPT + 2 {
+		ctx.set_company_name(format!("Company {n}"));
+		persistence::save(&ctx, &data_file).unwrap();
+	}
+
+	// the first save had nothing to back up
+	let backups = persistence::list_backups(&data_file).unwrap();
+	assert_eq!(backups.len(), persistence::BACKUPS_KEPT);
+	assert!(backups.windows(2).all(|w| w[0].saved_at > w[1].saved_at));
+	// no temporary files are left behind
+	assert_eq!(fs::read_dir(&dir).unwrap().count(), persistence::BACKUPS_KEPT + 1);
+
+	let latest = persistence::load(&backups[0].path).unwrap();
+	assert_eq!(latest.company_name(), &format!("Company {}", persistence::BACKUPS_KEPT));
+
+	// a corrupted data file can still be restored from the command line
+	fs::write(&data_file, "{ not json").unwrap();
+	let command =
+		Cli::try_parse_from(["staff-directory", data_file.to_str().unwrap(), "backup", "restore", "2"])
+			.unwrap();
+	run(Some(command)).unwrap();
+	let restored = persistence::load(&data_file).unwrap();
+	assert_eq!(restored.company_name(), &format!("Company {}", persistence::BACKUPS_KEPT - 1));
+
+	// the restored directory is saved back to the data file it was restored to. The latest backup is
+	// now the corrupted file.
+	let input = RestoreBackupInput { data_file: data_file.clone(), backup: BackupRef::Number(2) };
+	RestoreBackup().execute(&mut ctx, input).unwrap();
+	assert_eq!(*ctx.data_file(), Some(data_file.clone()));
+	ctx.set_company_name("Restored".to_string());
+	SaveContext().execute(&mut ctx, ()).unwrap();
+	assert_eq!(persistence::load(&data_file).unwrap().company_name(), "Restored");
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sqlite_storage_saves_changed_records_only() {
+	let dir = std::env::temp_dir().join(format!("staff-directory-sqlite-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let db = dir.join("directory.db");
+
+	let mut ctx = Context::new();
+	ctx.set_company_name("Acme".to_string());
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", Some(eng))).unwrap().id();
+	let al = *ctx.insert_staff(staff_builder("Al", "Smith", Some(eng))).unwrap().id();
+	assert_eq!(ctx.unsaved_changes().staff.len(), 2);
+
+	let mut storage = storage::open(StorageKind::Sqlite, &db).unwrap();
+	storage.save(&ctx).unwrap();
+	ctx.mark_saved();
+	assert!(ctx.unsaved_changes().is_empty());
+
+	ctx.update_staff(&jo, StaffChanges { monthly_salary: Some(Some(4000)), ..Default::default() }).unwrap();
+	ctx.delete_staff(&al).unwrap();
+	assert_eq!(ctx.unsaved_changes().staff.iter().copied().collect::<Vec<_>>(), vec![jo, al]);
+	storage.save_changes(&ctx).unwrap();
+
+	// the records can be queried with SQL
+	let conn = rusqlite::Connection::open(&db).unwrap();
+	let salaries = conn
+		.prepare("SELECT last_name, monthly_salary FROM staff WHERE department = ?1")
+		.unwrap()
+		.query_map([eng.0], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<u32>>(1)?)))
+		.unwrap()
+		.collect::<Result<Vec<_>, _>>()
+		.unwrap();
+	assert_eq!(salaries, vec![("Bloggs".to_string(), Some(4000))]);
+
+	let loaded = storage::open(StorageKind::Sqlite, &db).unwrap().load().unwrap();
+	assert_eq!(loaded.company_name(), "Acme");
+	assert_eq!(loaded.staff().len(), 1);
+	assert_eq!(*loaded.next_staff_id(), StaffId(2));
+	assert_eq!(loaded.fuzzy_search("blogs").len(), 1);
+
+	// the storage is picked on the command line
+	let cli = Cli::try_parse_from([
+		"staff-directory",
+		db.to_str().unwrap(),
+		"--storage",
+		"sqlite",
+		"dept",
+		"add",
+		"--name",
+		"Sales",
+	])
+	.unwrap();
+	run(Some(cli)).unwrap();
+	let loaded = storage::open(StorageKind::Sqlite, &db).unwrap().load().unwrap();
+	assert_eq!(loaded.departments().len(), 2);
+	assert_eq!(loaded.staff().len(), 1);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sqlite_storage_rewrites_a_migrated_database_on_the_next_save() {
+	let dir = std::env::temp_dir().join(format!("staff-directory-sqlite-migrated-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let db = dir.join("directory.db");
+
+	let mut ctx = Context::new();
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", None)).unwrap().id();
+	ctx.insert_staff(staff_builder("Al", "Smith", None)).unwrap();
+	storage::open(StorageKind::Sqlite, &db).unwrap().save(&ctx).unwrap();
+
+	// as written before staff had an employment history
+	let conn = rusqlite::Connection::open(&db).unwrap();
+	conn.execute_batch(
+		"UPDATE meta SET value = '3' WHERE key = 'format_version';
+		UPDATE staff SET data = json_remove(data, '$.employment_history', '$.title');",
+	)
+	.unwrap();
+	drop(conn);
+
+	// loaded and saved by different storage instances, as the commands and "Save" do
+	let mut ctx = storage::open(StorageKind::Sqlite, &db).unwrap().load().unwrap();
+	ctx.update_staff(&jo, StaffChanges { monthly_salary: Some(Some(100)), ..Default::default() }).unwrap();
+	storage::open(StorageKind::Sqlite, &db).unwrap().save_changes(&ctx).unwrap();
+	ctx.mark_saved();
+	assert!(!ctx.unsaved_changes().migrated);
+
+	let loaded = storage::open(StorageKind::Sqlite, &db).unwrap().load().unwrap();
+	assert_eq!(loaded.staff().len(), 2);
+	assert_eq!(*loaded.staff_member(&jo).unwrap().monthly_salary(), Some(100));
+	assert!(!loaded.unsaved_changes().migrated);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_writes_to_the_current_data_file_and_clears_the_dirty_flag() {
+	let dir = std::env::temp_dir().join(format!("staff-directory-save-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let data_file = dir.join("data.json");
+
+	let mut ctx = Context::new();
+	assert!(!ctx.is_dirty());
+	let input = NameCompanyInput { name: "Acme".to_string(), domain: None, enforce_email_domain: None };
+	NameCompany::new().execute(&mut ctx, input).unwrap();
+	assert!(ctx.is_dirty());
+
+	// there is no file to save to yet
+	assert!(SaveContext().execute(&mut ctx, ()).is_err());
+	SaveContextAs().execute(&mut ctx, data_file.clone()).unwrap();
+	assert_eq!(*ctx.data_file(), Some(data_file.clone()));
+	assert!(!ctx.is_dirty());
+
+	ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap();
+	assert!(ctx.is_dirty());
+	SaveContext().execute(&mut ctx, ()).unwrap();
+	assert!(!ctx.is_dirty());
+
+	let mut loaded = Context::new();
+	LoadContext().execute(&mut loaded, data_file.clone()).unwrap();
+	assert_eq!(loaded.departments().len(), 1);
+	assert_eq!(*loaded.data_file(), Some(data_file));
+	assert!(!loaded.is_dirty());
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mutations_can_be_undone_and_redone() {
+	let mut ctx = Context::new();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let web = *ctx.insert_department(DepartmentBuilder::new("Web".to_string(), Some(eng))).unwrap().id();
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", Some(web))).unwrap().id();
+
+	// a cascading removal is undone in one step, staff assignments included
+	ctx.remove_department(&eng, RemovalPolicy::Cascade).unwrap();
+	assert!(ctx.departments().is_empty());
+	assert_eq!(ctx.undo().unwrap(), "removed Engineering department (id: 0), and 2 more change(s)");
+	assert_eq!(ctx.departments().iter().map(|d| *d.id()).collect::<Vec<_>>(), vec![eng, web]);
+	assert_eq!(*ctx.staff_member(&jo).unwrap().department(), Some(web));
+
+	assert!(ctx.redo().is_some());
+	assert!(ctx.departments().is_empty());
+	assert!(ctx.redo().is_none());
+	ctx.undo().unwrap();
+
+	// undoing a creation removes the record from the search index too
+	ctx.undo().unwrap();
+	assert!(ctx.staff().is_empty());
+	assert!(ctx.fuzzy_search("bloggs").is_empty());
+	// a new mutation discards what could be redone
+	ctx.set_company_name("Acme".to_string());
+	assert!(ctx.redo().is_none());
+	ctx.undo().unwrap();
+	assert_eq!(ctx.company_name(), "");
+
+	// a CSV import is a single step
+	let csv = "name,parent\nSales,\nMarketing,Sales\n";
+	import::import_csv(&mut ctx, ImportKind::Departments, csv.as_bytes(), &ImportOptions::default()).unwrap();
+	assert_eq!(ctx.departments().len(), 4);
+	ctx.undo().unwrap();
+	assert_eq!(ctx.departments().len(), 2);
+
+	// the history is bounded
+	ctx.set_history(History::new(2, true));
+	for name in ["A", "B", "C"] {
+		ctx.set_company_name(name.to_string());
+	}
+	assert!(ctx.undo().is_some() && ctx.undo().is_some());
+	assert!(ctx.undo().is_none());
+	assert_eq!(ctx.company_name(), "A");
+
+	// unless the history is cleared on load, loading can be undone as well, along with the data file saved to
+	ctx.set_history(History::new(10, false));
+	ctx.set_data_file(Some(PathBuf::from("a.json")));
+	ctx.replace_directory(Context::new(), PathBuf::from("b.json"));
+	assert!(ctx.departments().is_empty());
+	assert_eq!(ctx.undo().unwrap(), "directory loaded");
+	assert_eq!(ctx.departments().len(), 2);
+	assert_eq!(ctx.company_name(), "A");
+	assert_eq!(*ctx.data_file(), Some(PathBuf::from("a.json")));
+	ctx.redo().unwrap();
+	assert_eq!(*ctx.data_file(), Some(PathBuf::from("b.json")));
+
+	ctx.set_history(History::new(10, true));
+	ctx.replace_directory(Context::new(), PathBuf::from("c.json"));
+	assert!(ctx.undo().is_none());
+}
+
+#[test]
+fn changes_are_logged_and_can_be_queried() {
+	let log_file = std::env::temp_dir().join(format!("staff-directory-audit-{}.jsonl", std::process::id()));
+	let _ = fs::remove_file(&log_file);
+
+	let mut ctx = Context::new();
+	ctx.set_audit_log(Some(AuditLog::new(&log_file, "tester")));
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", None)).unwrap().id();
+	let changes = StaffChanges { last_name: Some("Smith".to_string()), ..Default::default() };
+	ctx.update_staff(&jo, changes).unwrap();
+	ctx.set_company_name("Acme".to_string());
+	ctx.undo().unwrap();
+	ctx.flush_audit_log().unwrap();
+
+	let entries = audit::query(&log_file, &AuditQuery::default()).unwrap();
+	assert_eq!(entries.len(), 4);
+	assert!(entries.iter().all(|e| e.actor == "tester"));
+	assert_eq!(entries[3].origin, AuditOrigin::Undo);
+	assert_eq!(entries[3].before.as_ref().unwrap()["name"], "Acme");
+
+	let staff_query = AuditQuery { entity: Some(AuditEntity::Staff), id: Some(jo.0), ..Default::default() };
+	let staff_entries = audit::query(&log_file, &staff_query).unwrap();
+	assert_eq!(
+		staff_entries.iter().map(|e| e.action).collect::<Vec<_>>(),
+		vec![AuditAction::Create, AuditAction::Update]
+	);
+	assert_eq!(staff_entries[1].before.as_ref().unwrap()["last_name"], "Bloggs");
+	assert_eq!(staff_entries[1].after.as_ref().unwrap()["last_name"], "Smith");
+
+	// the dates are inclusive
+	let today = chrono::Local::now().date_naive();
+	let until_yesterday = AuditQuery { to: Some(today.pred_opt().unwrap()), ..Default::default() };
+	assert!(audit::query(&log_file, &until_yesterday).unwrap().is_empty());
+	let from_today = AuditQuery { from: Some(today), to: Some(today), ..Default::default() };
+	assert_eq!(audit::query(&log_file, &from_today).unwrap().len(), 4);
+
+	// workspaces aren't in the history, but are logged all the same
+	ctx.create_workspace("beta").unwrap();
+	ctx.rename_workspace("beta", "gamma").unwrap();
+	ctx.delete_workspace("gamma").unwrap();
+	let workspace_query = AuditQuery { entity: Some(AuditEntity::Workspace), ..Default::default() };
+	let workspace_entries = audit::query(&log_file, &workspace_query).unwrap();
+	assert_eq!(
+		workspace_entries.iter().map(|e| (e.action, e.workspace.as_str())).collect::<Vec<_>>(),
+		vec![(AuditAction::Create, "beta"), (AuditAction::Update, "beta"), (AuditAction::Delete, "gamma")]
+	);
+	assert_eq!(workspace_entries[1].after.as_ref().unwrap()["name"], "gamma");
+	assert_eq!(workspace_entries[2].before.as_ref().unwrap()["name"], "gamma");
+	assert!(workspace_entries[2].after.is_none());
+
+	fs::remove_file(&log_file).unwrap();
+
+	// commands log their changes once saved, and not if they can't be
+	let dir = std::env::temp_dir().join(format!("staff-directory-audit-commands-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	for (data_file, logged) in [(dir.join("missing").join("data.json"), 0), (dir.join("data.json"), 1)] {
+		let command = Cli::try_parse_from([
+			"staff-directory",
+			data_file.to_str().unwrap(),
+			"--audit-log",
+			log_file.to_str().unwrap(),
+			"dept",
+			"add",
+			"--name",
+			"Sales",
+		])
+		.unwrap();
+		assert_eq!(run(Some(command)).is_ok(), logged == 1);
+		assert_eq!(audit::query(&log_file, &AuditQuery::default()).unwrap().len(), logged);
+	}
+	fs::remove_dir_all(&dir).unwrap();
+	fs::remove_file(&log_file).unwrap();
+}
+
+#[test]
+fn workspaces_hold_several_companies_and_staff_move_between_them() {
+	let mut ctx = Context::new();
+	ctx.set_company_info(CompanyInfo {
+		name: "Acme".to_string(),
+		domain: "example.com".to_string(),
+		enforce_email_domain: true,
+	});
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", None)).unwrap().id();
+	let al = *ctx.insert_staff(staff_builder("Al", "Smith", None)).unwrap().id();
+
+	ctx.create_workspace("beta").unwrap();
+	assert_eq!(
+		ctx.create_workspace(" beta "),
+		Err(ConflictError::DuplicateWorkspace("beta".to_string()).into())
+	);
+	ctx.switch_workspace("beta").unwrap();
+	assert!(ctx.staff().is_empty());
+	ctx.set_company_info(CompanyInfo {
+		name: "Beta".to_string(),
+		domain: "beta.com".to_string(),
+		enforce_email_domain: true,
+	});
+	let ops = *ctx.insert_department(DepartmentBuilder::new("Ops".to_string(), None)).unwrap().id();
+	ctx.insert_staff(StaffBuilder { email: "kim@beta.com".to_string(), ..staff_builder("Kim", "Lee", None) })
+		.unwrap();
+	ctx.switch_workspace("default").unwrap();
+	assert_eq!(ctx.company_name(), "Acme");
+
+	// staff get new ids in the other workspace, and emails under its domain
+	assert_eq!(
+		ctx.move_staff(&[jo, StaffId(9)], "beta", Some(ops)).unwrap_err(),
+		Error::unknown_staff(StaffId(9))
+	);
+	let moved = ctx.move_staff(&[jo], "beta", Some(ops)).unwrap();
+	assert_eq!(moved, vec![MovedStaff { from: jo, to: StaffId(1) }]);
+	assert_eq!(ctx.staff().iter().map(|s| *s.id()).collect::<Vec<_>>(), vec![al]);
+	let beta = ctx.workspaces()["beta"];
+	assert_eq!(beta.staff_member(&StaffId(1)).unwrap().email(), "jo.bloggs@beta.com");
+	assert_eq!(*beta.staff_member(&StaffId(1)).unwrap().department(), Some(ops));
+
+	// nothing moves when a staff doesn't fit in the other workspace
+	assert!(ctx.move_staff(&[al], "beta", Some(DepartmentId(7))).is_err());
+	assert_eq!(ctx.staff().len(), 1);
+	assert_eq!(ctx.workspaces()["beta"].staff().len(), 2);
+
+	assert_eq!(
+		ctx.delete_workspace("default"),
+		Err(ConflictError::CurrentWorkspace("default".to_string()).into())
+	);
+	ctx.rename_workspace("beta", "subsidiary").unwrap();
+	let summaries = ctx.workspace_summaries();
+	assert_eq!(summaries.iter().map(|w| w.name.as_str()).collect::<Vec<_>>(), vec!["default", "subsidiary"]);
+	assert_eq!((summaries[1].staff, summaries[0].current), (2, true));
+
+	// a move is undone in both workspaces at once, even under a new name
+	let staff_counts = |ctx: &Context| ctx.workspaces().values().map(|w| w.staff().len()).collect::<Vec<_>>();
+	assert!(ctx.undo().unwrap().ends_with("and 1 more change(s)"));
+	assert_eq!(staff_counts(&ctx), vec![2, 1]);
+	assert!(ctx.staff_member(&jo).is_some());
+	ctx.redo().unwrap();
+	assert_eq!(staff_counts(&ctx), vec![1, 2]);
+
+	// every workspace is saved, in either storage, and the current one is remembered
+	let dir = std::env::temp_dir().join(format!("staff-directory-workspaces-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	ctx.switch_workspace("subsidiary").unwrap();
+	for (kind, file) in [(StorageKind::Json, "data.json"), (StorageKind::Sqlite, "data.db")] {
+		storage::open(kind, &dir.join(file)).unwrap().save(&ctx).unwrap();
+		let loaded = storage::open(kind, &dir.join(file)).unwrap().load().unwrap();
+		assert_eq!(loaded.workspace(), "subsidiary");
+		assert_eq!(loaded.company_name(), "Beta");
+		assert_eq!(loaded.fuzzy_search("bloggs").len(), 1);
+		assert_eq!(loaded.workspaces()["default"].staff().len(), 1);
+	}
+	fs::remove_dir_all(&dir).unwrap();
+
+	ctx.switch_workspace("default").unwrap();
+	ctx.delete_workspace("subsidiary").unwrap();
+	assert_eq!(ctx.workspaces().len(), 1);
+}
+
+#[test]
+fn org_chart_renders_dot_and_mermaid_with_subtree_and_depth() {
+	let mut ctx = Context::new();
+	ctx.set_company_name("Acme".to_string());
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let web = *ctx.insert_department(DepartmentBuilder::new("Web".to_string(), Some(eng))).unwrap().id();
+	let lab =
+		*ctx.insert_department(DepartmentBuilder::new("R&D \"Lab\"".to_string(), Some(web))).unwrap().id();
+	ctx.insert_department(DepartmentBuilder::new("Sales".to_string(), None)).unwrap();
+	ctx.insert_staff(staff_builder("Jo", "Bloggs", Some(web))).unwrap();
+	ctx.insert_staff(staff_builder("Al", "Smith", Some(lab))).unwrap();
+
+	let options = OrgChartOptions { staff: true, ..Default::default() };
+	let dot = org_chart::org_chart(&ctx, &options).unwrap();
+	assert!(dot.starts_with("digraph org_chart {\n\tlabel=\"Acme\";"));
+	assert!(dot.contains("\td1 [label=\"Web\\n1 staff\\nJo Bloggs\"];\n"));
+	assert!(dot.contains("\td2 [label=\"R&D \\\"Lab\\\"\\n1 staff\\nAl Smith\"];\n"));
+	assert!(dot.contains("\td0 -> d1;\n\td1 -> d2;\n}\n"));
+	assert!(dot.contains("d3 [label=\"Sales\\n0 staff\"]"));
+
+	// the sub-departments left out are counted in their ancestor's headcount
+	let options = OrgChartOptions {
+		syntax: ChartSyntax::Mermaid,
+		root: Some(eng),
+		depth: Some(1),
+		staff: false,
+		heads: false,
+	};
+	assert_eq!(
+		org_chart::org_chart(&ctx, &options).unwrap(),
+		"---\ntitle: \"Acme\"\n---\nflowchart TD\n\td0[\"Engineering<br>0 staff\"]\n\td1[\"Web<br>2 staff incl. sub-departments\"]\n\td0 --> d1\n"
+	);
+	let options = OrgChartOptions { syntax: ChartSyntax::Mermaid, root: Some(lab), ..Default::default() };
+	assert!(org_chart::org_chart(&ctx, &options).unwrap().contains("d2[\"R&D #quot;Lab#quot;<br>1 staff\"]"));
+
+	let options = OrgChartOptions { root: Some(DepartmentId(9)), ..Default::default() };
+	assert_eq!(org_chart::org_chart(&ctx, &options), Err(Error::unknown_department(DepartmentId(9))));
+}
+
+#[test]
+fn reporting_lines_reject_cycles_and_survive_deletions() {
+	let mut ctx = Context::new();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let ceo = *ctx.insert_staff(staff_builder("Ann", "Chief", None)).unwrap().id();
+	let cto = *ctx
+		.insert_staff(StaffBuilder { manager: Some(ceo), ..staff_builder("Bob", "Tech", Some(eng)) })
+		.unwrap()
+		.id();
+	let dev = *ctx
+		.insert_staff(StaffBuilder { manager: Some(cto), ..staff_builder("Cy", "Dev", Some(eng)) })
+		.unwrap()
+		.id();
+	let ops = *ctx
+		.insert_staff(StaffBuilder { manager: Some(cto), ..staff_builder("Di", "Ops", Some(eng)) })
+		.unwrap()
+		.id();
+
+	assert_eq!(
+		ctx.insert_staff(StaffBuilder { manager: Some(StaffId(9)), ..staff_builder("No", "One", None) })
+			.unwrap_err(),
+		Error::unknown_staff(StaffId(9))
+	);
+	let changes = StaffChanges { manager: Some(Some(dev)), ..Default::default() };
+	assert_eq!(
+		ctx.update_staff(&ceo, changes).unwrap_err(),
+		ConflictError::ReportingCycle { staff: ceo, manager: dev }.into()
+	);
+	let changes = StaffChanges { manager: Some(Some(ceo)), ..Default::default() };
+	assert_eq!(
+		ctx.update_staff(&ceo, changes).unwrap_err(),
+		ConflictError::ReportingCycle { staff: ceo, manager: ceo }.into()
+	);
+
+	let ids = |staff: Vec<&Staff>| staff.iter().map(|s| *s.id()).collect::<Vec<_>>();
+	assert_eq!(ids(ctx.chain_of_command(&dev)), vec![cto, ceo]);
+	assert_eq!(ids(ctx.direct_reports(&cto)), vec![dev, ops]);
+	assert_eq!(ctx.span_of_control(&cto), 2);
+	let line = ctx.reporting_line(&ceo).unwrap();
+	assert_eq!((line.span_of_control, line.total_reports), (1, 3));
+	let tree = ctx.reporting_tree(None).unwrap();
+	assert_eq!(
+		tree.flatten().iter().map(|(depth, s)| (*depth, *s.id())).collect::<Vec<_>>(),
+		vec![(0, ceo), (1, cto), (2, dev), (2, ops)]
+	);
+
+	let changes = DepartmentChanges { head: Some(Some(StaffId(9))), ..Default::default() };
+	assert_eq!(ctx.update_department(&eng, changes).unwrap_err(), Error::unknown_staff(StaffId(9)));
+	let changes = DepartmentChanges { head: Some(Some(cto)), ..Default::default() };
+	ctx.update_department(&eng, changes).unwrap();
+	let options = OrgChartOptions { heads: true, ..Default::default() };
+	assert!(org_chart::org_chart(&ctx, &options).unwrap().contains("Engineering\\n3 staff\\nHead: Bob Tech"));
+
+	// the reports of a deleted staff move up to their manager, and the department loses its head
+	ctx.delete_staff(&cto).unwrap();
+	assert_eq!(ids(ctx.direct_reports(&ceo)), vec![dev, ops]);
+	assert_eq!(*ctx.department(&eng).unwrap().head(), None);
+	ctx.undo();
+	assert_eq!(ids(ctx.direct_reports(&cto)), vec![dev, ops]);
+	assert_eq!(*ctx.department(&eng).unwrap().head(), Some(cto));
+}
+
+#[test]
+fn trees_survive_cycles_in_a_hand_edited_data_file() {
+	let mut ctx = Context::new();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	ctx.insert_department(DepartmentBuilder::new("Platform".to_string(), Some(eng))).unwrap();
+	let ann = *ctx.insert_staff(staff_builder("Ann", "Chief", Some(eng))).unwrap().id();
+	let bob = *ctx
+		.insert_staff(StaffBuilder { manager: Some(ann), ..staff_builder("Bob", "Tech", Some(eng)) })
+		.unwrap()
+		.id();
+
+	// Ann and Bob manage each other, and so do Engineering and Platform
+	let mut value = serde_json::from_str::<serde_json::Value>(&persistence::to_json(&ctx).unwrap()).unwrap();
+	let directory = &mut value["workspaces"]["default"];
+	directory["staff"][0]["manager"] = directory["staff"][1]["id"].clone();
+	directory["departments"][0]["parent"] = directory["departments"][1]["id"].clone();
+	let ctx = persistence::from_json(&value.to_string()).unwrap();
+
+	let tree = ctx.reporting_tree(Some(ann)).unwrap();
+	assert_eq!(
+		tree.flatten().iter().map(|(depth, s)| (*depth, *s.id())).collect::<Vec<_>>(),
+		vec![(0, ann), (1, bob)]
+	);
+	assert!(ctx.reporting_tree(None).unwrap().flatten().is_empty());
+	assert!(ctx.department_tree().roots.is_empty());
+	assert!(org_chart::org_chart(&ctx, &OrgChartOptions::default()).is_ok());
+}
+
+#[test]
+fn employment_history_answers_as_of_questions() {
+	let mut ctx = Context::new();
+	let sales = *ctx.insert_department(DepartmentBuilder::new("Sales".to_string(), None)).unwrap().id();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let builder = StaffBuilder {
+		monthly_salary: Some(3000),
+		title: Some("Rep".to_string()),
+		..staff_builder("Jo", "Bloggs", Some(sales))
+	};
+	let jo = *ctx.insert_staff(builder).unwrap().id();
+
+	let changes = StaffChanges {
+		department: Some(Some(eng)),
+		monthly_salary: Some(Some(4000)),
+		title: Some(Some("Engineer".to_string())),
+		effective: Some(date("2022-03-01")),
+		..Default::default()
+	};
+	ctx.update_staff(&jo, changes).unwrap();
+	// recorded late, as of a date before the transfer, so the current salary stays
+	let changes = StaffChanges {
+		monthly_salary: Some(Some(3500)),
+		effective: Some(date("2021-01-01")),
+		..Default::default()
+	};
+	let staff = ctx.update_staff(&jo, changes).unwrap();
+	assert_eq!((*staff.department(), *staff.monthly_salary()), (Some(eng), Some(4000)));
+	assert_eq!(staff.monthly_salary_on(date("2021-06-30")), Some(3500));
+	assert_eq!(staff.title_on(date("2019-12-31")), None);
+
+	let changes = StaffChanges {
+		monthly_salary: Some(Some(1)),
+		effective: Some(date("2019-01-01")),
+		..Default::default()
+	};
+	assert_eq!(
+		ctx.update_staff(&jo, changes).unwrap_err(),
+		ValidationError::ChangeBeforeJoining { doj: date("2020-01-01"), effective: date("2019-01-01") }
+			.into()
+	);
+
+	let info = |ctx: &Context, dep_id, as_of| {
+		let info = ctx.department_info_as_of(&dep_id, InfoScope::Direct, as_of).unwrap();
+		(info.headcount, info.monthly_expense)
+	};
+	assert_eq!(info(&ctx, sales, Some(date("2021-06-30"))), (1, 3500));
+	assert_eq!(info(&ctx, eng, Some(date("2021-06-30"))), (0, 0));
+	assert_eq!(info(&ctx, eng, None), (1, 4000));
+	ctx.terminate_staff(&jo, date("2023-01-01")).unwrap();
+	assert_eq!(info(&ctx, eng, Some(date("2022-12-31"))), (1, 4000));
+	assert_eq!(info(&ctx, eng, Some(date("2023-01-01"))), (0, 0));
+
+	let filter = StaffFilter {
+		department: Some(sales),
+		active: Some(true),
+		as_of: Some(date("2020-06-01")),
+		..Default::default()
+	};
+	assert_eq!(ctx.list_staff(&filter).total, 1);
+
+	let timeline = ctx.staff_timeline(&jo).unwrap();
+	assert_eq!(
+		timeline.entries.iter().map(|e| (e.date.to_string(), e.event, e.value.as_str())).collect::<Vec<_>>(),
+		vec![
+			("2020-01-01".to_string(), "joined", ""),
+			("2020-01-01".to_string(), "department", "Sales (id: 0)"),
+			("2020-01-01".to_string(), "monthly_salary", "3000"),
+			("2020-01-01".to_string(), "title", "Rep"),
+			("2021-01-01".to_string(), "monthly_salary", "3500"),
+			("2022-03-01".to_string(), "department", "Engineering (id: 1)"),
+			("2022-03-01".to_string(), "monthly_salary", "4000"),
+			("2022-03-01".to_string(), "title", "Engineer"),
+			("2023-01-01".to_string(), "terminated", ""),
+		]
+	);
+
+	// the history is kept through a save, and undoing the changes removes them from the history
+	let reloaded = persistence::from_json(&persistence::to_json(&ctx).unwrap()).unwrap();
+	assert_eq!(reloaded.staff_member(&jo).unwrap().employment_history().len(), 7);
+	(0..3).for_each(|_| {
+		ctx.undo();
+	});
+	assert_eq!(ctx.staff_member(&jo).unwrap().employment_history().len(), 3);
+}
+
+#[test]
+fn future_transfers_are_pending_and_follow_removed_departments() {
+	let mut ctx = Context::new();
+	let eng = *ctx.insert_department(DepartmentBuilder::new("Engineering".to_string(), None)).unwrap().id();
+	let ops = *ctx.insert_department(DepartmentBuilder::new("Ops".to_string(), Some(eng))).unwrap().id();
+	let jo = *ctx.insert_staff(staff_builder("Jo", "Bloggs", Some(eng))).unwrap().id();
+
+	let transfer = StaffChanges {
+		department: Some(Some(ops)),
+		effective: Some(date("2099-01-01")),
+		..Default::default()
+	};
+	ctx.update_staff(&jo, transfer).unwrap();
+	let staff = ctx.staff_member(&jo).unwrap();
+	assert_eq!(*staff.department(), Some(eng));
+	assert_eq!(staff.department_on(date("2099-01-01")), Some(ops));
+
+	// the pending transfer goes where the staff of the removed department go
+	ctx.remove_department(&ops, RemovalPolicy::ReassignToParent).unwrap();
+	let staff = ctx.staff_member(&jo).unwrap();
+	assert_eq!(*staff.department(), Some(eng));
+	assert_eq!(staff.department_on(date("2099-01-01")), Some(eng));
+
+	let transfer =
+		StaffChanges { department: Some(None), effective: Some(date("2098-01-01")), ..Default::default() };
+	ctx.update_staff(&jo, transfer).unwrap();
+	let sales = *ctx.insert_department(DepartmentBuilder::new("Sales".to_string(), None)).unwrap().id();
+	let transfer = StaffChanges {
+		department: Some(Some(sales)),
+		effective: Some(date("2099-06-01")),
+		..Default::default()
+	};
+	ctx.update_staff(&jo, transfer).unwrap();
+	ctx.remove_department(&eng, RemovalPolicy::Cascade).unwrap();
+	ctx.remove_department(&sales, RemovalPolicy::Cascade).unwrap();
+	let staff = ctx.staff_member(&jo).unwrap();
+	assert_eq!(*staff.department(), None);
+	assert!((2098..2100).all(|year| staff.department_on(date(&format!("{year}-12-31"))).is_none()));
+}
+
+fn http_request(
+	addr: std::net::SocketAddr,
+	method: &str,
+	path: &str,
+	body: &str,
+) -> (u16, serde_json::Value) {
+	use std::io::{Read, Write};
+
+	let mut stream = std::net::TcpStream::connect(addr).unwrap();
+	write!(
+		stream,
+		"{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+		body.len()
+	)
+	.unwrap();
+	let mut response = String::new();
+	stream.read_to_string(&mut response).unwrap();
+
+	let (head, body) = response.split_once("\r\n\r\n").unwrap();
+	let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+	(status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn http_api_serves_and_persists_the_directory() {
+	let dir = std::env::temp_dir().join(format!("staff-directory-server-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let data_file = dir.join("data.json");
+
+	let mut ctx = Context::new();
+	ctx.set_data_file(Some(data_file.clone()));
+	let mut server = server::ApiServer::bind("127.0.0.1:0", ctx).unwrap();
+	let addr = server.local_addr().unwrap();
+	std::thread::spawn(move || server.run().map_err(|err| err.to_string()));
+
+	let (status, eng) = http_request(addr, "POST", "/departments", r#"{"name": "Engineering"}"#);
+	assert_eq!(status, 201);
+	assert_eq!(eng["name"], "Engineering");
+	let (status, _) =
+		http_request(addr, "POST", "/departments", r#"{"name": "Web", "parent": 0, "active": true}"#);
+	assert_eq!(status, 201);
+
+	let body = r#"{"first_name": "Jo", "last_name": "Bloggs", "email": "jo@example.com", "dob": "1990-01-01",
+		"doj": "2020-01-01", "gender": "Female", "department": 1, "monthly_salary": 3000}"#;
+	let (status, jo) = http_request(addr, "POST", "/staff", body);
+	assert_eq!(status, 201);
+	assert_eq!(jo["id"], 0);
+
+	// `null` clears a field, while a missing field is left alone
+	let body = r#"{"monthly_salary": 3500, "department": null, "effective": "2021-01-01"}"#;
+	let (status, jo) = http_request(addr, "PATCH", "/staff/0", body);
+	assert_eq!(status, 200);
+	assert_eq!(
+		(jo["monthly_salary"].clone(), jo["department"].clone()),
+		(3500.into(), serde_json::Value::Null)
+	);
+	assert_eq!(jo["first_name"], "Jo");
+
+	let (status, page) = http_request(addr, "GET", "/staff?active=true&per_page=10", "");
+	assert_eq!((status, page["total"].clone()), (200, 1.into()));
+	let (status, info) =
+		http_request(addr, "GET", "/departments/0/info?scope=rolled_up&as_of=2020-06-01", "");
+	assert_eq!((status, info["headcount"].clone()), (200, 1.into()));
+	let (_, tree) = http_request(addr, "GET", "/departments/tree", "");
+	assert_eq!(tree["roots"][0]["children"][0]["department"]["name"], "Web");
+	let (_, deps) = http_request(addr, "GET", "/departments", "");
+	assert_eq!(deps.as_array().unwrap().len(), 2);
+
+	// failures come back as JSON errors, and change nothing
+	let (status, error) = http_request(addr, "GET", "/staff/7", "");
+	assert_eq!(
+		(status, error["error"]["code"].clone(), error["error"]["message"].clone()),
+		(404, "not_found".into(), "Not Found: unknown staff 7".into())
+	);
+	let (status, error) = http_request(addr, "DELETE", "/departments/0", "");
+	assert_eq!((status, error["error"]["code"].clone()), (409, "department_has_children".into()));
+	let (status, error) = http_request(addr, "PATCH", "/staff/0", r#"{"email": "jo"}"#);
+	assert_eq!((status, error["error"]["field"].clone()), (422, "email".into()));
+	assert_eq!(http_request(addr, "PATCH", "/departments/0", "{").0, 400);
+	assert_eq!(http_request(addr, "GET", "/payroll", "").0, 404);
+
+	let (status, _) = http_request(addr, "DELETE", "/departments/1?policy=reassign_to_parent", "");
+	assert_eq!(status, 200);
+
+	// every change has been saved through to the data file
+	let saved = storage::open(StorageKind::Json, &data_file).unwrap().load().unwrap();
+	assert_eq!(saved.departments().len(), 1);
+	assert_eq!(*saved.staff_member(&StaffId(0)).unwrap().monthly_salary(), Some(3500));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changes_that_cannot_be_saved_are_rolled_back() {
+	// the directory of the data file doesn't exist
+	let data_file = std::env::temp_dir()
+		.join(format!("staff-directory-missing-{}", std::process::id()))
+		.join("data.json");
+
+	let log_file =
+		std::env::temp_dir().join(format!("staff-directory-rolled-back-{}.jsonl", std::process::id()));
+	let _ = fs::remove_file(&log_file);
+
+	let mut ctx = Context::new();
+	ctx.set_data_file(Some(data_file));
+	ctx.set_audit_log(Some(AuditLog::new(&log_file, "tester")));
+	let mut server = server::ApiServer::bind("127.0.0.1:0", ctx.clone()).unwrap();
+	let response = server.handle(&tiny_http::Method::Post, "/departments", r#"{"name": "Engineering"}"#);
+	assert_eq!(response.status, 500);
+	let response = server.handle(&tiny_http::Method::Get, "/departments", "");
+	assert_eq!(response.body, "[]");
+	// nor is the change logged
+	assert!(audit::query(&log_file, &AuditQuery::default()).unwrap().is_empty());
+
+	let response =
+		protocol::handle(&mut ctx, r#"{"id": 1, "op": "create_department", "params": {"name": "Web"}}"#);
+	assert!(matches!(response.outcome, protocol::Outcome::Error(Error::Io { .. })));
+	assert!(ctx.departments().is_empty() && !ctx.is_dirty());
+	assert!(ctx.undo().is_none());
+	assert!(audit::query(&log_file, &AuditQuery::default()).unwrap().is_empty());
+
+	// reads don't try to save, even with changes left unsaved
+	ctx.set_company_name("Acme".to_string());
+	let response = protocol::handle(&mut ctx, r#"{"id": 2, "op": "list_staff", "params": {}}"#);
+	assert!(matches!(response.outcome, protocol::Outcome::Result(_)));
+}
+
+#[test]
+fn errors_have_stable_codes_and_name_the_field_at_fault() {
+	let mut ctx = Context::new();
+	let mut builder = staff_builder("A", "Staff", None);
+	builder.email = "a.staff".to_string();
+	let err = ctx.insert_staff(builder).unwrap_err();
+	assert_eq!((err.code(), err.field()), ("invalid_email", Some("email")));
+	assert_eq!(commands::exit_code(&err), 65);
+
+	let err = ctx.remove_department(&DepartmentId(4), RemovalPolicy::Refuse).unwrap_err();
+	assert_eq!((err.code(), err.field()), ("not_found", None));
+	assert_eq!(err.to_string(), "Not Found: unknown department 4");
+
+	let err = persistence::from_json("{").unwrap_err();
+	assert_eq!(err.code(), "malformed_data_file");
+	assert_eq!(commands::exit_code(&err), 66);
+	assert_eq!(
+		serde_json::to_value(Error::unknown_workspace("beta")).unwrap(),
+		serde_json::json!({"code": "not_found", "message": "Not Found: unknown workspace \"beta\""})
+	);
+}
+
+#[test]
+fn line_protocol_answers_each_request_with_a_result_or_an_error() {
+	let input = [
+		r#"{"id": 1, "op": "create_department", "params": {"name": "Engineering"}}"#,
+		"",
+		concat!(
+			r#"{"id": 2, "op": "create_staff", "params": {"first_name": "Jo", "last_name": "Bloggs", "#,
+			r#""email": "jo@example.com", "dob": "1990-01-01", "doj": "2020-01-01", "gender": "Female", "#,
+			r#""department": 0}}"#,
+		),
+		r#"{"id": 3, "op": "update_staff", "params": {"id": 0, "changes": {"title": "Engineer", "department": null}}}"#,
+		r#"{"id": 4, "op": "list_staff", "params": {"active": true}}"#,
+		r#"{"id": "five", "op": "remove_department", "params": {"id": 7}}"#,
+		r#"{"id": 6, "op": "fire_everyone"}"#,
+		"not json",
+		r#"{"op": "undo"}"#,
+	]
+	.join("\n");
+	let mut ctx = Context::new();
+	let mut output = vec![];
+	protocol::run(&mut ctx, input.as_bytes(), &mut output).unwrap();
+
+	let responses = String::from_utf8(output)
+		.unwrap()
+		.lines()
+		.map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(responses.len(), 8);
+	assert_eq!(responses[0]["result"]["name"], "Engineering");
+	assert_eq!(responses[1]["result"]["department"], 0);
+	assert_eq!(responses[2]["result"]["title"], "Engineer");
+	assert_eq!(responses[2]["result"]["department"], serde_json::Value::Null);
+	assert_eq!((responses[3]["id"].clone(), responses[3]["result"]["total"].clone()), (4.into(), 1.into()));
+
+	let errors =
+		responses[4..7].iter().map(|r| (r["id"].clone(), r["error"]["code"].clone())).collect::<Vec<_>>();
+	assert_eq!(
+		errors,
+		vec![
+			("five".into(), "not_found".into()),
+			(6.into(), "parse_error".into()),
+			(serde_json::Value::Null, "parse_error".into()),
+		]
+	);
+
+	// the undo took back the title and department change
+	assert!(responses[7]["result"].as_str().unwrap().starts_with("Undone"));
+	assert_eq!(*ctx.staff_member(&StaffId(0)).unwrap().department(), Some(DepartmentId(0)));
+}
@@ -1,3 +1,11 @@
 pub trait OneLiner {
 	fn one_liner(&self) -> String;
 }
+
+/**
+ * Types that can be laid out as a row of a table, e.g. in CSV
+ **/
+pub trait Tabular {
+	fn headers() -> Vec<&'static str>;
+	fn row(&self) -> Vec<String>;
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+
+use crate::{
+	context::Context,
+	errors::{Error, ValidationError},
+	staff::StaffId,
+	traits::Tabular,
+};
+
+/// Name of the workspace of a new data file, and of files saved before workspaces existed
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/**
+ * One line per workspace of a data file, e.g. to list them
+ **/
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WorkspaceSummary {
+	pub name: String,
+	pub company_name: String,
+	pub departments: usize,
+	pub staff: usize,
+	pub current: bool,
+}
+
+impl WorkspaceSummary {
+	pub fn new(name: &str, ctx: &Context, current: bool) -> Self {
+		Self {
+			name: name.to_string(),
+			company_name: ctx.company_name().clone(),
+			departments: ctx.departments().len(),
+			staff: ctx.staff().len(),
+			current,
+		}
+	}
+}
+
+impl Tabular for WorkspaceSummary {
+	fn headers() -> Vec<&'static str> {
+		vec!["name", "company_name", "departments", "staff", "current"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![
+			self.name.clone(),
+			self.company_name.clone(),
+			self.departments.to_string(),
+			self.staff.to_string(),
+			self.current.to_string(),
+		]
+	}
+}
+
+/// A staff moved to another workspace, where they were given a new id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct MovedStaff {
+	pub from: StaffId,
+	pub to: StaffId,
+}
+
+impl Tabular for MovedStaff {
+	fn headers() -> Vec<&'static str> {
+		vec!["from", "to"]
+	}
+
+	fn row(&self) -> Vec<String> {
+		vec![self.from.0.to_string(), self.to.0.to_string()]
+	}
+}
+
+/// Workspace names are trimmed, and must not be empty
+pub fn validate_name(name: &str) -> Result<String, Error> {
+	let name = name.trim();
+	if name.is_empty() {
+		Err(ValidationError::EmptyField("workspace name"))?
+	}
+	Ok(name.to_string())
+}